@@ -32,7 +32,7 @@ fn pairs(keys: &[i32]) -> Vec<(i32, String)> {
         .collect()
 }
 
-fn build_tree(entries: Vec<(i32, String)>) -> Tree {
+fn build_tree(entries: Vec<(i32, String)>) -> Tree<i32, String> {
     let mut tree = Tree::new();
     for (key, value) in entries {
         tree.insert(key, value);
@@ -71,6 +71,18 @@ fn insertion_benchmarks(c: &mut Criterion) {
             },
         );
 
+        group.bench_with_input(
+            BenchmarkId::new("from_sorted", size),
+            &ascending,
+            |b, input| {
+                b.iter_batched(
+                    || input.clone(),
+                    |entries| black_box(Tree::from_sorted_iter(entries).expect("ascending keys")),
+                    BatchSize::LargeInput,
+                );
+            },
+        );
+
         let descending = pairs(&(0..size as i32).rev().collect::<Vec<_>>());
         group.bench_with_input(
             BenchmarkId::new("descending", size),
@@ -103,7 +115,7 @@ fn lookup_benchmarks(c: &mut Criterion) {
         group.bench_with_input(BenchmarkId::new("successful", size), &keys, |b, input| {
             b.iter(|| {
                 for &key in input {
-                    black_box(tree.find(black_box(key)));
+                    black_box(tree.find(black_box(&key)));
                 }
             });
         });
@@ -111,7 +123,7 @@ fn lookup_benchmarks(c: &mut Criterion) {
         group.bench_with_input(BenchmarkId::new("missing", size), &missing, |b, input| {
             b.iter(|| {
                 for &key in input {
-                    black_box(tree.find(black_box(key)));
+                    black_box(tree.find(black_box(&key)));
                 }
             });
         });
@@ -159,7 +171,7 @@ fn duplicate_update_benchmarks(c: &mut Criterion) {
 fn topology_removal_benchmarks(c: &mut Criterion) {
     const BATCH: usize = 4_096;
 
-    fn trees(entries: &[(i32, &str)]) -> Vec<Tree> {
+    fn trees(entries: &[(i32, &str)]) -> Vec<Tree<i32, String>> {
         (0..BATCH)
             .map(|_| {
                 let mut tree = Tree::new();
@@ -191,7 +203,7 @@ fn topology_removal_benchmarks(c: &mut Criterion) {
                 || trees(entries),
                 |mut trees| {
                     for tree in &mut trees {
-                        black_box(tree.remove(black_box(key)));
+                        black_box(tree.remove(black_box(&key)));
                     }
                 },
                 BatchSize::LargeInput,
@@ -221,7 +233,7 @@ fn missing_removal_benchmarks(c: &mut Criterion) {
                     || build_tree(tree_entries.clone()),
                     |mut tree| {
                         for &key in missing {
-                            black_box(tree.remove(black_box(key)));
+                            black_box(tree.remove(black_box(&key)));
                         }
                     },
                     BatchSize::LargeInput,
@@ -255,7 +267,7 @@ fn mixed_workload_benchmarks(c: &mut Criterion) {
                             if index % 10 == 0 {
                                 tree.insert(key, format!("updated-{index}"));
                             } else {
-                                black_box(tree.find(black_box(key)));
+                                black_box(tree.find(black_box(&key)));
                             }
                         }
                         black_box(tree);
@@ -274,9 +286,9 @@ fn mixed_workload_benchmarks(c: &mut Criterion) {
                     |mut tree| {
                         for (index, &key) in keys.iter().enumerate() {
                             if index % 2 == 0 {
-                                black_box(tree.find(black_box(key)));
+                                black_box(tree.find(black_box(&key)));
                             } else {
-                                black_box(tree.remove(black_box(key)));
+                                black_box(tree.remove(black_box(&key)));
                                 tree.insert(key, format!("reinserted-{index}"));
                             }
                         }
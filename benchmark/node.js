@@ -79,6 +79,9 @@ function measure(name, size, operations, setup, run) {
   };
 }
 
+// Batch methods are skipped when measuring a baseline module without them.
+const hasBatchMethods = typeof AvlTree.prototype.insertMany === 'function';
+
 function topologyTrees(input, count) {
   return Array.from({ length: count }, () => buildTree(input));
 }
@@ -208,6 +211,81 @@ for (const size of SIZES) {
     )
   );
 
+  if (hasBatchMethods) {
+    const keyBuffer = Int32Array.from(randomKeys);
+
+    results.push(
+      measure(
+        'batch/insert_many',
+        size,
+        size,
+        () => randomEntries,
+        (fixture) => new AvlTree().insertMany(fixture)
+      )
+    );
+
+    for (const [name, keys] of [
+      ['batch/find_many/array', randomKeys],
+      ['batch/find_many/int32array', keyBuffer],
+    ]) {
+      results.push(
+        measure(
+          name,
+          size,
+          size,
+          () => buildTree(randomEntries),
+          (tree) => tree.findMany(keys)
+        )
+      );
+    }
+
+    results.push(
+      measure(
+        'batch/has_loop',
+        size,
+        size,
+        () => buildTree(randomEntries),
+        (tree) => {
+          for (const key of randomKeys) {
+            tree.has(key);
+          }
+        }
+      )
+    );
+    results.push(
+      measure(
+        'batch/has_many',
+        size,
+        size,
+        () => buildTree(randomEntries),
+        (tree) => tree.hasMany(keyBuffer)
+      )
+    );
+
+    results.push(
+      measure(
+        'batch/remove_loop',
+        size,
+        size,
+        () => buildTree(randomEntries),
+        (tree) => {
+          for (const key of randomKeys) {
+            tree.remove(key);
+          }
+        }
+      )
+    );
+    results.push(
+      measure(
+        'batch/remove_many',
+        size,
+        size,
+        () => buildTree(randomEntries),
+        (tree) => tree.removeMany(keyBuffer)
+      )
+    );
+  }
+
   results.push(
     measure(
       'dump',
@@ -1,3 +1,15 @@
 import { AvlTree } from './native';
 
+declare module './native' {
+  interface AvlTree {
+    [Symbol.iterator](): AvlTreeEntries;
+  }
+  interface AvlTreeSnapshot {
+    [Symbol.iterator](): AvlTreeEntries;
+  }
+  interface AvlTreeEntries extends IterableIterator<[number, string]> {}
+  interface AvlTreeKeys extends IterableIterator<number> {}
+  interface AvlTreeValues extends IterableIterator<string> {}
+}
+
 export = AvlTree;
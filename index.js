@@ -1,7 +1,48 @@
 'use strict';
 
+// Node-API classes cannot declare symbol-keyed methods, so the iteration
+// protocol is layered over the native `advance()` and `close()` cursors.
+const iteratorProtocol = {
+  next() {
+    const value = this.advance();
+    return value === null
+      ? { done: true, value: undefined }
+      : { done: false, value };
+  },
+  return(value) {
+    this.close();
+    return { done: true, value };
+  },
+  [Symbol.iterator]() {
+    return this;
+  },
+};
+
+function define(target, key, value) {
+  Object.defineProperty(target, key, {
+    configurable: true,
+    writable: true,
+    value,
+  });
+}
+
 function loadNativeConstructor() {
-  return require('./native.js').AvlTree;
+  const native = require('./native.js');
+
+  for (const Cursor of [
+    native.AvlTreeEntries,
+    native.AvlTreeKeys,
+    native.AvlTreeValues,
+  ]) {
+    for (const key of Reflect.ownKeys(iteratorProtocol)) {
+      define(Cursor.prototype, key, iteratorProtocol[key]);
+    }
+  }
+  for (const Source of [native.AvlTree, native.AvlTreeSnapshot]) {
+    define(Source.prototype, Symbol.iterator, Source.prototype.entries);
+  }
+
+  return native.AvlTree;
 }
 
 module.exports = loadNativeConstructor();
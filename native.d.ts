@@ -4,12 +4,51 @@
 export declare class AvlTree {
   /** Creates an empty tree in constant time. */
   constructor()
+  /**
+   * Creates a tree from `[key, value]` pairs in any order, keeping the last
+   * value for a repeated key.
+   *
+   * Runs in `O(n)` time when the pairs are already in ascending key order
+   * and `O(n log n)` otherwise, without rebalancing rotations.
+   */
+  static fromEntries(entries: Array<[number, string]>): AvlTree
+  /**
+   * Reads a tree from bytes produced by `serialize`, throwing an error that
+   * describes truncated, corrupt, or unsupported input.
+   *
+   * Runs in `O(n)` time.
+   */
+  static deserialize(bytes: Uint8Array): AvlTree
+  /**
+   * Reads a tree from a file written by `saveToFile`, throwing an error that
+   * describes a missing, truncated, corrupt, or unsupported file.
+   *
+   * Runs in `O(n)` time.
+   */
+  static loadFromFile(path: string): AvlTree
+  /**
+   * Opens the durable tree stored in `directory`, creating it when missing.
+   * The tree is rebuilt from the last snapshot plus a replay of the
+   * write-ahead log, ignoring a final record torn by a crash. Every later
+   * mutation is appended to the log and synced to disk before it is
+   * applied.
+   *
+   * Runs in `O(n + r log n)` time for `r` logged records.
+   */
+  static openDurable(directory: string): AvlTree
   /**
    * Inserts a key/value pair, replacing the value when the key already exists.
    *
    * Runs in `O(log n)` time.
    */
   insert(key: number, value: string): void
+  /**
+   * Inserts a key/value pair and returns the value it replaced, or `null` in
+   * JavaScript when the key was absent.
+   *
+   * Runs in `O(log n)` time.
+   */
+  set(key: number, value: string): string | null
   /**
    * Returns the value for `key`, or `null` in JavaScript when absent.
    *
@@ -24,7 +63,485 @@ export declare class AvlTree {
   remove(key: number): string | null
   /** Reports whether `key` exists in `O(log n)` time. */
   has(key: number): boolean
+  /** The number of entries, read in constant time. */
+  get size(): number
+  /** Reports whether the tree has no entries in constant time. */
+  isEmpty(): boolean
+  /**
+   * Returns the number of levels in constant time: `0` for an empty tree
+   * and `1` for a lone root.
+   */
+  height(): number
+  /**
+   * Returns the size, height, AVL height bound, approximate heap bytes,
+   * and average search depth of the tree. Nodes and values shared with
+   * snapshots and live iterators are counted in full.
+   *
+   * Runs in `O(n)` time.
+   */
+  stats(): TreeStats
+  /**
+   * Checks the ordering, stored heights, balance factors, subtree sizes, and
+   * entry count of the tree, throwing an error that names the first broken
+   * invariant and the key where it was found.
+   *
+   * Runs in `O(n)` time.
+   */
+  validate(): void
+  /**
+   * Stores `callback(value, key)` under `key` and returns it, where `value` is
+   * the current value or `null` in JavaScript when absent. The callback may
+   * read the tree, but writing to it from the callback throws, and an
+   * exception the callback throws propagates without changing the tree.
+   *
+   * Runs in `O(log n)` time plus the callback.
+   */
+  update(key: number, callback: (value: string | null, key: number) => string): string
+  /**
+   * Returns the value for `key`, first inserting `value` when the key is
+   * absent.
+   *
+   * Runs in `O(log n)` time.
+   */
+  getOrInsert(key: number, value: string): string
+  /**
+   * Removes every entry. Nodes that no snapshot or live iterator shares are
+   * freed in `O(n)` time.
+   */
+  clear(): void
+  /**
+   * Removes and returns the `[key, value]` pair with the smallest key, or
+   * `null` in JavaScript when the tree is empty.
+   *
+   * Runs in `O(log n)` time.
+   */
+  popFirst(): [number, string] | null
+  /**
+   * Removes and returns the `[key, value]` pair with the largest key, or
+   * `null` in JavaScript when the tree is empty.
+   *
+   * Runs in `O(log n)` time.
+   */
+  popLast(): [number, string] | null
+  /**
+   * Keeps only the entries for which `callback(value, key)` returns a truthy
+   * value. The callback runs in ascending key order and may read the tree,
+   * but writing to it from the callback throws. An exception the callback
+   * throws propagates without removing anything.
+   *
+   * Runs in `O(n + k log n)` time for `k` removed entries, plus the
+   * callbacks.
+   */
+  retain(callback: (value: string, key: number) => unknown): void
+  /**
+   * Removes the entries that `range` would return for the same arguments
+   * and returns them as `[key, value]` pairs in ascending key order.
+   *
+   * Runs in `O(k log n)` time for `k` removed pairs.
+   */
+  drainRange(lo?: number | undefined | null, hi?: number | undefined | null, options?: RangeOptions | undefined | null): Array<[number, string]>
+  /**
+   * Inserts every `[key, value]` pair in order, so the last value wins for a
+   * repeated key. The whole batch crosses the Node-API boundary once and is
+   * converted before any pair is inserted, so an invalid pair leaves the
+   * tree unchanged.
+   *
+   * Runs in `O(m log(n + m))` time for `m` pairs.
+   */
+  insertMany(entries: Array<[number, string]>): void
+  /**
+   * Returns the value for each key in `keys`, with `null` for absent keys.
+   *
+   * Runs in `O(m log n)` time for `m` keys.
+   */
+  findMany(keys: Int32Array | Array<number>): Array<string | undefined | null>
+  /**
+   * Reports whether each key in `keys` exists.
+   *
+   * Runs in `O(m log n)` time for `m` keys.
+   */
+  hasMany(keys: Int32Array | Array<number>): Array<boolean>
+  /**
+   * Removes each key in `keys` in order and returns the removed values, with
+   * `null` for keys that were absent or already removed earlier in the batch.
+   *
+   * Runs in `O(m log n)` time for `m` keys.
+   */
+  removeMany(keys: Int32Array | Array<number>): Array<string | undefined | null>
+  /**
+   * Calls `callback(transaction)` and then applies the inserts and removals
+   * it staged on `transaction` all at once, returning what the callback
+   * returned. Reads through `transaction` see the staged changes over the
+   * tree as it was when `transaction` was called. The callback may read the
+   * tree directly, but writing to it other than through `transaction`
+   * throws. When the callback throws, the exception propagates and nothing
+   * staged is applied, leaving the tree exactly as it was. A durable tree logs the staged changes in one
+   * append, so a crash keeps either all of them or none.
+   *
+   * The callback must be synchronous, since the transaction ends when it
+   * returns. A callback that returns a `Promise`, as an `async` function
+   * does, makes `transaction` throw without applying anything.
+   *
+   * Runs in `O(m log(n + m))` time for `m` staged keys, plus the callback.
+   */
+  transaction(callback: (transaction: AvlTreeTransaction) => unknown): unknown
+  /**
+   * Returns the `[key, value]` pair with the smallest key, or `null` in
+   * JavaScript when the tree is empty.
+   *
+   * Runs in `O(log n)` time.
+   */
+  first(): [number, string] | null
+  /**
+   * Returns the `[key, value]` pair with the largest key, or `null` in
+   * JavaScript when the tree is empty.
+   *
+   * Runs in `O(log n)` time.
+   */
+  last(): [number, string] | null
+  /**
+   * Returns the `[key, value]` pair with the largest key less than or equal
+   * to `key`, or `null` in JavaScript when absent.
+   *
+   * Runs in `O(log n)` time.
+   */
+  floor(key: number): [number, string] | null
+  /**
+   * Returns the `[key, value]` pair with the smallest key greater than or
+   * equal to `key`, or `null` in JavaScript when absent.
+   *
+   * Runs in `O(log n)` time.
+   */
+  ceiling(key: number): [number, string] | null
+  /**
+   * Returns the `[key, value]` pair with the largest key strictly less than
+   * `key`, or `null` in JavaScript when absent.
+   *
+   * Runs in `O(log n)` time.
+   */
+  predecessor(key: number): [number, string] | null
+  /**
+   * Returns the `[key, value]` pair with the smallest key strictly greater
+   * than `key`, or `null` in JavaScript when absent.
+   *
+   * Runs in `O(log n)` time.
+   */
+  successor(key: number): [number, string] | null
+  /**
+   * Returns the zero-based position of `key` in ascending key order, or
+   * `null` in JavaScript when absent.
+   *
+   * Runs in `O(log n)` time.
+   */
+  rank(key: number): number | null
+  /**
+   * Returns the `[key, value]` pair at zero-based `index` in ascending key
+   * order, or `null` in JavaScript when `index` is out of range.
+   *
+   * Runs in `O(log n)` time.
+   */
+  select(index: number): [number, string] | null
+  /**
+   * Counts the keys strictly less than `key`, which need not be present.
+   *
+   * Runs in `O(log n)` time.
+   */
+  countLessThan(key: number): number
+  /**
+   * Returns the `[key, value]` pairs with keys from `lo` up to `hi` in
+   * ascending key order. `lo` is inclusive and `hi` is exclusive unless
+   * `options.inclusive` is `true`; a `null` or omitted bound is unbounded.
+   *
+   * Runs in `O(log n + k)` time for `k` returned pairs.
+   */
+  range(lo?: number | undefined | null, hi?: number | undefined | null, options?: RangeOptions | undefined | null): Array<[number, string]>
+  /**
+   * Counts the keys that `range` would return for the same arguments
+   * without materializing them.
+   *
+   * Runs in `O(log n)` time.
+   */
+  countRange(lo?: number | undefined | null, hi?: number | undefined | null, options?: RangeOptions | undefined | null): number
+  /**
+   * Returns a new tree holding every key of this tree or `other`. For a key
+   * in both, `merge(key, value, otherValue)` picks the stored value; without
+   * `merge` the value from `other` wins. Neither input tree changes, and an
+   * exception thrown by `merge` propagates without producing a tree.
+   *
+   * Runs in `O(m log(n / m + 1))` time for sizes `m <= n`, joining
+   * snapshots of both trees so that untouched subtrees stay shared.
+   */
+  union(other: AvlTree, merge?: (key: number, value: string, otherValue: string) => string): AvlTree
+  /**
+   * Returns a new tree with the entries of this tree whose keys also occur
+   * in `other`.
+   *
+   * Runs in `O(m log(n / m + 1))` time for sizes `m <= n`.
+   */
+  intersection(other: AvlTree): AvlTree
+  /**
+   * Returns a new tree with the entries of this tree whose keys do not
+   * occur in `other`.
+   *
+   * Runs in `O(m log(n / m + 1))` time for sizes `m <= n`.
+   */
+  difference(other: AvlTree): AvlTree
+  /**
+   * Returns a new tree with the entries whose keys occur in exactly one of
+   * this tree and `other`.
+   *
+   * Runs in `O(m log(n / m + 1))` time for sizes `m <= n`.
+   */
+  symmetricDifference(other: AvlTree): AvlTree
+  /**
+   * Returns a read-only view of the entries present now, which later writes
+   * to this tree do not affect.
+   *
+   * Runs in `O(1)` time. The snapshot shares its nodes with the tree, and
+   * each later write copies only the `O(log n)` nodes it changes.
+   */
+  snapshot(): AVLTreeSnapshot
+  /**
+   * Returns an iterator over `[key, value]` pairs in ascending key order.
+   * The iterator reads the entries present when it was created; later
+   * writes to the tree do not affect it.
+   *
+   * Creating the iterator takes `O(1)` time and each step `O(log n)`. Like a
+   * snapshot, the iterator shares its nodes with the tree, and each later
+   * write copies only the `O(log n)` nodes it changes.
+   */
+  entries(): AVLTreeEntries
+  /**
+   * Returns an iterator over the keys in ascending order, with the same
+   * snapshot behaviour as `entries()`.
+   */
+  keys(): AVLTreeKeys
+  /**
+   * Returns an iterator over the values in ascending key order, with the
+   * same snapshot behaviour as `entries()`.
+   */
+  values(): AVLTreeValues
+  /**
+   * Calls `callback(value, key)` for each entry in ascending key order.
+   * Writes made by the callback do not change which entries are visited,
+   * and an exception thrown by the callback stops the walk and propagates.
+   *
+   * Runs in `O(n)` time plus the callbacks.
+   */
+  forEach(callback: (value: string, key: number) => void): void
+  /** Returns every `[key, value]` pair in ascending key order in `O(n)` time. */
+  toArray(): Array<[number, string]>
+  /**
+   * Encodes the tree in the versioned, checksummed binary format that
+   * `AvlTree.deserialize` reads.
+   *
+   * Runs in `O(n)` time.
+   */
+  serialize(): Buffer
+  /**
+   * Writes the tree to `path` in the `serialize` format, atomically
+   * replacing any existing file: the bytes are synced to a temporary file
+   * beside it, which is then renamed over `path`. A crash mid-save leaves
+   * either the previous file or the complete new one.
+   *
+   * Runs in `O(n)` time.
+   */
+  saveToFile(path: string): void
+  /**
+   * Writes a fresh snapshot of a tree opened with `openDurable` and empties
+   * its write-ahead log, throwing an error for any other tree.
+   *
+   * Runs in `O(n)` time.
+   */
+  compact(): void
+  /**
+   * Starts recording the value each later mutation overwrites, so that
+   * `undo` and `redo` can revert and reapply changes. At most `depth` steps
+   * and uncheckpointed changes are kept, each counting as one, dropping the
+   * oldest first. Any earlier history is discarded.
+   */
+  enableHistory(depth: number): void
+  /** Stops recording mutations and discards the history. */
+  disableHistory(): void
+  /**
+   * Groups the changes made since the previous checkpoint into one undo
+   * step named `label`. Does nothing when there are none.
+   */
+  checkpoint(label: string): void
+  /**
+   * Reverts the changes made since the last checkpoint or, when there are
+   * none, the last checkpointed step, and reports whether anything was
+   * undone. A durable tree logs the reverted values first.
+   *
+   * Runs in `O(m log n)` time for `m` changes in the step.
+   */
+  undo(): boolean
+  /**
+   * Reapplies the step most recently undone and reports whether there was
+   * one. Any mutation other than `undo` and `redo` discards the steps
+   * available to redo.
+   *
+   * Runs in `O(m log n)` time for `m` changes in the step.
+   */
+  redo(): boolean
+  /**
+   * Returns the label of the step `undo` would revert, or `null` in
+   * JavaScript for unlabelled changes, an empty history, or a tree without
+   * history.
+   */
+  undoLabel(): string | null
+  /**
+   * Returns the label of the step `redo` would reapply, or `null` in
+   * JavaScript when there is none.
+   */
+  redoLabel(): string | null
+  /**
+   * Returns the entries as a JSON array of `[key, value]` pairs in ascending
+   * key order, escaped exactly as `JSON.stringify` would escape them.
+   *
+   * Runs in `O(n)` time.
+   */
+  dumpJson(): string
   /** Returns the legacy in-order debug representation in `O(n)` time. */
   dump(): string
 }
 export type AVLTree = AvlTree
+
+/**
+ * An iterator over `[key, value]` pairs returned by `AvlTree.entries()` and
+ * `AvlTreeSnapshot.entries()`.
+ */
+export declare class AvlTreeEntries {
+  /**
+   * Returns the next `[key, value]` pair, or `null` in JavaScript when the
+   * iterator is exhausted. `index.js` builds `next()` on top of this.
+   */
+  advance(): [number, string] | null
+  /** Ends the iteration early, releasing the entries it was reading. */
+  close(): void
+}
+export type AVLTreeEntries = AvlTreeEntries
+
+/**
+ * An iterator over keys returned by `AvlTree.keys()` and
+ * `AvlTreeSnapshot.keys()`.
+ */
+export declare class AvlTreeKeys {
+  /**
+   * Returns the next key, or `null` in JavaScript when the iterator is
+   * exhausted. `index.js` builds `next()` on top of this.
+   */
+  advance(): number | null
+  /** Ends the iteration early, releasing the entries it was reading. */
+  close(): void
+}
+export type AVLTreeKeys = AvlTreeKeys
+
+/**
+ * A read-only view returned by `AvlTree.snapshot()`, holding the entries of
+ * the source tree at that moment however the source changes afterwards.
+ */
+export declare class AvlTreeSnapshot {
+  /** The number of entries, read in constant time. */
+  get size(): number
+  /**
+   * Returns the value for `key`, or `null` in JavaScript when absent.
+   *
+   * Runs in `O(log n)` time.
+   */
+  find(key: number): string | null
+  /** Reports whether `key` exists in `O(log n)` time. */
+  has(key: number): boolean
+  /**
+   * Returns the `[key, value]` pairs in a key range, with the same
+   * arguments as `AvlTree.range`.
+   *
+   * Runs in `O(log n + k)` time for `k` returned pairs.
+   */
+  range(lo?: number | undefined | null, hi?: number | undefined | null, options?: RangeOptions | undefined | null): Array<[number, string]>
+  /**
+   * Returns an iterator over `[key, value]` pairs in ascending key order.
+   *
+   * Each step runs in `O(log n)` time.
+   */
+  entries(): AVLTreeEntries
+  /** Returns an iterator over the keys in ascending order. */
+  keys(): AVLTreeKeys
+  /** Returns an iterator over the values in ascending key order. */
+  values(): AVLTreeValues
+}
+export type AVLTreeSnapshot = AvlTreeSnapshot
+
+/**
+ * The handle passed to an `AvlTree.transaction` callback for staging
+ * changes, which throws an error once the callback has returned.
+ */
+export declare class AvlTreeTransaction {
+  /**
+   * Stages an insert of `value` under `key`.
+   *
+   * Runs in `O(log n + log m)` time for `m` staged keys.
+   */
+  insert(key: number, value: string): void
+  /**
+   * Stages the removal of `key` and returns the value it had in the
+   * transaction, or `null` in JavaScript when it was absent.
+   *
+   * Runs in `O(log n + log m)` time for `m` staged keys.
+   */
+  remove(key: number): string | null
+  /**
+   * Returns the value for `key` with the staged changes applied, or `null`
+   * in JavaScript when absent.
+   *
+   * Runs in `O(log n + log m)` time for `m` staged keys.
+   */
+  find(key: number): string | null
+  /**
+   * Reports whether `key` exists with the staged changes applied.
+   *
+   * Runs in `O(log n + log m)` time for `m` staged keys.
+   */
+  has(key: number): boolean
+  /**
+   * The number of entries with the staged changes applied, read in constant
+   * time.
+   */
+  get size(): number
+}
+export type AVLTreeTransaction = AvlTreeTransaction
+
+/**
+ * An iterator over values returned by `AvlTree.values()` and
+ * `AvlTreeSnapshot.values()`.
+ */
+export declare class AvlTreeValues {
+  /**
+   * Returns the next value, or `null` in JavaScript when the iterator is
+   * exhausted. `index.js` builds `next()` on top of this.
+   */
+  advance(): string | null
+  /** Ends the iteration early, releasing the entries it was reading. */
+  close(): void
+}
+export type AVLTreeValues = AvlTreeValues
+
+/** Options accepted by `range` and `countRange` in JavaScript. */
+export interface RangeOptions {
+  /** Includes entries whose key equals `hi`. Defaults to `false`. */
+  inclusive?: boolean
+}
+
+/** Shape statistics returned by `stats` in JavaScript. */
+export interface TreeStats {
+  /** The number of entries, which equals the number of nodes. */
+  size: number
+  /** The number of levels; `0` for an empty tree and `1` for a lone root. */
+  height: number
+  /** The greatest height the AVL balance condition allows for `size` nodes. */
+  heightBound: number
+  /** Approximate native heap bytes held by nodes and value strings. */
+  heapBytes: number
+  /** The mean number of nodes visited to find a present key. */
+  averageDepth: number
+}
@@ -589,3 +589,13 @@ if (!nativeBinding) {
 module.exports = nativeBinding
 module.exports.AvlTree = nativeBinding.AvlTree
 module.exports.AVLTree = nativeBinding.AVLTree
+module.exports.AvlTreeEntries = nativeBinding.AvlTreeEntries
+module.exports.AVLTreeEntries = nativeBinding.AVLTreeEntries
+module.exports.AvlTreeKeys = nativeBinding.AvlTreeKeys
+module.exports.AVLTreeKeys = nativeBinding.AVLTreeKeys
+module.exports.AvlTreeSnapshot = nativeBinding.AvlTreeSnapshot
+module.exports.AVLTreeSnapshot = nativeBinding.AVLTreeSnapshot
+module.exports.AvlTreeTransaction = nativeBinding.AvlTreeTransaction
+module.exports.AVLTreeTransaction = nativeBinding.AVLTreeTransaction
+module.exports.AvlTreeValues = nativeBinding.AvlTreeValues
+module.exports.AVLTreeValues = nativeBinding.AVLTreeValues
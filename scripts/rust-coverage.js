@@ -12,6 +12,7 @@ for (const args of [
   [
     'llvm-cov',
     '--lib',
+    '--all-features',
     '--lcov',
     '--output-path',
     path.join(outputDirectory, 'lcov.info'),
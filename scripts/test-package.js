@@ -235,6 +235,29 @@ if (!process.argv.includes('--pack-only')) {
         "tree.insert(1, 'one');",
         'const present: boolean = tree.has(1);',
         'void present;',
+        'for (const [key, value] of tree) {',
+        '  const pair: [number, string] = [key, value];',
+        '  void pair;',
+        '}',
+        'const keys: number[] = [...tree.keys()];',
+        'void keys;',
+        'const snapshot = tree.snapshot();',
+        'const sizes: number[] = [snapshot.size];',
+        'for (const [key, value] of snapshot) {',
+        '  sizes.push(key, value.length);',
+        '}',
+        'void sizes;',
+        'const staged: unknown = tree.transaction((transaction) => {',
+        "  transaction.insert(1, 'one');",
+        '  return transaction.remove(2) ?? transaction.size;',
+        '});',
+        'void staged;',
+        'tree.enableHistory(10);',
+        "tree.checkpoint('edit');",
+        'const undone: boolean = tree.undo() && tree.redo();',
+        'const label: string | null = tree.undoLabel();',
+        'void undone;',
+        'void label;',
         '',
       ].join('\n')
     );
@@ -0,0 +1,268 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, ErrorKind, Read, Write};
+
+use super::{PersistentTree, Tree, UnsortedError};
+
+/// The bytes that open every serialized tree.
+pub const MAGIC: [u8; 4] = *b"AVLT";
+
+/// The binary format version written by [`Tree::serialize_into`].
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Caps the space reserved up front from a length read out of untrusted
+/// input, so a corrupt header cannot trigger a huge allocation on its own.
+const RESERVE_LIMIT: usize = 4096;
+
+impl Tree<i32, String> {
+    /// Writes the tree in the versioned binary format, all integers
+    /// little-endian:
+    ///
+    /// 1. the four [`MAGIC`] bytes and the one-byte [`FORMAT_VERSION`];
+    /// 2. the entry count as a `u64`;
+    /// 3. each entry in ascending key order, as an `i32` key followed by the
+    ///    value's UTF-8 byte length as a `u32` and the bytes themselves;
+    /// 4. the CRC-32 (IEEE) of everything before it, as a `u32`.
+    ///
+    /// The writer receives many small writes, so wrap unbuffered sinks such as
+    /// files in a [`BufWriter`](std::io::BufWriter).
+    pub fn serialize_into<W: Write>(&self, writer: W) -> io::Result<()> {
+        write_entries(writer, self.len, self)
+    }
+
+    /// Reads a tree written by [`Tree::serialize_into`], leaving `reader`
+    /// positioned just after the checksum.
+    ///
+    /// The checksum is verified before any value is decoded, and the entries
+    /// are then built into a balanced tree in `O(n)` time.
+    pub fn deserialize_from<R: Read>(reader: R) -> Result<Self, DeserializeError> {
+        let mut reader = Checksummed::new(reader);
+        let [magic @ .., version] = read_array::<5>(&mut reader)?;
+        if magic != MAGIC {
+            return Err(DeserializeError::BadMagic);
+        }
+        if version != FORMAT_VERSION {
+            return Err(DeserializeError::UnsupportedVersion(version));
+        }
+
+        let count = u64::from_le_bytes(read_array(&mut reader)?);
+        let reserve =
+            usize::try_from(count).map_or(RESERVE_LIMIT, |count| count.min(RESERVE_LIMIT));
+        let mut entries = Vec::with_capacity(reserve);
+        for _ in 0..count {
+            let key = i32::from_le_bytes(read_array(&mut reader)?);
+            let length = u32::from_le_bytes(read_array(&mut reader)?);
+            let mut bytes = Vec::with_capacity((length as usize).min(RESERVE_LIMIT));
+            (&mut reader).take(length.into()).read_to_end(&mut bytes)?;
+            if bytes.len() != length as usize {
+                return Err(DeserializeError::Truncated);
+            }
+            entries.push((key, bytes));
+        }
+
+        let computed = reader.crc.finish();
+        let stored = u32::from_le_bytes(read_array(&mut reader.inner)?);
+        if stored != computed {
+            return Err(DeserializeError::ChecksumMismatch { stored, computed });
+        }
+
+        let entries = entries
+            .into_iter()
+            .enumerate()
+            .map(|(index, (key, bytes))| {
+                String::from_utf8(bytes)
+                    .map(|value| (key, value))
+                    .map_err(|_| DeserializeError::InvalidUtf8 { index })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::from_sorted_iter(entries)
+            .map_err(|UnsortedError { index }| DeserializeError::Unsorted { index })
+    }
+}
+
+impl PersistentTree<i32, String> {
+    /// Writes the tree in the binary format of [`Tree::serialize_into`],
+    /// reading the entries straight from the shared nodes.
+    pub fn serialize_into<W: Write>(&self, writer: W) -> io::Result<()> {
+        write_entries(writer, self.len(), self)
+    }
+}
+
+/// Writes `len` entries, given in ascending key order, in the format of
+/// [`Tree::serialize_into`].
+fn write_entries<'a, W: Write>(
+    writer: W,
+    len: usize,
+    entries: impl IntoIterator<Item = (&'a i32, &'a String)>,
+) -> io::Result<()> {
+    let mut writer = Checksummed::new(writer);
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&[FORMAT_VERSION])?;
+    writer.write_all(&(len as u64).to_le_bytes())?;
+    for (key, value) in entries {
+        let length = u32::try_from(value.len()).map_err(|_| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("the value at key {key} is longer than u32::MAX bytes"),
+            )
+        })?;
+        writer.write_all(&key.to_le_bytes())?;
+        writer.write_all(&length.to_le_bytes())?;
+        writer.write_all(value.as_bytes())?;
+    }
+    let checksum = writer.crc.finish();
+    writer.inner.write_all(&checksum.to_le_bytes())
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// The error returned by [`Tree::deserialize_from`].
+#[derive(Debug)]
+pub enum DeserializeError {
+    /// The reader failed for a reason other than running out of input.
+    Io(io::Error),
+    /// The input ended before the checksum.
+    Truncated,
+    /// The input does not start with [`MAGIC`].
+    BadMagic,
+    /// The input was written in a format version this build cannot read.
+    UnsupportedVersion(u8),
+    /// The stored checksum does not match the bytes before it.
+    ChecksumMismatch { stored: u32, computed: u32 },
+    /// The value of entry `index` is not valid UTF-8.
+    InvalidUtf8 { index: usize },
+    /// The key of entry `index` does not follow the previous key.
+    Unsorted { index: usize },
+    /// A complete tree was followed by `length` more bytes. Callers that own
+    /// the whole input, such as [`Tree::load`] and the JavaScript
+    /// `AvlTree.deserialize`, check for this; [`Tree::deserialize_from`] does
+    /// not, since a reader may hold more data.
+    TrailingData { length: u64 },
+}
+
+impl Display for DeserializeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "failed to read the serialized tree: {error}"),
+            Self::Truncated => f.write_str("the serialized tree is truncated"),
+            Self::BadMagic => f.write_str("the input is not a serialized tree"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported serialized tree format version {version}")
+            }
+            Self::ChecksumMismatch { stored, computed } => write!(
+                f,
+                "checksum mismatch: stored {stored:#010x}, computed {computed:#010x}"
+            ),
+            Self::InvalidUtf8 { index } => write!(f, "entry {index} has a value that is not UTF-8"),
+            Self::Unsorted { index } => write!(f, "entry {index} is out of ascending key order"),
+            Self::TrailingData { length } => {
+                write!(f, "{length} unexpected bytes after the checksum")
+            }
+        }
+    }
+}
+
+impl Error for DeserializeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DeserializeError {
+    fn from(error: io::Error) -> Self {
+        if error.kind() == ErrorKind::UnexpectedEof {
+            Self::Truncated
+        } else {
+            Self::Io(error)
+        }
+    }
+}
+
+/// Wraps a reader or writer, folding every byte that passes through into a
+/// running CRC-32.
+struct Checksummed<T> {
+    inner: T,
+    crc: Crc32,
+}
+
+impl<T> Checksummed<T> {
+    fn new(inner: T) -> Self {
+        Self {
+            inner,
+            crc: Crc32::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.crc.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R: Read> Read for Checksummed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.crc.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// The reflected CRC-32 used by zlib, PNG, and Ethernet.
+struct Crc32(u32);
+
+impl Crc32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut index = 0;
+        while index < 256 {
+            let mut crc = index as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 == 0 {
+                    crc >> 1
+                } else {
+                    (crc >> 1) ^ 0xedb8_8320
+                };
+                bit += 1;
+            }
+            table[index] = crc;
+            index += 1;
+        }
+        table
+    };
+
+    fn new() -> Self {
+        Self(u32::MAX)
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = Self::TABLE[usize::from(self.0 as u8 ^ byte)] ^ (self.0 >> 8);
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+/// Returns the CRC-32 of `bytes`.
+pub(super) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.finish()
+}
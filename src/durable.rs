@@ -0,0 +1,442 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str;
+
+use super::binary::crc32;
+use super::persist::sync_directory;
+use super::{DeserializeError, PersistentTree, Tree};
+
+/// The name of the snapshot file inside a durable tree's directory.
+pub const SNAPSHOT_FILE: &str = "snapshot.bin";
+
+/// The name of the log file inside a durable tree's directory.
+pub const LOG_FILE: &str = "wal.log";
+
+/// The bytes that open every log file.
+pub const LOG_MAGIC: [u8; 4] = *b"AVLW";
+
+/// The log format version written by [`WriteAheadLog`].
+pub const LOG_VERSION: u8 = 1;
+
+/// The magic bytes and version byte that open the log file.
+const LOG_HEADER: [u8; 5] = [
+    LOG_MAGIC[0],
+    LOG_MAGIC[1],
+    LOG_MAGIC[2],
+    LOG_MAGIC[3],
+    LOG_VERSION,
+];
+
+/// The payload length, its checksum, and the payload checksum that open every
+/// frame.
+const FRAME_HEADER_LEN: usize = 12;
+
+const INSERT: u8 = 1;
+const REMOVE: u8 = 2;
+const CLEAR: u8 = 3;
+
+/// One mutation recorded in a [`WriteAheadLog`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogRecord<'a> {
+    Insert { key: i32, value: &'a str },
+    Remove { key: i32 },
+    Clear,
+}
+
+impl LogRecord<'_> {
+    /// Applies the mutation to `tree`.
+    ///
+    /// Every record sets a key, removes a key, or empties the tree outright,
+    /// so replaying a log over a snapshot that already reflects some prefix of
+    /// it yields the same tree as replaying it over the older snapshot.
+    pub fn apply(&self, tree: &mut Tree<i32, String>) {
+        match *self {
+            Self::Insert { key, value } => {
+                tree.insert(key, value.to_owned());
+            }
+            Self::Remove { key } => {
+                tree.remove(&key);
+            }
+            Self::Clear => tree.clear(),
+        }
+    }
+
+    /// Appends the tag byte followed by the key and, for an insert, the value
+    /// length and bytes, integers little-endian.
+    fn encode(&self, output: &mut Vec<u8>) -> io::Result<()> {
+        match *self {
+            Self::Insert { key, value } => {
+                let length = u32::try_from(value.len()).map_err(|_| {
+                    io::Error::new(
+                        ErrorKind::InvalidInput,
+                        "a logged value is longer than u32::MAX bytes",
+                    )
+                })?;
+                output.push(INSERT);
+                output.extend(key.to_le_bytes());
+                output.extend(length.to_le_bytes());
+                output.extend(value.as_bytes());
+            }
+            Self::Remove { key } => {
+                output.push(REMOVE);
+                output.extend(key.to_le_bytes());
+            }
+            Self::Clear => output.push(CLEAR),
+        }
+        Ok(())
+    }
+
+    /// Decodes the record at the start of `payload` and returns it with the
+    /// bytes after it.
+    fn decode(payload: &[u8]) -> Option<(LogRecord<'_>, &[u8])> {
+        let (&tag, rest) = payload.split_first()?;
+        match tag {
+            INSERT => {
+                let (key, rest) = rest.split_first_chunk::<4>()?;
+                let (length, rest) = rest.split_first_chunk::<4>()?;
+                let length = u32::from_le_bytes(*length) as usize;
+                if rest.len() < length {
+                    return None;
+                }
+                let (value, rest) = rest.split_at(length);
+                let record = LogRecord::Insert {
+                    key: i32::from_le_bytes(*key),
+                    value: str::from_utf8(value).ok()?,
+                };
+                Some((record, rest))
+            }
+            REMOVE => {
+                let (key, rest) = rest.split_first_chunk::<4>()?;
+                let key = i32::from_le_bytes(*key);
+                Some((LogRecord::Remove { key }, rest))
+            }
+            CLEAR => Some((LogRecord::Clear, rest)),
+            _ => None,
+        }
+    }
+}
+
+/// Appends one frame holding `records`: the payload length, the CRC-32 of
+/// those four bytes, the CRC-32 of the payload, and the payload of records
+/// back to back, integers little-endian. A frame is replayed whole or not at
+/// all, which makes a multi-record append atomic.
+fn encode_frame(records: &[LogRecord<'_>], output: &mut Vec<u8>) -> io::Result<()> {
+    output.extend([0; FRAME_HEADER_LEN]);
+    for record in records {
+        record.encode(output)?;
+    }
+    let length = u32::try_from(output.len() - FRAME_HEADER_LEN).map_err(|_| {
+        io::Error::new(
+            ErrorKind::InvalidInput,
+            "a log frame is longer than u32::MAX bytes",
+        )
+    })?;
+    let length = length.to_le_bytes();
+    let checksum = crc32(&output[FRAME_HEADER_LEN..]);
+    output[..4].copy_from_slice(&length);
+    output[4..8].copy_from_slice(&crc32(&length).to_le_bytes());
+    output[8..FRAME_HEADER_LEN].copy_from_slice(&checksum.to_le_bytes());
+    Ok(())
+}
+
+/// Decodes every record of a frame's payload, failing unless they fill it
+/// exactly.
+fn decode_frame(mut payload: &[u8]) -> Option<Vec<LogRecord<'_>>> {
+    let mut records = Vec::new();
+    while !payload.is_empty() {
+        let (record, rest) = LogRecord::decode(payload)?;
+        records.push(record);
+        payload = rest;
+    }
+    (!records.is_empty()).then_some(records)
+}
+
+/// Applies every complete frame in `log` after the file header to `tree` and
+/// returns the length of the prefix they occupy.
+///
+/// A crash mid-append can only leave a frame cut short at the end of the file,
+/// so a tail shorter than a frame header, a frame whose checksummed length
+/// runs past the end, or a final frame whose payload fails its checksum is
+/// ignored as torn. Damage that is not provably at the end of the file,
+/// including a frame length that fails its checksum with more data after it,
+/// is reported as corruption.
+fn replay(log: &[u8], tree: &mut Tree<i32, String>) -> Result<usize, DurableError> {
+    let mut offset = LOG_HEADER.len();
+    while let Some((header, body)) = log[offset..].split_first_chunk::<FRAME_HEADER_LEN>() {
+        let corrupt = DurableError::CorruptLog {
+            offset: offset as u64,
+        };
+        let [l0, l1, l2, l3, h0, h1, h2, h3, c0, c1, c2, c3] = *header;
+        if crc32(&[l0, l1, l2, l3]) != u32::from_le_bytes([h0, h1, h2, h3]) {
+            // Without a trusted length, only a header with nothing after it
+            // can be the torn end of the file.
+            if body.is_empty() {
+                break;
+            }
+            return Err(corrupt);
+        }
+        let length = u32::from_le_bytes([l0, l1, l2, l3]) as usize;
+        if body.len() < length {
+            break;
+        }
+        let end = offset + FRAME_HEADER_LEN + length;
+        if crc32(&body[..length]) != u32::from_le_bytes([c0, c1, c2, c3]) {
+            if end == log.len() {
+                break;
+            }
+            return Err(corrupt);
+        }
+        for record in decode_frame(&body[..length]).ok_or(corrupt)? {
+            record.apply(tree);
+        }
+        offset = end;
+    }
+    Ok(offset)
+}
+
+/// The append-only log of mutations made since a durable tree's last snapshot.
+///
+/// A durable tree lives in a directory holding a [`SNAPSHOT_FILE`] in the
+/// format of [`Tree::save`] and a [`LOG_FILE`] that opens with the four
+/// [`LOG_MAGIC`] bytes and the one-byte [`LOG_VERSION`], followed by
+/// checksummed frames of records. Each append is synced to disk before it
+/// returns, so a mutation applied after a successful append survives a crash.
+/// Only one process may have a directory open at a time.
+pub struct WriteAheadLog {
+    directory: PathBuf,
+    file: File,
+    len: u64,
+}
+
+impl WriteAheadLog {
+    /// Opens the durable tree stored in `directory`, creating the directory
+    /// and an empty log when missing, and returns the tree rebuilt from the
+    /// snapshot and the log. A torn final frame is cut off the log; any other
+    /// damage fails the call and leaves the log untouched.
+    pub fn open<P: AsRef<Path>>(directory: P) -> Result<(Tree<i32, String>, Self), DurableError> {
+        let directory = directory.as_ref().to_owned();
+        fs::create_dir_all(&directory)?;
+        let mut tree = match Tree::load(directory.join(SNAPSHOT_FILE)) {
+            Ok(tree) => tree,
+            Err(DeserializeError::Io(error)) if error.kind() == ErrorKind::NotFound => Tree::new(),
+            Err(error) => return Err(DurableError::Snapshot(error)),
+        };
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(directory.join(LOG_FILE))?;
+        let mut log = Vec::new();
+        file.read_to_end(&mut log)?;
+        if log.len() < LOG_HEADER.len() {
+            if !LOG_HEADER.starts_with(&log) {
+                return Err(DurableError::BadLogMagic);
+            }
+            // A missing or partly written header means that nothing was ever
+            // appended, so the log can be started over.
+            file.set_len(0)?;
+            file.write_all(&LOG_HEADER)?;
+            file.sync_data()?;
+            log = LOG_HEADER.to_vec();
+        }
+        let (magic, version) = (&log[..4], log[4]);
+        if magic != LOG_MAGIC {
+            return Err(DurableError::BadLogMagic);
+        }
+        if version != LOG_VERSION {
+            return Err(DurableError::UnsupportedLogVersion(version));
+        }
+        let len = replay(&log, &mut tree)? as u64;
+        if len < log.len() as u64 {
+            file.set_len(len)?;
+            file.sync_data()?;
+        }
+        sync_directory(&directory)?;
+        Ok((
+            tree,
+            Self {
+                directory,
+                file,
+                len,
+            },
+        ))
+    }
+
+    /// Writes `records` to the log as one frame and syncs it to disk, so a
+    /// crash keeps either all of them or none. When anything fails, the log is
+    /// cut back to its previous length.
+    pub fn append(&mut self, records: &[LogRecord<'_>]) -> io::Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let mut frame = Vec::new();
+        encode_frame(records, &mut frame)?;
+        match self
+            .file
+            .write_all(&frame)
+            .and_then(|()| self.file.sync_data())
+        {
+            Ok(()) => {
+                self.len += frame.len() as u64;
+                Ok(())
+            }
+            Err(error) => {
+                // Appends after a torn frame would be reported as corruption
+                // on the next open, so drop any partial frame now.
+                let _ = self.file.set_len(self.len);
+                Err(error)
+            }
+        }
+    }
+
+    /// Replaces the snapshot with `tree` and truncates the log to its header.
+    ///
+    /// The snapshot is replaced atomically before the log is truncated, so a
+    /// crash in between replays records that the new snapshot already holds,
+    /// which leaves the same tree.
+    pub fn compact(&mut self, tree: &Tree<i32, String>) -> io::Result<()> {
+        tree.save(self.directory.join(SNAPSHOT_FILE))?;
+        self.truncate()
+    }
+
+    /// Compacts like [`WriteAheadLog::compact`], writing the snapshot
+    /// straight from the shared nodes of `tree`.
+    pub fn compact_persistent(&mut self, tree: &PersistentTree<i32, String>) -> io::Result<()> {
+        tree.save(self.directory.join(SNAPSHOT_FILE))?;
+        self.truncate()
+    }
+
+    /// Truncates the log to its header once the snapshot holds every record.
+    fn truncate(&mut self) -> io::Result<()> {
+        let len = LOG_HEADER.len() as u64;
+        self.file.set_len(len)?;
+        self.file.sync_data()?;
+        self.len = len;
+        Ok(())
+    }
+
+    /// Returns the directory holding the snapshot and the log.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Returns the byte length of the log, file header included.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Reports whether the log holds no records, as after a compaction.
+    pub fn is_empty(&self) -> bool {
+        self.len == LOG_HEADER.len() as u64
+    }
+}
+
+/// A [`Tree`] whose every mutation is recorded in a [`WriteAheadLog`] before
+/// it is applied.
+///
+/// A failed append leaves the tree unchanged, so the tree in memory never gets
+/// ahead of the one recovered by the next [`DurableTree::open`].
+pub struct DurableTree {
+    tree: Tree<i32, String>,
+    log: WriteAheadLog,
+}
+
+impl DurableTree {
+    /// Opens or creates the durable tree stored in `directory`. See
+    /// [`WriteAheadLog::open`].
+    pub fn open<P: AsRef<Path>>(directory: P) -> Result<Self, DurableError> {
+        let (tree, log) = WriteAheadLog::open(directory)?;
+        Ok(Self { tree, log })
+    }
+
+    /// Returns the tree for reading.
+    pub fn tree(&self) -> &Tree<i32, String> {
+        &self.tree
+    }
+
+    pub fn log(&self) -> &WriteAheadLog {
+        &self.log
+    }
+
+    /// Logs and then performs [`Tree::insert`].
+    pub fn insert(&mut self, key: i32, value: String) -> io::Result<Option<String>> {
+        self.log
+            .append(&[LogRecord::Insert { key, value: &value }])?;
+        Ok(self.tree.insert(key, value))
+    }
+
+    /// Logs and then performs [`Tree::remove`]. Nothing is logged for an
+    /// absent key.
+    pub fn remove(&mut self, key: &i32) -> io::Result<Option<String>> {
+        if !self.tree.has(key) {
+            return Ok(None);
+        }
+        self.log.append(&[LogRecord::Remove { key: *key }])?;
+        Ok(self.tree.remove(key))
+    }
+
+    /// Logs and then performs [`Tree::clear`].
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.log.append(&[LogRecord::Clear])?;
+        self.tree.clear();
+        Ok(())
+    }
+
+    /// Writes a fresh snapshot and empties the log. See
+    /// [`WriteAheadLog::compact`].
+    pub fn compact(&mut self) -> io::Result<()> {
+        self.log.compact(&self.tree)
+    }
+}
+
+/// The error returned when a durable tree cannot be opened.
+#[derive(Debug)]
+pub enum DurableError {
+    /// The directory or the log could not be read, created, or truncated.
+    Io(io::Error),
+    /// The snapshot exists but cannot be loaded.
+    Snapshot(DeserializeError),
+    /// The log does not start with [`LOG_MAGIC`].
+    BadLogMagic,
+    /// The log was written in a format version this build cannot read.
+    UnsupportedLogVersion(u8),
+    /// The log frame at byte `offset` is damaged in a way that a crash
+    /// mid-append cannot explain.
+    CorruptLog { offset: u64 },
+}
+
+impl Display for DurableError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "failed to open the durable tree: {error}"),
+            Self::Snapshot(error) => write!(f, "cannot load the snapshot: {error}"),
+            Self::BadLogMagic => f.write_str("the log file is not a write-ahead log"),
+            Self::UnsupportedLogVersion(version) => {
+                write!(f, "unsupported write-ahead log format version {version}")
+            }
+            Self::CorruptLog { offset } => {
+                write!(f, "the log record at byte {offset} is corrupt")
+            }
+        }
+    }
+}
+
+impl Error for DurableError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::Snapshot(error) => Some(error),
+            Self::BadLogMagic | Self::UnsupportedLogVersion(_) | Self::CorruptLog { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for DurableError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
@@ -0,0 +1,184 @@
+use std::error::Error;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::mem;
+
+use super::{Comparator, Natural, Path, Tree};
+
+/// A view into a single key of a [`Tree`], which is either present or absent.
+///
+/// Created by [`Tree::entry`].
+pub enum Entry<'a, K, V, C = Natural> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V, C>),
+}
+
+impl<'a, K, V, C: Comparator<K>> Entry<'a, K, V, C> {
+    /// Returns the key of this entry.
+    pub fn key(&self) -> &K {
+        match self {
+            Self::Occupied(entry) => entry.key(),
+            Self::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Returns the stored value, inserting `default` first when the key is
+    /// absent.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Self::Occupied(entry) => entry.into_mut(),
+            Self::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Returns the stored value, inserting the result of `default` first when
+    /// the key is absent. `default` is not called for a present key.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Self::Occupied(entry) => entry.into_mut(),
+            Self::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Returns the stored value, inserting `V::default()` first when the key is
+    /// absent.
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Calls `modify` on the stored value when the key is present.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, modify: F) -> Self {
+        if let Self::Occupied(entry) = &mut self {
+            modify(entry.get_mut());
+        }
+        self
+    }
+}
+
+/// An [`Entry`] for a key that is present in the tree.
+pub struct OccupiedEntry<'a, K, V> {
+    key: &'a K,
+    value: &'a mut V,
+}
+
+impl<'a, K, V> OccupiedEntry<'a, K, V> {
+    pub(super) fn new(key: &'a K, value: &'a mut V) -> Self {
+        Self { key, value }
+    }
+
+    /// Returns the key stored in the tree.
+    pub fn key(&self) -> &K {
+        self.key
+    }
+
+    pub fn get(&self) -> &V {
+        self.value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        self.value
+    }
+
+    /// Converts the entry into a reference that lives as long as the tree
+    /// borrow.
+    pub fn into_mut(self) -> &'a mut V {
+        self.value
+    }
+
+    /// Replaces the stored value and returns the previous one.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.value, value)
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for OccupiedEntry<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OccupiedEntry")
+            .field("key", self.key)
+            .field("value", self.value)
+            .finish()
+    }
+}
+
+/// The error returned by [`Tree::try_insert`] when the key is already present.
+#[derive(Debug)]
+pub struct OccupiedError<'a, K, V> {
+    /// The entry that was left unchanged.
+    pub entry: OccupiedEntry<'a, K, V>,
+    /// The value that was not inserted.
+    pub value: V,
+}
+
+impl<K: Debug, V: Debug> Display for OccupiedError<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "key {:?} is already present with value {:?}",
+            self.entry.key(),
+            self.entry.get()
+        )
+    }
+}
+
+impl<K: Debug, V: Debug> Error for OccupiedError<'_, K, V> {}
+
+/// An [`Entry`] for a key that is absent from the tree.
+///
+/// The search path to the key stays detached from the tree until the entry is
+/// used or dropped, so inserting rebalances along it without searching again.
+/// Leaking the entry with [`mem::forget`] leaves those nodes out of the tree.
+pub struct VacantEntry<'a, K, V, C = Natural> {
+    key: K,
+    path: DetachedPath<'a, K, V, C>,
+}
+
+impl<'a, K, V, C: Comparator<K>> VacantEntry<'a, K, V, C> {
+    /// `path` is the search path to `key`, detached from `tree`.
+    pub(super) fn new(key: K, tree: &'a mut Tree<K, V, C>, path: Path<K, V>) -> Self {
+        let path = DetachedPath {
+            tree: Some(tree),
+            nodes: path,
+        };
+        Self { key, path }
+    }
+
+    /// Returns the key that would be inserted.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Takes back the key without inserting anything.
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts `value` under the entry's key and returns a reference to it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        let Self { key, mut path } = self;
+        let tree = path.tree.take().expect("a vacant entry holds its tree");
+        let mut nodes = mem::take(&mut path.nodes);
+        let leaf =
+            Tree::<K, V, C>::insert_into_path(&mut nodes, Box::new(super::Node::new(key, value)));
+        tree.len += 1;
+        &mut Tree::<K, V, C>::reattach(&mut tree.root, nodes)
+            .insert(leaf)
+            .value
+    }
+}
+
+/// Puts the search path of a [`VacantEntry`] back into its tree when the
+/// entry is dropped without inserting.
+struct DetachedPath<'a, K, V, C> {
+    tree: Option<&'a mut Tree<K, V, C>>,
+    nodes: Path<K, V>,
+}
+
+impl<K, V, C> Drop for DetachedPath<'_, K, V, C> {
+    fn drop(&mut self) {
+        if let Some(tree) = self.tree.take() {
+            Tree::<K, V, C>::reattach(&mut tree.root, mem::take(&mut self.nodes));
+        }
+    }
+}
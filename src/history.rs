@@ -0,0 +1,275 @@
+use std::collections::VecDeque;
+use std::mem;
+
+use super::{Comparator, Natural, PersistentTree, Tree};
+
+/// The changes between two checkpoints, each a key and the value it held
+/// before the change, with `None` for an absent key.
+struct Step<K, V> {
+    label: Option<String>,
+    changes: Vec<(K, Option<V>)>,
+}
+
+impl<K: Clone, V> Step<K, V> {
+    /// Restores every recorded value, newest first, and returns the step that
+    /// restores the values it overwrote.
+    fn revert<T: Undoable<K, V>>(self, tree: &mut T) -> Self {
+        let changes = self
+            .changes
+            .into_iter()
+            .rev()
+            .map(|(key, previous)| {
+                let current = match previous {
+                    Some(value) => tree.insert(key.clone(), value),
+                    None => tree.remove(&key),
+                };
+                (key, current)
+            })
+            .collect();
+        Self {
+            label: self.label,
+            changes,
+        }
+    }
+}
+
+/// A map whose changes a [`History`] can revert, implemented by [`Tree`] and
+/// [`PersistentTree`].
+pub trait Undoable<K, V> {
+    /// Inserts `value` under `key` and returns the value it replaced.
+    fn insert(&mut self, key: K, value: V) -> Option<V>;
+
+    /// Removes `key` and returns its value.
+    fn remove(&mut self, key: &K) -> Option<V>;
+}
+
+impl<K, V, C: Comparator<K>> Undoable<K, V> for Tree<K, V, C> {
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        Tree::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        Tree::remove(self, key)
+    }
+}
+
+impl<K: Clone, V: Clone, C: Comparator<K>> Undoable<K, V> for PersistentTree<K, V, C> {
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        PersistentTree::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        PersistentTree::remove(self, key)
+    }
+}
+
+/// An undo and redo history of the changes made to a [`Tree`] or any other
+/// [`Undoable`] map.
+///
+/// Every change is recorded as its inverse: the key and the value it held
+/// before, which covers inserts that replace a value as well as removals.
+/// [`History::checkpoint`] closes the changes made since the previous
+/// checkpoint into one labelled step, and [`History::undo`] reverts the
+/// changes since the last checkpoint or, when there are none, the last step.
+/// At most `depth` steps and uncheckpointed changes are kept, each counting as
+/// one: past that, the oldest step is dropped or, once no step is left, the
+/// oldest change since the last checkpoint.
+pub struct History<K, V> {
+    undo: VecDeque<Step<K, V>>,
+    redo: Vec<Step<K, V>>,
+    pending: Vec<(K, Option<V>)>,
+    depth: usize,
+}
+
+impl<K, V> History<K, V> {
+    /// Creates an empty history that keeps up to `depth` steps. A depth of 0
+    /// records nothing.
+    pub fn new(depth: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            pending: Vec::new(),
+            depth,
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Records that `key` held `previous` before a change, and discards the
+    /// steps available to redo.
+    pub fn record(&mut self, key: K, previous: Option<V>) {
+        if self.depth == 0 {
+            return;
+        }
+        self.redo.clear();
+        self.pending.push((key, previous));
+        if self.undo.len() + self.pending.len() <= self.depth {
+            return;
+        }
+        if self.undo.pop_front().is_none() && self.pending.len() / 2 >= self.depth {
+            // With no step left to drop, `pending` hides the oldest changes
+            // and cuts them off once they outnumber the kept ones, which keeps
+            // recording amortized `O(1)`.
+            self.pending.drain(..self.pending.len() - self.depth);
+        }
+    }
+
+    /// Closes the changes made since the previous checkpoint into a step named
+    /// `label`. Does nothing when there are none.
+    pub fn checkpoint<L: Into<String>>(&mut self, label: L) {
+        self.close(Some(label.into()));
+    }
+
+    /// Returns the changes the next [`History::undo`] restores, applied from
+    /// last to first, or `None` when there is nothing to undo.
+    pub fn undo_changes(&self) -> Option<&[(K, Option<V>)]> {
+        if self.pending.is_empty() {
+            self.undo.back().map(|step| &step.changes[..])
+        } else {
+            Some(self.pending())
+        }
+    }
+
+    /// Returns the changes the next [`History::redo`] restores, applied from
+    /// last to first, or `None` when there is nothing to redo.
+    pub fn redo_changes(&self) -> Option<&[(K, Option<V>)]> {
+        self.redo.last().map(|step| &step.changes[..])
+    }
+
+    /// Returns the label of the step the next [`History::undo`] reverts,
+    /// which is `None` for the changes since the last checkpoint.
+    pub fn undo_label(&self) -> Option<&str> {
+        if self.pending.is_empty() {
+            self.undo.back()?.label.as_deref()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the label of the step the next [`History::redo`] reapplies.
+    pub fn redo_label(&self) -> Option<&str> {
+        self.redo.last()?.label.as_deref()
+    }
+
+    /// Forgets every recorded change.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.pending.clear();
+    }
+
+    /// The changes since the last checkpoint that are still kept.
+    fn pending(&self) -> &[(K, Option<V>)] {
+        &self.pending[self.pending.len().saturating_sub(self.depth)..]
+    }
+
+    fn close(&mut self, label: Option<String>) {
+        if !self.pending.is_empty() {
+            let mut changes = mem::take(&mut self.pending);
+            changes.drain(..changes.len().saturating_sub(self.depth));
+            self.push(Step { label, changes });
+        }
+    }
+
+    fn push(&mut self, step: Step<K, V>) {
+        self.undo.push_back(step);
+        if self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+    }
+}
+
+impl<K: Clone, V> History<K, V> {
+    /// Reverts the changes since the last checkpoint, or the last step when
+    /// there are none, and reports whether there was anything to undo.
+    pub fn undo<T: Undoable<K, V>>(&mut self, tree: &mut T) -> bool {
+        self.close(None);
+        match self.undo.pop_back() {
+            Some(step) => {
+                self.redo.push(step.revert(tree));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reapplies the last undone step and reports whether there was one.
+    pub fn redo<T: Undoable<K, V>>(&mut self, tree: &mut T) -> bool {
+        match self.redo.pop() {
+            Some(step) => {
+                let step = step.revert(tree);
+                self.push(step);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A [`Tree`] whose inserts and removals are recorded in a [`History`], so
+/// they can be undone and redone.
+///
+/// Created by [`Tree::with_history`].
+pub struct HistoryTree<K, V, C = Natural> {
+    tree: Tree<K, V, C>,
+    history: History<K, V>,
+}
+
+impl<K, V, C> HistoryTree<K, V, C> {
+    pub(super) fn new(tree: Tree<K, V, C>, depth: usize) -> Self {
+        Self {
+            tree,
+            history: History::new(depth),
+        }
+    }
+
+    /// Returns the tree for reading.
+    pub fn tree(&self) -> &Tree<K, V, C> {
+        &self.tree
+    }
+
+    pub fn history(&self) -> &History<K, V> {
+        &self.history
+    }
+
+    /// Returns the tree and drops the history.
+    pub fn into_tree(self) -> Tree<K, V, C> {
+        self.tree
+    }
+
+    /// Closes the changes made since the previous checkpoint into a step named
+    /// `label`. See [`History::checkpoint`].
+    pub fn checkpoint<L: Into<String>>(&mut self, label: L) {
+        self.history.checkpoint(label);
+    }
+}
+
+impl<K: Clone, V: Clone, C: Comparator<K>> HistoryTree<K, V, C> {
+    /// Performs [`Tree::insert`] and records the key with the value it
+    /// replaced, if any.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let replaced = self.tree.insert(key.clone(), value);
+        self.history.record(key, replaced.clone());
+        replaced
+    }
+
+    /// Performs [`Tree::remove`] and records the removed value. Nothing is
+    /// recorded for an absent key.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let removed = self.tree.remove(key)?;
+        self.history.record(key.clone(), Some(removed.clone()));
+        Some(removed)
+    }
+
+    /// See [`History::undo`].
+    pub fn undo(&mut self) -> bool {
+        self.history.undo(&mut self.tree)
+    }
+
+    /// See [`History::redo`].
+    pub fn redo(&mut self) -> bool {
+        self.history.redo(&mut self.tree)
+    }
+}
@@ -0,0 +1,489 @@
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::iter::FusedIterator;
+use std::ops::Bound;
+
+use super::{Comparator, Link, Node};
+
+/// An iterator over the entries of a [`Tree`](super::Tree) whose keys fall
+/// within a range, in ascending key order.
+///
+/// Created by [`Tree::range`](super::Tree::range). Each end keeps an explicit
+/// stack of the ancestors it still has to visit, so iteration never recurses
+/// and uses `O(log n)` auxiliary space.
+pub struct Range<'a, K, V> {
+    front: Vec<&'a Node<K, V>>,
+    back: Vec<&'a Node<K, V>>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Range<'a, K, V> {
+    /// Positions both ends at the extremes of a tree holding `len` entries.
+    fn full(root: &'a Link<K, V>, len: usize) -> Self {
+        let mut range = Self {
+            front: Vec::new(),
+            back: Vec::new(),
+            remaining: len,
+        };
+        let mut current = root.as_deref();
+        while let Some(node) = current {
+            range.front.push(node);
+            current = node.left.as_deref();
+        }
+        let mut current = root.as_deref();
+        while let Some(node) = current {
+            range.back.push(node);
+            current = node.right.as_deref();
+        }
+        range
+    }
+
+    /// Positions both ends of the range. `remaining` must be the exact number
+    /// of entries within the bounds, which stops the ends from crossing.
+    pub(super) fn new<C: Comparator<K>>(
+        root: &'a Link<K, V>,
+        start: Bound<&K>,
+        end: Bound<&K>,
+        compare: &C,
+        remaining: usize,
+    ) -> Self {
+        let mut range = Self {
+            front: Vec::new(),
+            back: Vec::new(),
+            remaining,
+        };
+        if remaining == 0 {
+            return range;
+        }
+
+        let mut current = root.as_deref();
+        while let Some(node) = current {
+            let above_start = match start {
+                Bound::Included(start) => compare.compare(&node.key, start) != Ordering::Less,
+                Bound::Excluded(start) => compare.compare(&node.key, start) == Ordering::Greater,
+                Bound::Unbounded => true,
+            };
+            if above_start {
+                range.front.push(node);
+                current = node.left.as_deref();
+            } else {
+                current = node.right.as_deref();
+            }
+        }
+
+        let mut current = root.as_deref();
+        while let Some(node) = current {
+            let below_end = match end {
+                Bound::Included(end) => compare.compare(&node.key, end) != Ordering::Greater,
+                Bound::Excluded(end) => compare.compare(&node.key, end) == Ordering::Less,
+                Bound::Unbounded => true,
+            };
+            if below_end {
+                range.back.push(node);
+                current = node.right.as_deref();
+            } else {
+                current = node.left.as_deref();
+            }
+        }
+
+        range
+    }
+}
+
+impl<K, V> Clone for Range<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            front: self.front.clone(),
+            back: self.back.clone(),
+            remaining: self.remaining,
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self
+            .front
+            .pop()
+            .expect("a non-empty range has a front node");
+        let mut current = node.right.as_deref();
+        while let Some(next) = current {
+            self.front.push(next);
+            current = next.left.as_deref();
+        }
+        self.remaining -= 1;
+        Some((&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Range<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.back.pop().expect("a non-empty range has a back node");
+        let mut current = node.left.as_deref();
+        while let Some(next) = current {
+            self.back.push(next);
+            current = next.right.as_deref();
+        }
+        self.remaining -= 1;
+        Some((&node.key, &node.value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Range<'_, K, V> {}
+
+impl<K, V> FusedIterator for Range<'_, K, V> {}
+
+/// An iterator over the entries of a [`Tree`](super::Tree) in ascending key
+/// order.
+///
+/// Created by [`Tree::iter`](super::Tree::iter).
+pub struct Iter<'a, K, V> {
+    range: Range<'a, K, V>,
+}
+
+impl<'a, K, V> Iter<'a, K, V> {
+    pub(super) fn new(root: &'a Link<K, V>, len: usize) -> Self {
+        Self {
+            range: Range::full(root, len),
+        }
+    }
+}
+
+impl<K, V> Clone for Iter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            range: self.range.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.range.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.range.next_back()
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+/// An iterator over the keys of a [`Tree`](super::Tree) in ascending order.
+///
+/// Created by [`Tree::keys`](super::Tree::keys).
+pub struct Keys<'a, K, V> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Keys<'a, K, V> {
+    pub(super) fn new(inner: Iter<'a, K, V>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<K, V> Clone for Keys<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Keys<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(key, _)| key)
+    }
+}
+
+impl<K, V> ExactSizeIterator for Keys<'_, K, V> {}
+
+impl<K, V> FusedIterator for Keys<'_, K, V> {}
+
+/// An iterator over the values of a [`Tree`](super::Tree) in ascending key
+/// order.
+///
+/// Created by [`Tree::values`](super::Tree::values).
+pub struct Values<'a, K, V> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Values<'a, K, V> {
+    pub(super) fn new(inner: Iter<'a, K, V>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<K, V> Clone for Values<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, value)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Values<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(_, value)| value)
+    }
+}
+
+impl<K, V> ExactSizeIterator for Values<'_, K, V> {}
+
+impl<K, V> FusedIterator for Values<'_, K, V> {}
+
+/// Work left for an iterator that cannot stack borrows of its ancestors:
+/// either an entry ready to yield or a subtree that has not been split yet.
+enum Pending<S, E> {
+    Subtree(S),
+    Entry(E),
+}
+
+/// The traversal shared by [`IterMut`] and [`IntoIter`]. Mutable borrows and
+/// owned boxes cannot be stacked the way [`Range`] stacks shared references,
+/// so each end splits the subtree it reaches into its left child, entry and
+/// right child and queues the pieces, keeping `O(log n)` items pending.
+struct Unfold<S, E> {
+    pending: VecDeque<Pending<S, E>>,
+    remaining: usize,
+}
+
+impl<S, E> Unfold<S, E> {
+    fn new(root: Option<S>, len: usize) -> Self {
+        Self {
+            pending: root.map(Pending::Subtree).into_iter().collect(),
+            remaining: len,
+        }
+    }
+
+    fn next(&mut self, split: impl Fn(S) -> (Option<S>, E, Option<S>)) -> Option<E> {
+        loop {
+            match self.pending.pop_front()? {
+                Pending::Entry(entry) => {
+                    self.remaining -= 1;
+                    return Some(entry);
+                }
+                Pending::Subtree(subtree) => {
+                    let (left, entry, right) = split(subtree);
+                    if let Some(right) = right {
+                        self.pending.push_front(Pending::Subtree(right));
+                    }
+                    self.pending.push_front(Pending::Entry(entry));
+                    if let Some(left) = left {
+                        self.pending.push_front(Pending::Subtree(left));
+                    }
+                }
+            }
+        }
+    }
+
+    fn next_back(&mut self, split: impl Fn(S) -> (Option<S>, E, Option<S>)) -> Option<E> {
+        loop {
+            match self.pending.pop_back()? {
+                Pending::Entry(entry) => {
+                    self.remaining -= 1;
+                    return Some(entry);
+                }
+                Pending::Subtree(subtree) => {
+                    let (left, entry, right) = split(subtree);
+                    if let Some(left) = left {
+                        self.pending.push_back(Pending::Subtree(left));
+                    }
+                    self.pending.push_back(Pending::Entry(entry));
+                    if let Some(right) = right {
+                        self.pending.push_back(Pending::Subtree(right));
+                    }
+                }
+            }
+        }
+    }
+}
+
+type BorrowedMut<'a, K, V> = (
+    Option<&'a mut Node<K, V>>,
+    (&'a K, &'a mut V),
+    Option<&'a mut Node<K, V>>,
+);
+
+fn split_mut<K, V>(node: &mut Node<K, V>) -> BorrowedMut<'_, K, V> {
+    let Node {
+        key,
+        value,
+        left,
+        right,
+        ..
+    } = node;
+    (left.as_deref_mut(), (key, value), right.as_deref_mut())
+}
+
+type Owned<K, V> = (Link<K, V>, (K, V), Link<K, V>);
+
+fn split_owned<K, V>(node: Node<K, V>) -> Owned<K, V> {
+    let Node {
+        key,
+        value,
+        left,
+        right,
+        ..
+    } = node;
+    (left, (key, value), right)
+}
+
+/// A mutable iterator over the entries of a [`Tree`](super::Tree) in
+/// ascending key order. Keys stay shared so the order cannot be broken.
+///
+/// Created by [`Tree::iter_mut`](super::Tree::iter_mut).
+pub struct IterMut<'a, K, V> {
+    unfold: Unfold<&'a mut Node<K, V>, (&'a K, &'a mut V)>,
+}
+
+impl<'a, K, V> IterMut<'a, K, V> {
+    pub(super) fn new(root: &'a mut Link<K, V>, len: usize) -> Self {
+        Self {
+            unfold: Unfold::new(root.as_deref_mut(), len),
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.unfold.next(split_mut)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.unfold.remaining, Some(self.unfold.remaining))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IterMut<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.unfold.next_back(split_mut)
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+impl<K, V> FusedIterator for IterMut<'_, K, V> {}
+
+/// A mutable iterator over the values of a [`Tree`](super::Tree) in ascending
+/// key order.
+///
+/// Created by [`Tree::values_mut`](super::Tree::values_mut).
+pub struct ValuesMut<'a, K, V> {
+    inner: IterMut<'a, K, V>,
+}
+
+impl<'a, K, V> ValuesMut<'a, K, V> {
+    pub(super) fn new(inner: IterMut<'a, K, V>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, K, V> Iterator for ValuesMut<'a, K, V> {
+    type Item = &'a mut V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, value)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for ValuesMut<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(_, value)| value)
+    }
+}
+
+impl<K, V> ExactSizeIterator for ValuesMut<'_, K, V> {}
+
+impl<K, V> FusedIterator for ValuesMut<'_, K, V> {}
+
+/// An owning iterator over the entries of a [`Tree`](super::Tree) in ascending
+/// key order.
+///
+/// Created by the [`IntoIterator`] implementation for `Tree`. Entries that are
+/// never yielded are dropped with the iterator.
+pub struct IntoIter<K, V> {
+    unfold: Unfold<Box<Node<K, V>>, (K, V)>,
+}
+
+impl<K, V> IntoIter<K, V> {
+    pub(super) fn new(root: Link<K, V>, len: usize) -> Self {
+        Self {
+            unfold: Unfold::new(root, len),
+        }
+    }
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.unfold.next(|node| split_owned(*node))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.unfold.remaining, Some(self.unfold.remaining))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.unfold.next_back(|node| split_owned(*node))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+impl<K, V> FusedIterator for IntoIter<K, V> {}
@@ -0,0 +1,265 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter, Write};
+
+use super::{Comparator, PersistentTree, Tree};
+
+impl<C: Comparator<i32>> Tree<i32, String, C> {
+    /// Writes the entries as a compact JSON array of `[key, value]` pairs in
+    /// tree order. The output matches `JSON.stringify` applied to the same
+    /// pairs, so any JSON parser reads it back losslessly.
+    pub fn to_json(&self) -> String {
+        write_entries(self.len, self)
+    }
+}
+
+impl<C> PersistentTree<i32, String, C> {
+    /// Writes the entries like [`Tree::to_json`], reading them straight from
+    /// the shared nodes.
+    pub fn to_json(&self) -> String {
+        write_entries(self.len(), self)
+    }
+}
+
+/// Writes `len` entries as the JSON array of [`Tree::to_json`].
+fn write_entries<'a>(
+    len: usize,
+    entries: impl IntoIterator<Item = (&'a i32, &'a String)>,
+) -> String {
+    let mut output = String::with_capacity(len.saturating_mul(16) + 2);
+    output.push('[');
+    for (index, (key, value)) in entries.into_iter().enumerate() {
+        if index > 0 {
+            output.push(',');
+        }
+        write!(output, "[{key},").expect("writing to a String cannot fail");
+        write_string(&mut output, value);
+        output.push(']');
+    }
+    output.push(']');
+    output
+}
+
+impl Tree<i32, String> {
+    /// Parses a JSON array of `[key, value]` pairs, as written by
+    /// [`Tree::to_json`], where each key is an integer in the `i32` range and
+    /// each value is a string. Pairs may come in any order, and the last value
+    /// wins for a repeated key.
+    pub fn from_json(json: &str) -> Result<Self, JsonError> {
+        let mut parser = Parser { json, offset: 0 };
+        let entries = parser.document()?;
+        Ok(entries.into_iter().collect())
+    }
+}
+
+/// Escapes `value` the way `JSON.stringify` does: quotes, backslashes, and
+/// control characters are escaped, and everything else is written verbatim.
+fn write_string(output: &mut String, value: &str) {
+    output.push('"');
+    let mut start = 0;
+    for (index, byte) in value.bytes().enumerate() {
+        let escape = match byte {
+            b'"' => "\\\"",
+            b'\\' => "\\\\",
+            b'\x08' => "\\b",
+            b'\t' => "\\t",
+            b'\n' => "\\n",
+            b'\x0c' => "\\f",
+            b'\r' => "\\r",
+            0x00..=0x1f => "",
+            _ => continue,
+        };
+        output.push_str(&value[start..index]);
+        if escape.is_empty() {
+            write!(output, "\\u{byte:04x}").expect("writing to a String cannot fail");
+        } else {
+            output.push_str(escape);
+        }
+        start = index + 1;
+    }
+    output.push_str(&value[start..]);
+    output.push('"');
+}
+
+/// The error returned by [`Tree::from_json`] for malformed input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct JsonError {
+    /// The byte offset in the input where parsing failed.
+    pub offset: usize,
+    /// What the parser expected to find at `offset`.
+    pub expected: &'static str,
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {} at byte {}", self.expected, self.offset)
+    }
+}
+
+impl Error for JsonError {}
+
+/// A recursive-descent parser for the fixed `[[key, "value"], ...]` shape.
+struct Parser<'a> {
+    json: &'a str,
+    offset: usize,
+}
+
+impl Parser<'_> {
+    fn document(&mut self) -> Result<Vec<(i32, String)>, JsonError> {
+        let mut entries = Vec::new();
+        self.token(b'[', "'['")?;
+        if !self.accept(b']') {
+            loop {
+                self.token(b'[', "'[' opening a pair")?;
+                let key = self.key()?;
+                self.token(b',', "',' after the key")?;
+                let value = self.string()?;
+                self.token(b']', "']' closing the pair")?;
+                entries.push((key, value));
+                if self.accept(b']') {
+                    break;
+                }
+                self.token(b',', "',' or ']'")?;
+            }
+        }
+        self.skip_whitespace();
+        if self.offset < self.json.len() {
+            return Err(self.error("the end of the input"));
+        }
+        Ok(entries)
+    }
+
+    fn key(&mut self) -> Result<i32, JsonError> {
+        self.skip_whitespace();
+        let start = self.offset;
+        let negative = self.peek() == Some(b'-');
+        let digits = start + usize::from(negative);
+        let end = self.json.as_bytes()[digits..]
+            .iter()
+            .position(|byte| !byte.is_ascii_digit())
+            .map_or(self.json.len(), |length| digits + length);
+        let leading_zero = end - digits > 1 && self.json.as_bytes()[digits] == b'0';
+        let fraction = matches!(self.json.as_bytes().get(end), Some(b'.' | b'e' | b'E'));
+        if end == digits || leading_zero || fraction {
+            return Err(self.error("an integer key"));
+        }
+        let key = self.json[start..end]
+            .parse()
+            .map_err(|_| self.error("a key in the i32 range"))?;
+        self.offset = end;
+        Ok(key)
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.token(b'"', "a string value")?;
+        let mut value = String::new();
+        loop {
+            let rest = &self.json[self.offset..];
+            let Some(length) = rest.find(|c: char| c == '"' || c == '\\' || c < ' ') else {
+                self.offset = self.json.len();
+                return Err(self.error("'\"' closing the string"));
+            };
+            value.push_str(&rest[..length]);
+            self.offset += length;
+            match self.next() {
+                Some(b'"') => return Ok(value),
+                Some(b'\\') => value.push(self.escape()?),
+                _ => {
+                    self.offset -= 1;
+                    return Err(self.error("an escaped control character"));
+                }
+            }
+        }
+    }
+
+    /// Decodes the escape sequence after a backslash.
+    fn escape(&mut self) -> Result<char, JsonError> {
+        let escaped = match self.next() {
+            Some(b'"') => '"',
+            Some(b'\\') => '\\',
+            Some(b'/') => '/',
+            Some(b'b') => '\x08',
+            Some(b'f') => '\x0c',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'u') => {
+                let unit = self.hex()?;
+                let code = match unit {
+                    0xd800..=0xdbff => {
+                        if !self.json[self.offset..].starts_with("\\u") {
+                            return Err(self.error("a low surrogate escape"));
+                        }
+                        self.offset += 2;
+                        let low = self.hex()?;
+                        if !(0xdc00..=0xdfff).contains(&low) {
+                            self.offset -= 4;
+                            return Err(self.error("a low surrogate escape"));
+                        }
+                        0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00)
+                    }
+                    0xdc00..=0xdfff => {
+                        self.offset -= 4;
+                        return Err(self.error("a high surrogate before a low surrogate"));
+                    }
+                    _ => unit,
+                };
+                char::from_u32(code).expect("surrogates are combined or rejected")
+            }
+            _ => {
+                self.offset = self.offset.saturating_sub(1);
+                return Err(self.error("a valid escape character"));
+            }
+        };
+        Ok(escaped)
+    }
+
+    fn hex(&mut self) -> Result<u32, JsonError> {
+        let digits = self
+            .json
+            .get(self.offset..self.offset + 4)
+            .filter(|digits| digits.bytes().all(|byte| byte.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("four hexadecimal digits"))?;
+        self.offset += 4;
+        Ok(u32::from_str_radix(digits, 16).expect("the digits are hexadecimal"))
+    }
+
+    /// Skips whitespace and consumes `byte`, or fails expecting `expected`.
+    fn token(&mut self, byte: u8, expected: &'static str) -> Result<(), JsonError> {
+        if self.accept(byte) {
+            Ok(())
+        } else {
+            Err(self.error(expected))
+        }
+    }
+
+    /// Skips whitespace and consumes `byte` if it comes next.
+    fn accept(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.peek() == Some(byte);
+        self.offset += usize::from(found);
+        found
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.offset += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.json.as_bytes().get(self.offset).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.offset += 1;
+        Some(byte)
+    }
+
+    fn error(&self, expected: &'static str) -> JsonError {
+        JsonError {
+            offset: self.offset,
+            expected,
+        }
+    }
+}
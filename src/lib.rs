@@ -2,8 +2,21 @@
 
 pub mod tree;
 
-use tree::Tree;
+use std::cell::RefCell;
+#[cfg(not(test))]
+use std::cell::{Ref, RefMut};
+#[cfg(not(test))]
+use std::ops::Bound;
+use std::rc::Rc;
+
+#[cfg(not(test))]
+use tree::{DeserializeError, LogRecord};
+use tree::{History, PersistentTree, Tree, WriteAheadLog};
 
+#[cfg(not(test))]
+use napi::bindgen_prelude::{Buffer, Either, Error, FnArgs, Function, Int32Array, Result, Unknown};
+#[cfg(not(test))]
+use napi::JsValue;
 #[cfg(not(test))]
 use napi_derive::napi;
 
@@ -11,52 +24,971 @@ use napi_derive::napi;
 #[cfg_attr(not(test), napi)]
 #[cfg_attr(test, allow(dead_code))]
 pub struct AVLTree {
-    tree: Tree,
+    /// Borrowed mutably only while a write runs, and never across a call into
+    /// JavaScript, so that a write made from one of this tree's own callbacks
+    /// throws instead of interleaving with the call that ran the callback.
+    state: RefCell<State>,
 }
 
+/// The entries of an `AvlTree` together with its log and history.
+#[cfg_attr(test, allow(dead_code))]
+struct State {
+    /// Shares its nodes with snapshots and live iterators, which read the
+    /// contents as of their creation while writes copy only the paths they
+    /// change.
+    tree: PersistentTree<i32, String>,
+    /// Records every mutation before it is applied, for trees opened with
+    /// `openDurable`.
+    log: Option<WriteAheadLog>,
+    /// Records the value each mutation overwrote, once `enableHistory` is
+    /// called.
+    history: Option<History<i32, String>>,
+}
+
+/// Options accepted by `range` and `countRange` in JavaScript.
+#[cfg_attr(not(test), napi(object))]
+#[cfg_attr(test, allow(dead_code))]
+pub struct RangeOptions {
+    /// Includes entries whose key equals `hi`. Defaults to `false`.
+    pub inclusive: Option<bool>,
+}
+
+/// Shape statistics returned by `stats` in JavaScript.
+#[cfg_attr(not(test), napi(object))]
+#[cfg_attr(test, allow(dead_code))]
+pub struct TreeStats {
+    /// The number of entries, which equals the number of nodes.
+    pub size: u32,
+    /// The number of levels; `0` for an empty tree and `1` for a lone root.
+    pub height: u32,
+    /// The greatest height the AVL balance condition allows for `size` nodes.
+    pub height_bound: u32,
+    /// Approximate native heap bytes held by nodes and value strings.
+    pub heap_bytes: f64,
+    /// The mean number of nodes visited to find a present key.
+    pub average_depth: f64,
+}
+
+/// The `merge(key, value, otherValue)` callback accepted by `union`.
+#[cfg(not(test))]
+type MergeFn<'env> = Function<'env, FnArgs<(i32, String, String)>, String>;
+
+/// The `callback(value, key)` accepted by `update`.
+#[cfg(not(test))]
+type UpdateFn<'env> = Function<'env, FnArgs<(Option<&'env str>, i32)>, String>;
+
+/// The `callback(transaction)` accepted by `transaction`.
+#[cfg(not(test))]
+type TransactionFn<'env> = Function<'env, AVLTreeTransaction, Unknown<'env>>;
+
+/// Peeks at the changes the next undo or redo restores.
+#[cfg(not(test))]
+type ChangesFn = fn(&History<i32, String>) -> Option<&[(i32, Option<String>)]>;
+
+/// Takes the next undo or redo step.
+#[cfg(not(test))]
+type StepFn = fn(&mut History<i32, String>, &mut PersistentTree<i32, String>) -> bool;
+
 #[cfg(not(test))]
 #[napi]
 impl AVLTree {
     /// Creates an empty tree in constant time.
     #[napi(constructor)]
     pub fn new() -> Self {
-        Self { tree: Tree::new() }
+        Self::wrap(PersistentTree::new())
+    }
+
+    /// Creates a tree from `[key, value]` pairs in any order, keeping the last
+    /// value for a repeated key.
+    ///
+    /// Runs in `O(n)` time when the pairs are already in ascending key order
+    /// and `O(n log n)` otherwise, without rebalancing rotations.
+    #[napi(factory)]
+    pub fn from_entries(entries: Vec<(i32, String)>) -> Self {
+        Self::wrap(entries.into_iter().collect())
+    }
+
+    /// Reads a tree from bytes produced by `serialize`, throwing an error that
+    /// describes truncated, corrupt, or unsupported input.
+    ///
+    /// Runs in `O(n)` time.
+    #[napi(factory)]
+    pub fn deserialize(bytes: &[u8]) -> Result<Self> {
+        let mut reader = bytes;
+        Tree::deserialize_from(&mut reader)
+            .and_then(|tree| match reader.len() {
+                0 => Ok(Self::wrap(tree.into())),
+                length => Err(DeserializeError::TrailingData {
+                    length: length as u64,
+                }),
+            })
+            .map_err(|error| Error::from_reason(format!("cannot deserialize AvlTree: {error}")))
+    }
+
+    /// Reads a tree from a file written by `saveToFile`, throwing an error that
+    /// describes a missing, truncated, corrupt, or unsupported file.
+    ///
+    /// Runs in `O(n)` time.
+    #[napi(factory)]
+    pub fn load_from_file(path: String) -> Result<Self> {
+        Tree::load(&path)
+            .map(|tree| Self::wrap(tree.into()))
+            .map_err(|error| {
+                Error::from_reason(format!("cannot load AvlTree from {path}: {error}"))
+            })
+    }
+
+    /// Opens the durable tree stored in `directory`, creating it when missing.
+    /// The tree is rebuilt from the last snapshot plus a replay of the
+    /// write-ahead log, ignoring a final record torn by a crash. Every later
+    /// mutation is appended to the log and synced to disk before it is
+    /// applied.
+    ///
+    /// Runs in `O(n + r log n)` time for `r` logged records.
+    #[napi(factory)]
+    pub fn open_durable(directory: String) -> Result<Self> {
+        let (tree, log) = WriteAheadLog::open(&directory).map_err(|error| {
+            Error::from_reason(format!(
+                "cannot open durable AvlTree in {directory}: {error}"
+            ))
+        })?;
+        Ok(Self {
+            state: RefCell::new(State {
+                tree: tree.into(),
+                log: Some(log),
+                history: None,
+            }),
+        })
     }
 
     /// Inserts a key/value pair, replacing the value when the key already exists.
     ///
     /// Runs in `O(log n)` time.
     #[napi]
-    pub fn insert(&mut self, key: i32, value: String) {
-        self.tree.insert(key, value);
+    pub fn insert(&self, key: i32, value: String) -> Result<()> {
+        let mut state = self.state_mut()?;
+        state.record(&[LogRecord::Insert { key, value: &value }])?;
+        let replaced = state.tree.insert(key, value);
+        state.remember(key, replaced.as_deref());
+        Ok(())
+    }
+
+    /// Inserts a key/value pair and returns the value it replaced, or `null` in
+    /// JavaScript when the key was absent.
+    ///
+    /// Runs in `O(log n)` time.
+    #[napi]
+    pub fn set(&self, key: i32, value: String) -> Result<Option<String>> {
+        let mut state = self.state_mut()?;
+        state.record(&[LogRecord::Insert { key, value: &value }])?;
+        let replaced = state.tree.insert(key, value);
+        state.remember(key, replaced.as_deref());
+        Ok(replaced)
     }
 
     /// Returns the value for `key`, or `null` in JavaScript when absent.
     ///
     /// Runs in `O(log n)` time.
     #[napi]
-    pub fn find(&self, key: i32) -> Option<&str> {
-        self.tree.find(key)
+    pub fn find(&self, key: i32) -> Option<String> {
+        self.state().tree.find(&key).cloned()
     }
 
     /// Removes `key` and returns its value, or `null` in JavaScript when absent.
     ///
     /// Runs in `O(log n)` time.
     #[napi]
-    pub fn remove(&mut self, key: i32) -> Option<String> {
-        self.tree.remove(key)
+    pub fn remove(&self, key: i32) -> Result<Option<String>> {
+        let mut state = self.state_mut()?;
+        if state.log.is_some() && state.tree.has(&key) {
+            state.record(&[LogRecord::Remove { key }])?;
+        }
+        let removed = state.tree.remove(&key);
+        if removed.is_some() {
+            state.remember(key, removed.as_deref());
+        }
+        Ok(removed)
     }
 
     /// Reports whether `key` exists in `O(log n)` time.
     #[napi]
     pub fn has(&self, key: i32) -> bool {
-        self.tree.has(key)
+        self.state().tree.has(&key)
+    }
+
+    /// The number of entries, read in constant time.
+    #[napi(getter)]
+    pub fn size(&self) -> u32 {
+        position(self.state().tree.len())
+    }
+
+    /// Reports whether the tree has no entries in constant time.
+    #[napi]
+    pub fn is_empty(&self) -> bool {
+        self.state().tree.is_empty()
+    }
+
+    /// Returns the number of levels in constant time: `0` for an empty tree
+    /// and `1` for a lone root.
+    #[napi]
+    pub fn height(&self) -> u32 {
+        position(self.state().tree.height())
+    }
+
+    /// Returns the size, height, AVL height bound, approximate heap bytes,
+    /// and average search depth of the tree. Nodes and values shared with
+    /// snapshots and live iterators are counted in full.
+    ///
+    /// Runs in `O(n)` time.
+    #[napi]
+    pub fn stats(&self) -> TreeStats {
+        let state = self.state();
+        let stats = state.tree.stats();
+        let value_bytes: usize = state.tree.iter().map(|(_, value)| value.capacity()).sum();
+        TreeStats {
+            size: position(stats.len),
+            height: position(stats.height),
+            height_bound: position(stats.height_bound),
+            heap_bytes: (stats.node_bytes + value_bytes) as f64,
+            average_depth: stats.average_depth,
+        }
+    }
+
+    /// Checks the ordering, stored heights, balance factors, subtree sizes, and
+    /// entry count of the tree, throwing an error that names the first broken
+    /// invariant and the key where it was found.
+    ///
+    /// Runs in `O(n)` time.
+    #[napi]
+    pub fn validate(&self) -> Result<()> {
+        self.state()
+            .tree
+            .validate()
+            .map_err(|violation| Error::from_reason(format!("invalid AVL tree: {violation}")))
+    }
+
+    /// Stores `callback(value, key)` under `key` and returns it, where `value` is
+    /// the current value or `null` in JavaScript when absent. The callback may
+    /// read the tree, but writing to it from the callback throws, and an
+    /// exception the callback throws propagates without changing the tree.
+    ///
+    /// Runs in `O(log n)` time plus the callback.
+    #[napi(ts_args_type = "key: number, callback: (value: string | null, key: number) => string")]
+    pub fn update(&self, key: i32, callback: UpdateFn) -> Result<String> {
+        let value = {
+            let state = self.state();
+            let current = state.tree.find(&key).map(String::as_str);
+            callback.call((current, key).into())?
+        };
+        let mut state = self.state_mut()?;
+        state.record(&[LogRecord::Insert { key, value: &value }])?;
+        let replaced = state.tree.insert(key, value.clone());
+        state.remember(key, replaced.as_deref());
+        Ok(value)
+    }
+
+    /// Returns the value for `key`, first inserting `value` when the key is
+    /// absent.
+    ///
+    /// Runs in `O(log n)` time.
+    #[napi]
+    pub fn get_or_insert(&self, key: i32, value: String) -> Result<String> {
+        let mut state = self.state_mut()?;
+        if let Some(current) = state.tree.find(&key) {
+            return Ok(current.clone());
+        }
+        state.record(&[LogRecord::Insert { key, value: &value }])?;
+        state.remember(key, None);
+        state.tree.insert(key, value.clone());
+        Ok(value)
+    }
+
+    /// Removes every entry. Nodes that no snapshot or live iterator shares are
+    /// freed in `O(n)` time.
+    #[napi]
+    pub fn clear(&self) -> Result<()> {
+        let mut state = self.state_mut()?;
+        let state = &mut *state;
+        state.record(&[LogRecord::Clear])?;
+        if let Some(history) = &mut state.history {
+            for (&key, value) in state.tree.iter() {
+                history.record(key, Some(value.clone()));
+            }
+        }
+        state.tree.clear();
+        Ok(())
+    }
+
+    /// Removes and returns the `[key, value]` pair with the smallest key, or
+    /// `null` in JavaScript when the tree is empty.
+    ///
+    /// Runs in `O(log n)` time.
+    #[napi]
+    pub fn pop_first(&self) -> Result<Option<(i32, String)>> {
+        let mut state = self.state_mut()?;
+        if let (Some(_), Some((&key, _))) = (&state.log, state.tree.first()) {
+            state.record(&[LogRecord::Remove { key }])?;
+        }
+        let first = state.tree.pop_first();
+        if let Some((key, value)) = &first {
+            state.remember(*key, Some(value));
+        }
+        Ok(first)
+    }
+
+    /// Removes and returns the `[key, value]` pair with the largest key, or
+    /// `null` in JavaScript when the tree is empty.
+    ///
+    /// Runs in `O(log n)` time.
+    #[napi]
+    pub fn pop_last(&self) -> Result<Option<(i32, String)>> {
+        let mut state = self.state_mut()?;
+        if let (Some(_), Some((&key, _))) = (&state.log, state.tree.last()) {
+            state.record(&[LogRecord::Remove { key }])?;
+        }
+        let last = state.tree.pop_last();
+        if let Some((key, value)) = &last {
+            state.remember(*key, Some(value));
+        }
+        Ok(last)
+    }
+
+    /// Keeps only the entries for which `callback(value, key)` returns a truthy
+    /// value. The callback runs in ascending key order and may read the tree,
+    /// but writing to it from the callback throws. An exception the callback
+    /// throws propagates without removing anything.
+    ///
+    /// Runs in `O(n + k log n)` time for `k` removed entries, plus the
+    /// callbacks.
+    #[napi(ts_args_type = "callback: (value: string, key: number) => unknown")]
+    pub fn retain(&self, callback: Function<FnArgs<(&str, i32)>, Unknown>) -> Result<()> {
+        let mut rejected = Vec::new();
+        let state = self.state();
+        for (&key, value) in state.tree.iter() {
+            if !callback
+                .call((value.as_str(), key).into())?
+                .coerce_to_bool()?
+            {
+                rejected.push(key);
+            }
+        }
+        drop(state);
+
+        let mut state = self.state_mut()?;
+        if state.log.is_some() {
+            let records: Vec<_> = rejected
+                .iter()
+                .map(|&key| LogRecord::Remove { key })
+                .collect();
+            state.record(&records)?;
+        }
+        for key in rejected {
+            let removed = state.tree.remove(&key);
+            state.remember(key, removed.as_deref());
+        }
+        Ok(())
+    }
+
+    /// Removes the entries that `range` would return for the same arguments
+    /// and returns them as `[key, value]` pairs in ascending key order.
+    ///
+    /// Runs in `O(k log n)` time for `k` removed pairs.
+    #[napi]
+    pub fn drain_range(
+        &self,
+        lo: Option<i32>,
+        hi: Option<i32>,
+        options: Option<RangeOptions>,
+    ) -> Result<Vec<(i32, String)>> {
+        let mut state = self.state_mut()?;
+        let keys: Vec<_> = state
+            .tree
+            .range(bounds(lo, hi, options))
+            .map(|(&key, _)| key)
+            .collect();
+        if state.log.is_some() {
+            let records: Vec<_> = keys.iter().map(|&key| LogRecord::Remove { key }).collect();
+            state.record(&records)?;
+        }
+        let mut drained = Vec::with_capacity(keys.len());
+        for key in keys {
+            let value = state.tree.remove(&key).expect("the range holds the key");
+            state.remember(key, Some(&value));
+            drained.push((key, value));
+        }
+        Ok(drained)
+    }
+
+    /// Inserts every `[key, value]` pair in order, so the last value wins for a
+    /// repeated key. The whole batch crosses the Node-API boundary once and is
+    /// converted before any pair is inserted, so an invalid pair leaves the
+    /// tree unchanged.
+    ///
+    /// Runs in `O(m log(n + m))` time for `m` pairs.
+    #[napi]
+    pub fn insert_many(&self, entries: Vec<(i32, String)>) -> Result<()> {
+        let mut state = self.state_mut()?;
+        if state.log.is_some() {
+            let records: Vec<_> = entries
+                .iter()
+                .map(|(key, value)| LogRecord::Insert { key: *key, value })
+                .collect();
+            state.record(&records)?;
+        }
+        for (key, value) in entries {
+            let replaced = state.tree.insert(key, value);
+            state.remember(key, replaced.as_deref());
+        }
+        Ok(())
+    }
+
+    /// Returns the value for each key in `keys`, with `null` for absent keys.
+    ///
+    /// Runs in `O(m log n)` time for `m` keys.
+    #[napi]
+    pub fn find_many(&self, keys: Either<Int32Array, Vec<i32>>) -> Vec<Option<String>> {
+        key_batch(&keys)
+            .iter()
+            .map(|key| self.state().tree.find(key).cloned())
+            .collect()
+    }
+
+    /// Reports whether each key in `keys` exists.
+    ///
+    /// Runs in `O(m log n)` time for `m` keys.
+    #[napi]
+    pub fn has_many(&self, keys: Either<Int32Array, Vec<i32>>) -> Vec<bool> {
+        key_batch(&keys)
+            .iter()
+            .map(|key| self.state().tree.has(key))
+            .collect()
+    }
+
+    /// Removes each key in `keys` in order and returns the removed values, with
+    /// `null` for keys that were absent or already removed earlier in the batch.
+    ///
+    /// Runs in `O(m log n)` time for `m` keys.
+    #[napi]
+    pub fn remove_many(&self, keys: Either<Int32Array, Vec<i32>>) -> Result<Vec<Option<String>>> {
+        let mut state = self.state_mut()?;
+        let keys = key_batch(&keys);
+        if state.log.is_some() {
+            let records: Vec<_> = keys
+                .iter()
+                .filter(|key| state.tree.has(key))
+                .map(|&key| LogRecord::Remove { key })
+                .collect();
+            state.record(&records)?;
+        }
+        let removed: Vec<_> = keys.iter().map(|key| state.tree.remove(key)).collect();
+        for (&key, value) in keys.iter().zip(&removed) {
+            if value.is_some() {
+                state.remember(key, value.as_deref());
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Calls `callback(transaction)` and then applies the inserts and removals
+    /// it staged on `transaction` all at once, returning what the callback
+    /// returned. Reads through `transaction` see the staged changes over the
+    /// tree as it was when `transaction` was called. The callback may read the
+    /// tree directly, but writing to it other than through `transaction`
+    /// throws. When the callback throws, the exception propagates and nothing
+    /// staged is applied, leaving the tree exactly as it was. A durable tree logs the staged changes in one
+    /// append, so a crash keeps either all of them or none.
+    ///
+    /// The callback must be synchronous, since the transaction ends when it
+    /// returns. A callback that returns a `Promise`, as an `async` function
+    /// does, makes `transaction` throw without applying anything.
+    ///
+    /// Runs in `O(m log(n + m))` time for `m` staged keys, plus the callback.
+    #[napi(ts_args_type = "callback: (transaction: AvlTreeTransaction) => unknown")]
+    pub fn transaction<'env>(&self, callback: TransactionFn<'env>) -> Result<Unknown<'env>> {
+        let state = self.state();
+        let staging = Rc::new(RefCell::new(Some(Staging {
+            base: state.tree.snapshot(),
+            staged: Tree::new(),
+            len: state.tree.len(),
+        })));
+        let result = callback.call(AVLTreeTransaction {
+            staging: Rc::clone(&staging),
+        });
+        drop(state);
+        let Some(Staging { staged, .. }) = staging.take() else {
+            unreachable!("only the tree ends a transaction");
+        };
+        let result = result?;
+        if result.is_promise()? {
+            return Err(Error::from_reason(
+                "an AvlTree transaction callback must be synchronous, but it returned a Promise; \
+                 nothing was applied",
+            ));
+        }
+
+        let mut state = self.state_mut()?;
+        if state.log.is_some() {
+            let records: Vec<_> = staged
+                .iter()
+                .filter_map(|(&key, value)| match value {
+                    Some(value) => Some(LogRecord::Insert { key, value }),
+                    None => state.tree.has(&key).then_some(LogRecord::Remove { key }),
+                })
+                .collect();
+            state.record(&records)?;
+        }
+        for (key, value) in staged {
+            let previous = match value {
+                Some(value) => state.tree.insert(key, value),
+                None if state.tree.has(&key) => state.tree.remove(&key),
+                None => continue,
+            };
+            state.remember(key, previous.as_deref());
+        }
+        Ok(result)
+    }
+
+    /// Returns the `[key, value]` pair with the smallest key, or `null` in
+    /// JavaScript when the tree is empty.
+    ///
+    /// Runs in `O(log n)` time.
+    #[napi]
+    pub fn first(&self) -> Option<(i32, String)> {
+        self.state().tree.first().map(entry)
+    }
+
+    /// Returns the `[key, value]` pair with the largest key, or `null` in
+    /// JavaScript when the tree is empty.
+    ///
+    /// Runs in `O(log n)` time.
+    #[napi]
+    pub fn last(&self) -> Option<(i32, String)> {
+        self.state().tree.last().map(entry)
+    }
+
+    /// Returns the `[key, value]` pair with the largest key less than or equal
+    /// to `key`, or `null` in JavaScript when absent.
+    ///
+    /// Runs in `O(log n)` time.
+    #[napi]
+    pub fn floor(&self, key: i32) -> Option<(i32, String)> {
+        self.state().tree.floor(&key).map(entry)
+    }
+
+    /// Returns the `[key, value]` pair with the smallest key greater than or
+    /// equal to `key`, or `null` in JavaScript when absent.
+    ///
+    /// Runs in `O(log n)` time.
+    #[napi]
+    pub fn ceiling(&self, key: i32) -> Option<(i32, String)> {
+        self.state().tree.ceiling(&key).map(entry)
+    }
+
+    /// Returns the `[key, value]` pair with the largest key strictly less than
+    /// `key`, or `null` in JavaScript when absent.
+    ///
+    /// Runs in `O(log n)` time.
+    #[napi]
+    pub fn predecessor(&self, key: i32) -> Option<(i32, String)> {
+        self.state().tree.predecessor(&key).map(entry)
+    }
+
+    /// Returns the `[key, value]` pair with the smallest key strictly greater
+    /// than `key`, or `null` in JavaScript when absent.
+    ///
+    /// Runs in `O(log n)` time.
+    #[napi]
+    pub fn successor(&self, key: i32) -> Option<(i32, String)> {
+        self.state().tree.successor(&key).map(entry)
+    }
+
+    /// Returns the zero-based position of `key` in ascending key order, or
+    /// `null` in JavaScript when absent.
+    ///
+    /// Runs in `O(log n)` time.
+    #[napi]
+    pub fn rank(&self, key: i32) -> Option<u32> {
+        self.state().tree.rank(&key).map(position)
+    }
+
+    /// Returns the `[key, value]` pair at zero-based `index` in ascending key
+    /// order, or `null` in JavaScript when `index` is out of range.
+    ///
+    /// Runs in `O(log n)` time.
+    #[napi]
+    pub fn select(&self, index: u32) -> Option<(i32, String)> {
+        self.state().tree.select(index as usize).map(entry)
+    }
+
+    /// Counts the keys strictly less than `key`, which need not be present.
+    ///
+    /// Runs in `O(log n)` time.
+    #[napi]
+    pub fn count_less_than(&self, key: i32) -> u32 {
+        position(self.state().tree.count_less_than(&key))
+    }
+
+    /// Returns the `[key, value]` pairs with keys from `lo` up to `hi` in
+    /// ascending key order. `lo` is inclusive and `hi` is exclusive unless
+    /// `options.inclusive` is `true`; a `null` or omitted bound is unbounded.
+    ///
+    /// Runs in `O(log n + k)` time for `k` returned pairs.
+    #[napi]
+    pub fn range(
+        &self,
+        lo: Option<i32>,
+        hi: Option<i32>,
+        options: Option<RangeOptions>,
+    ) -> Vec<(i32, String)> {
+        self.state()
+            .tree
+            .range(bounds(lo, hi, options))
+            .map(entry)
+            .collect()
+    }
+
+    /// Counts the keys that `range` would return for the same arguments
+    /// without materializing them.
+    ///
+    /// Runs in `O(log n)` time.
+    #[napi]
+    pub fn count_range(
+        &self,
+        lo: Option<i32>,
+        hi: Option<i32>,
+        options: Option<RangeOptions>,
+    ) -> u32 {
+        position(self.state().tree.count_range(bounds(lo, hi, options)))
+    }
+
+    /// Returns a new tree holding every key of this tree or `other`. For a key
+    /// in both, `merge(key, value, otherValue)` picks the stored value; without
+    /// `merge` the value from `other` wins. Neither input tree changes, and an
+    /// exception thrown by `merge` propagates without producing a tree.
+    ///
+    /// Runs in `O(m log(n / m + 1))` time for sizes `m <= n`, joining
+    /// snapshots of both trees so that untouched subtrees stay shared.
+    #[napi(
+        ts_args_type = "other: AvlTree, merge?: (key: number, value: string, otherValue: string) => string"
+    )]
+    pub fn union(&self, other: &AVLTree, merge: Option<MergeFn>) -> Result<AVLTree> {
+        let mut failure = None;
+        // Snapshots let `merge` write to either tree without changing the
+        // inputs of the running union.
+        let (tree, other) = (self.state().tree.snapshot(), other.state().tree.snapshot());
+        let tree = tree.union(other, |&key, value, other_value| {
+            let Some(merge) = &merge else {
+                return other_value;
+            };
+            if failure.is_some() {
+                return value;
+            }
+            merge
+                .call((key, value, other_value).into())
+                .unwrap_or_else(|error| {
+                    failure = Some(error);
+                    String::new()
+                })
+        });
+        match failure {
+            Some(error) => Err(error),
+            None => Ok(AVLTree::wrap(tree)),
+        }
+    }
+
+    /// Returns a new tree with the entries of this tree whose keys also occur
+    /// in `other`.
+    ///
+    /// Runs in `O(m log(n / m + 1))` time for sizes `m <= n`.
+    #[napi]
+    pub fn intersection(&self, other: &AVLTree) -> AVLTree {
+        let tree = self.state().tree.snapshot();
+        AVLTree::wrap(tree.intersection(other.state().tree.snapshot()))
+    }
+
+    /// Returns a new tree with the entries of this tree whose keys do not
+    /// occur in `other`.
+    ///
+    /// Runs in `O(m log(n / m + 1))` time for sizes `m <= n`.
+    #[napi]
+    pub fn difference(&self, other: &AVLTree) -> AVLTree {
+        let tree = self.state().tree.snapshot();
+        AVLTree::wrap(tree.difference(other.state().tree.snapshot()))
+    }
+
+    /// Returns a new tree with the entries whose keys occur in exactly one of
+    /// this tree and `other`.
+    ///
+    /// Runs in `O(m log(n / m + 1))` time for sizes `m <= n`.
+    #[napi]
+    pub fn symmetric_difference(&self, other: &AVLTree) -> AVLTree {
+        let tree = self.state().tree.snapshot();
+        AVLTree::wrap(tree.symmetric_difference(other.state().tree.snapshot()))
+    }
+
+    /// Returns a read-only view of the entries present now, which later writes
+    /// to this tree do not affect.
+    ///
+    /// Runs in `O(1)` time. The snapshot shares its nodes with the tree, and
+    /// each later write copies only the `O(log n)` nodes it changes.
+    #[napi]
+    pub fn snapshot(&self) -> AVLTreeSnapshot {
+        AVLTreeSnapshot {
+            tree: self.state().tree.snapshot(),
+        }
+    }
+
+    /// Returns an iterator over `[key, value]` pairs in ascending key order.
+    /// The iterator reads the entries present when it was created; later
+    /// writes to the tree do not affect it.
+    ///
+    /// Creating the iterator takes `O(1)` time and each step `O(log n)`. Like a
+    /// snapshot, the iterator shares its nodes with the tree, and each later
+    /// write copies only the `O(log n)` nodes it changes.
+    #[napi]
+    pub fn entries(&self) -> AVLTreeEntries {
+        AVLTreeEntries {
+            cursor: Cursor::new(&self.state().tree),
+        }
+    }
+
+    /// Returns an iterator over the keys in ascending order, with the same
+    /// snapshot behaviour as `entries()`.
+    #[napi]
+    pub fn keys(&self) -> AVLTreeKeys {
+        AVLTreeKeys {
+            cursor: Cursor::new(&self.state().tree),
+        }
+    }
+
+    /// Returns an iterator over the values in ascending key order, with the
+    /// same snapshot behaviour as `entries()`.
+    #[napi]
+    pub fn values(&self) -> AVLTreeValues {
+        AVLTreeValues {
+            cursor: Cursor::new(&self.state().tree),
+        }
+    }
+
+    /// Calls `callback(value, key)` for each entry in ascending key order.
+    /// Writes made by the callback do not change which entries are visited,
+    /// and an exception thrown by the callback stops the walk and propagates.
+    ///
+    /// Runs in `O(n)` time plus the callbacks.
+    #[napi(ts_args_type = "callback: (value: string, key: number) => void")]
+    pub fn for_each(&self, callback: Function<FnArgs<(&str, i32)>, Unknown>) -> Result<()> {
+        let snapshot = self.state().tree.snapshot();
+        for (&key, value) in snapshot.iter() {
+            callback.call((value.as_str(), key).into())?;
+        }
+        Ok(())
+    }
+
+    /// Returns every `[key, value]` pair in ascending key order in `O(n)` time.
+    #[napi]
+    pub fn to_array(&self) -> Vec<(i32, String)> {
+        self.state().tree.iter().map(entry).collect()
+    }
+
+    /// Encodes the tree in the versioned, checksummed binary format that
+    /// `AvlTree.deserialize` reads.
+    ///
+    /// Runs in `O(n)` time.
+    #[napi]
+    pub fn serialize(&self) -> Result<Buffer> {
+        let mut bytes = Vec::new();
+        self.state()
+            .tree
+            .serialize_into(&mut bytes)
+            .map_err(|error| Error::from_reason(error.to_string()))?;
+        Ok(bytes.into())
+    }
+
+    /// Writes the tree to `path` in the `serialize` format, atomically
+    /// replacing any existing file: the bytes are synced to a temporary file
+    /// beside it, which is then renamed over `path`. A crash mid-save leaves
+    /// either the previous file or the complete new one.
+    ///
+    /// Runs in `O(n)` time.
+    #[napi]
+    pub fn save_to_file(&self, path: String) -> Result<()> {
+        self.state()
+            .tree
+            .save(&path)
+            .map_err(|error| Error::from_reason(format!("cannot save AvlTree to {path}: {error}")))
+    }
+
+    /// Writes a fresh snapshot of a tree opened with `openDurable` and empties
+    /// its write-ahead log, throwing an error for any other tree.
+    ///
+    /// Runs in `O(n)` time.
+    #[napi]
+    pub fn compact(&self) -> Result<()> {
+        let mut state = self.state_mut()?;
+        let state = &mut *state;
+        let log = state.log.as_mut().ok_or_else(|| {
+            Error::from_reason("compact requires an AvlTree opened with AvlTree.openDurable")
+        })?;
+        log.compact_persistent(&state.tree)
+            .map_err(|error| Error::from_reason(format!("cannot compact AvlTree: {error}")))
+    }
+
+    /// Starts recording the value each later mutation overwrites, so that
+    /// `undo` and `redo` can revert and reapply changes. At most `depth` steps
+    /// and uncheckpointed changes are kept, each counting as one, dropping the
+    /// oldest first. Any earlier history is discarded.
+    #[napi]
+    pub fn enable_history(&self, depth: u32) -> Result<()> {
+        self.state_mut()?.history = Some(History::new(depth as usize));
+        Ok(())
+    }
+
+    /// Stops recording mutations and discards the history.
+    #[napi]
+    pub fn disable_history(&self) -> Result<()> {
+        self.state_mut()?.history = None;
+        Ok(())
+    }
+
+    /// Groups the changes made since the previous checkpoint into one undo
+    /// step named `label`. Does nothing when there are none.
+    #[napi]
+    pub fn checkpoint(&self, label: String) -> Result<()> {
+        let mut state = self.state_mut()?;
+        state
+            .history
+            .as_mut()
+            .ok_or_else(|| no_history("checkpoint"))?
+            .checkpoint(label);
+        Ok(())
+    }
+
+    /// Reverts the changes made since the last checkpoint or, when there are
+    /// none, the last checkpointed step, and reports whether anything was
+    /// undone. A durable tree logs the reverted values first.
+    ///
+    /// Runs in `O(m log n)` time for `m` changes in the step.
+    #[napi]
+    pub fn undo(&self) -> Result<bool> {
+        let mut state = self.state_mut()?;
+        state.step_history("undo", History::undo_changes, History::undo)
+    }
+
+    /// Reapplies the step most recently undone and reports whether there was
+    /// one. Any mutation other than `undo` and `redo` discards the steps
+    /// available to redo.
+    ///
+    /// Runs in `O(m log n)` time for `m` changes in the step.
+    #[napi]
+    pub fn redo(&self) -> Result<bool> {
+        let mut state = self.state_mut()?;
+        state.step_history("redo", History::redo_changes, History::redo)
+    }
+
+    /// Returns the label of the step `undo` would revert, or `null` in
+    /// JavaScript for unlabelled changes, an empty history, or a tree without
+    /// history.
+    #[napi]
+    pub fn undo_label(&self) -> Option<String> {
+        self.state()
+            .history
+            .as_ref()?
+            .undo_label()
+            .map(str::to_owned)
+    }
+
+    /// Returns the label of the step `redo` would reapply, or `null` in
+    /// JavaScript when there is none.
+    #[napi]
+    pub fn redo_label(&self) -> Option<String> {
+        self.state()
+            .history
+            .as_ref()?
+            .redo_label()
+            .map(str::to_owned)
+    }
+
+    /// Returns the entries as a JSON array of `[key, value]` pairs in ascending
+    /// key order, escaped exactly as `JSON.stringify` would escape them.
+    ///
+    /// Runs in `O(n)` time.
+    #[napi]
+    pub fn dump_json(&self) -> String {
+        self.state().tree.to_json()
     }
 
     /// Returns the legacy in-order debug representation in `O(n)` time.
     #[napi]
     pub fn dump(&self) -> String {
-        self.tree.dump()
+        self.state().tree.dump()
+    }
+}
+
+#[cfg(not(test))]
+impl AVLTree {
+    fn wrap(tree: PersistentTree<i32, String>) -> Self {
+        Self {
+            state: RefCell::new(State {
+                tree,
+                log: None,
+                history: None,
+            }),
+        }
+    }
+
+    /// Borrows the state for reading. Writes never hold their borrow while
+    /// JavaScript runs, so this cannot conflict with one.
+    fn state(&self) -> Ref<'_, State> {
+        self.state.borrow()
+    }
+
+    /// Borrows the state for a write, throwing while a callback passed to one
+    /// of this tree's methods is running.
+    fn state_mut(&self) -> Result<RefMut<'_, State>> {
+        self.state.try_borrow_mut().map_err(|_| {
+            Error::from_reason("cannot modify an AvlTree while one of its callbacks is running")
+        })
+    }
+}
+
+#[cfg(not(test))]
+impl State {
+    /// Appends `records` to the write-ahead log of a durable tree, which must
+    /// happen before they are applied. Does nothing for other trees.
+    fn record(&mut self, records: &[LogRecord<'_>]) -> Result<()> {
+        match &mut self.log {
+            Some(log) => append(log, records),
+            None => Ok(()),
+        }
+    }
+
+    /// Logs the values the next undo or redo restores, in the order `step`
+    /// applies them, and then takes the step.
+    fn step_history(&mut self, method: &str, changes: ChangesFn, step: StepFn) -> Result<bool> {
+        let history = self.history.as_mut().ok_or_else(|| no_history(method))?;
+        let Some(changes) = changes(history) else {
+            return Ok(false);
+        };
+        if let Some(log) = &mut self.log {
+            let records: Vec<_> = changes
+                .iter()
+                .rev()
+                .map(|(key, previous)| match previous {
+                    Some(value) => LogRecord::Insert { key: *key, value },
+                    None => LogRecord::Remove { key: *key },
+                })
+                .collect();
+            append(log, &records)?;
+        }
+        Ok(step(history, &mut self.tree))
+    }
+
+    /// Records in the undo history, when enabled, that `key` held `previous`
+    /// before a write.
+    fn remember(&mut self, key: i32, previous: Option<&str>) {
+        if let Some(history) = &mut self.history {
+            history.record(key, previous.map(str::to_owned));
+        }
     }
 }
 
@@ -66,3 +998,343 @@ impl Default for AVLTree {
         Self::new()
     }
 }
+
+/// A read-only view returned by `AvlTree.snapshot()`, holding the entries of
+/// the source tree at that moment however the source changes afterwards.
+#[cfg_attr(not(test), napi)]
+#[cfg_attr(test, allow(dead_code))]
+pub struct AVLTreeSnapshot {
+    tree: PersistentTree<i32, String>,
+}
+
+#[cfg(not(test))]
+#[napi]
+impl AVLTreeSnapshot {
+    /// The number of entries, read in constant time.
+    #[napi(getter)]
+    pub fn size(&self) -> u32 {
+        position(self.tree.len())
+    }
+
+    /// Returns the value for `key`, or `null` in JavaScript when absent.
+    ///
+    /// Runs in `O(log n)` time.
+    #[napi]
+    pub fn find(&self, key: i32) -> Option<&str> {
+        self.tree.find(&key).map(String::as_str)
+    }
+
+    /// Reports whether `key` exists in `O(log n)` time.
+    #[napi]
+    pub fn has(&self, key: i32) -> bool {
+        self.tree.has(&key)
+    }
+
+    /// Returns the `[key, value]` pairs in a key range, with the same
+    /// arguments as `AvlTree.range`.
+    ///
+    /// Runs in `O(log n + k)` time for `k` returned pairs.
+    #[napi]
+    pub fn range(
+        &self,
+        lo: Option<i32>,
+        hi: Option<i32>,
+        options: Option<RangeOptions>,
+    ) -> Vec<(i32, String)> {
+        self.tree
+            .range(bounds(lo, hi, options))
+            .map(entry)
+            .collect()
+    }
+
+    /// Returns an iterator over `[key, value]` pairs in ascending key order.
+    ///
+    /// Each step runs in `O(log n)` time.
+    #[napi]
+    pub fn entries(&self) -> AVLTreeEntries {
+        AVLTreeEntries {
+            cursor: Cursor::new(&self.tree),
+        }
+    }
+
+    /// Returns an iterator over the keys in ascending order.
+    #[napi]
+    pub fn keys(&self) -> AVLTreeKeys {
+        AVLTreeKeys {
+            cursor: Cursor::new(&self.tree),
+        }
+    }
+
+    /// Returns an iterator over the values in ascending key order.
+    #[napi]
+    pub fn values(&self) -> AVLTreeValues {
+        AVLTreeValues {
+            cursor: Cursor::new(&self.tree),
+        }
+    }
+}
+
+/// The changes staged by an `AvlTree.transaction` callback.
+#[cfg_attr(test, allow(dead_code))]
+struct Staging {
+    /// The tree as of the start of the transaction, which reads fall back to.
+    base: PersistentTree<i32, String>,
+    /// The staged value of each touched key, with `None` marking a removal.
+    staged: Tree<i32, Option<String>>,
+    len: usize,
+}
+
+#[cfg_attr(test, allow(dead_code))]
+impl Staging {
+    fn find(&self, key: i32) -> Option<&String> {
+        match self.staged.find(&key) {
+            Some(staged) => staged.as_ref(),
+            None => self.base.find(&key),
+        }
+    }
+
+    /// Stages `value` for `key` and returns the value it hides.
+    fn stage(&mut self, key: i32, value: Option<String>) -> Option<String> {
+        let previous = self.find(key).cloned();
+        self.len = self.len - usize::from(previous.is_some()) + usize::from(value.is_some());
+        self.staged.insert(key, value);
+        previous
+    }
+}
+
+/// The handle passed to an `AvlTree.transaction` callback for staging
+/// changes, which throws an error once the callback has returned.
+#[cfg_attr(not(test), napi)]
+#[cfg_attr(test, allow(dead_code))]
+pub struct AVLTreeTransaction {
+    staging: Rc<RefCell<Option<Staging>>>,
+}
+
+#[cfg(not(test))]
+#[napi]
+impl AVLTreeTransaction {
+    /// Stages an insert of `value` under `key`.
+    ///
+    /// Runs in `O(log n + log m)` time for `m` staged keys.
+    #[napi]
+    pub fn insert(&mut self, key: i32, value: String) -> Result<()> {
+        self.with_staging(|staging| {
+            staging.stage(key, Some(value));
+        })
+    }
+
+    /// Stages the removal of `key` and returns the value it had in the
+    /// transaction, or `null` in JavaScript when it was absent.
+    ///
+    /// Runs in `O(log n + log m)` time for `m` staged keys.
+    #[napi]
+    pub fn remove(&mut self, key: i32) -> Result<Option<String>> {
+        self.with_staging(|staging| {
+            staging.find(key)?;
+            staging.stage(key, None)
+        })
+    }
+
+    /// Returns the value for `key` with the staged changes applied, or `null`
+    /// in JavaScript when absent.
+    ///
+    /// Runs in `O(log n + log m)` time for `m` staged keys.
+    #[napi]
+    pub fn find(&self, key: i32) -> Result<Option<String>> {
+        self.with_staging(|staging| staging.find(key).cloned())
+    }
+
+    /// Reports whether `key` exists with the staged changes applied.
+    ///
+    /// Runs in `O(log n + log m)` time for `m` staged keys.
+    #[napi]
+    pub fn has(&self, key: i32) -> Result<bool> {
+        self.with_staging(|staging| staging.find(key).is_some())
+    }
+
+    /// The number of entries with the staged changes applied, read in constant
+    /// time.
+    #[napi(getter)]
+    pub fn size(&self) -> Result<u32> {
+        self.with_staging(|staging| position(staging.len))
+    }
+}
+
+#[cfg(not(test))]
+impl AVLTreeTransaction {
+    fn with_staging<T>(&self, access: impl FnOnce(&mut Staging) -> T) -> Result<T> {
+        match self.staging.borrow_mut().as_mut() {
+            Some(staging) => Ok(access(staging)),
+            None => Err(Error::from_reason(
+                "this AvlTree transaction has already ended",
+            )),
+        }
+    }
+}
+
+/// Walks a version of a tree by sorted position, releasing it once exhausted.
+#[cfg_attr(test, allow(dead_code))]
+struct Cursor {
+    tree: Option<PersistentTree<i32, String>>,
+    index: usize,
+}
+
+#[cfg_attr(test, allow(dead_code))]
+impl Cursor {
+    fn new(tree: &PersistentTree<i32, String>) -> Self {
+        Self {
+            tree: Some(tree.snapshot()),
+            index: 0,
+        }
+    }
+
+    fn advance(&mut self) -> Option<(&i32, &String)> {
+        if self
+            .tree
+            .as_ref()
+            .is_some_and(|tree| self.index >= tree.len())
+        {
+            self.close();
+        }
+        let entry = self.tree.as_ref()?.select(self.index);
+        self.index += 1;
+        entry
+    }
+
+    fn close(&mut self) {
+        self.tree = None;
+    }
+}
+
+/// An iterator over `[key, value]` pairs returned by `AvlTree.entries()` and
+/// `AvlTreeSnapshot.entries()`.
+#[cfg_attr(not(test), napi)]
+#[cfg_attr(test, allow(dead_code))]
+pub struct AVLTreeEntries {
+    cursor: Cursor,
+}
+
+#[cfg(not(test))]
+#[napi]
+impl AVLTreeEntries {
+    /// Returns the next `[key, value]` pair, or `null` in JavaScript when the
+    /// iterator is exhausted. `index.js` builds `next()` on top of this.
+    #[napi]
+    pub fn advance(&mut self) -> Option<(i32, String)> {
+        self.cursor
+            .advance()
+            .map(|(&key, value)| (key, value.clone()))
+    }
+
+    /// Ends the iteration early, releasing the entries it was reading.
+    #[napi]
+    pub fn close(&mut self) {
+        self.cursor.close();
+    }
+}
+
+/// An iterator over keys returned by `AvlTree.keys()` and
+/// `AvlTreeSnapshot.keys()`.
+#[cfg_attr(not(test), napi)]
+#[cfg_attr(test, allow(dead_code))]
+pub struct AVLTreeKeys {
+    cursor: Cursor,
+}
+
+#[cfg(not(test))]
+#[napi]
+impl AVLTreeKeys {
+    /// Returns the next key, or `null` in JavaScript when the iterator is
+    /// exhausted. `index.js` builds `next()` on top of this.
+    #[napi]
+    pub fn advance(&mut self) -> Option<i32> {
+        self.cursor.advance().map(|(&key, _)| key)
+    }
+
+    /// Ends the iteration early, releasing the entries it was reading.
+    #[napi]
+    pub fn close(&mut self) {
+        self.cursor.close();
+    }
+}
+
+/// An iterator over values returned by `AvlTree.values()` and
+/// `AvlTreeSnapshot.values()`.
+#[cfg_attr(not(test), napi)]
+#[cfg_attr(test, allow(dead_code))]
+pub struct AVLTreeValues {
+    cursor: Cursor,
+}
+
+#[cfg(not(test))]
+#[napi]
+impl AVLTreeValues {
+    /// Returns the next value, or `null` in JavaScript when the iterator is
+    /// exhausted. `index.js` builds `next()` on top of this.
+    #[napi]
+    pub fn advance(&mut self) -> Option<String> {
+        self.cursor.advance().map(|(_, value)| value.clone())
+    }
+
+    /// Ends the iteration early, releasing the entries it was reading.
+    #[napi]
+    pub fn close(&mut self) {
+        self.cursor.close();
+    }
+}
+
+/// Appends `records` to `log`, reporting a failure as a JavaScript error.
+#[cfg(not(test))]
+fn append(log: &mut WriteAheadLog, records: &[LogRecord<'_>]) -> Result<()> {
+    log.append(records)
+        .map_err(|error| Error::from_reason(format!("cannot write the AvlTree log: {error}")))
+}
+
+/// The error thrown by history methods on a tree without history.
+#[cfg(not(test))]
+fn no_history(method: &str) -> Error {
+    Error::from_reason(format!(
+        "{method} requires an AvlTree with history; call enableHistory first"
+    ))
+}
+
+/// Views a batch of keys passed either as an `Int32Array` or a plain array.
+#[cfg(not(test))]
+fn key_batch(keys: &Either<Int32Array, Vec<i32>>) -> &[i32] {
+    match keys {
+        Either::A(buffer) => buffer,
+        Either::B(array) => array,
+    }
+}
+
+/// Copies a core entry into the `[key, value]` pair returned to JavaScript.
+#[cfg(not(test))]
+fn entry((key, value): (&i32, &String)) -> (i32, String) {
+    (*key, value.clone())
+}
+
+/// Translates JavaScript range arguments into bounds on the core tree.
+#[cfg(not(test))]
+fn bounds(
+    lo: Option<i32>,
+    hi: Option<i32>,
+    options: Option<RangeOptions>,
+) -> (Bound<i32>, Bound<i32>) {
+    let inclusive = options
+        .and_then(|options| options.inclusive)
+        .unwrap_or(false);
+    let start = lo.map_or(Bound::Unbounded, Bound::Included);
+    let end = match hi {
+        Some(hi) if inclusive => Bound::Included(hi),
+        Some(hi) => Bound::Excluded(hi),
+        None => Bound::Unbounded,
+    };
+    (start, end)
+}
+
+/// Converts a sorted position for JavaScript. At least one `i32` key is never
+/// counted, so positions always fit in a `u32`.
+#[cfg(not(test))]
+fn position(index: usize) -> u32 {
+    u32::try_from(index).expect("positions among i32 keys fit in u32")
+}
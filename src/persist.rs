@@ -0,0 +1,116 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::{DeserializeError, PersistentTree, Tree};
+
+/// Distinguishes the temporary files of concurrent saves within one process.
+static SAVE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+impl Tree<i32, String> {
+    /// Writes the tree to `path` in the binary format of
+    /// [`Tree::serialize_into`], atomically replacing any existing file.
+    ///
+    /// The bytes go to a temporary file beside `path`, which is flushed to
+    /// disk and then renamed over `path`, so a crash at any point leaves either
+    /// the previous file or the complete new one. On Unix the directory is
+    /// synced as well, making the rename itself durable. The temporary file is
+    /// removed if anything fails.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        save_with(path.as_ref(), |writer| self.serialize_into(writer))
+    }
+
+    /// Reads a tree written by [`Tree::save`], rejecting a file that holds
+    /// anything after the checksum.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, DeserializeError> {
+        let mut reader = BufReader::new(File::open(path).map_err(DeserializeError::Io)?);
+        let tree = Self::deserialize_from(&mut reader)?;
+        let length = io::copy(&mut reader, &mut io::sink())?;
+        if length > 0 {
+            return Err(DeserializeError::TrailingData { length });
+        }
+        Ok(tree)
+    }
+}
+
+impl PersistentTree<i32, String> {
+    /// Writes the tree to `path` like [`Tree::save`], reading the entries
+    /// straight from the shared nodes.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        save_with(path.as_ref(), |writer| self.serialize_into(writer))
+    }
+}
+
+/// Replaces `path` atomically with the bytes `serialize` writes, as described
+/// on [`Tree::save`].
+fn save_with(
+    path: &Path,
+    serialize: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let temporary = temporary_path(path)?;
+    let result = write_synced(&temporary, serialize).and_then(|()| {
+        fs::rename(&temporary, path)?;
+        sync_parent(path)
+    });
+    if result.is_err() {
+        // The temporary file may be missing already; the original error
+        // is the one worth reporting.
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
+
+fn write_synced(
+    path: &Path,
+    serialize: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    let mut writer = BufWriter::new(file);
+    serialize(&mut writer)?;
+    writer.flush()?;
+    writer
+        .into_inner()
+        .map_err(|error| error.into_error())?
+        .sync_all()
+}
+
+/// Names a file in the same directory as `path`, so the final rename never
+/// crosses file systems.
+fn temporary_path(path: &Path) -> io::Result<PathBuf> {
+    let name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} does not name a file", path.display()),
+        )
+    })?;
+    let mut temporary = name.to_owned();
+    temporary.push(format!(
+        ".{}-{}.tmp",
+        process::id(),
+        SAVE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    Ok(path.with_file_name(temporary))
+}
+
+fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => sync_directory(parent),
+        _ => sync_directory(Path::new(".")),
+    }
+}
+
+/// Flushes the entries of `directory`, so files created in or renamed into
+/// it survive a crash.
+#[cfg(unix)]
+pub(super) fn sync_directory(directory: &Path) -> io::Result<()> {
+    File::open(directory)?.sync_all()
+}
+
+/// Windows cannot open a directory as a file, so persisting directory
+/// entries is left to the file system.
+#[cfg(not(unix))]
+pub(super) fn sync_directory(_directory: &Path) -> io::Result<()> {
+    Ok(())
+}
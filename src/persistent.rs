@@ -0,0 +1,973 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::iter::FusedIterator;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+use std::rc::Rc;
+
+use super::{dump_entries, Comparator, InvariantViolation, Natural, Node, Stats, Tree};
+
+/// An AVL tree whose versions share structure, so [`PersistentTree::snapshot`]
+/// takes `O(1)` time and later writes never change an existing snapshot.
+///
+/// Nodes are reference-counted and never modified while another version
+/// shares them: a write copies the `O(log n)` nodes on the path it changes,
+/// plus those it rotates, and points the copies at the untouched subtrees.
+/// Nodes that no other version shares are updated in place. Copying a node
+/// clones its key and value, so cheaply cloned types suit this tree best.
+pub struct PersistentTree<K, V, C = Natural> {
+    root: SharedLink<K, V>,
+    compare: C,
+}
+
+impl<K, V> PersistentTree<K, V> {
+    pub fn new() -> Self {
+        Self::with_comparator(Natural)
+    }
+}
+
+impl<K, V, C> PersistentTree<K, V, C> {
+    /// Creates an empty tree that orders its keys with `compare`.
+    pub fn with_comparator(compare: C) -> Self {
+        Self {
+            root: None,
+            compare,
+        }
+    }
+
+    /// Returns another handle to this version of the tree in `O(1)` time. The
+    /// handle keeps its contents however either tree is changed afterwards.
+    pub fn snapshot(&self) -> Self
+    where
+        C: Clone,
+    {
+        self.clone()
+    }
+
+    pub fn len(&self) -> usize {
+        SharedNode::size(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns the number of levels: `0` for an empty tree and `1` for a lone
+    /// root.
+    pub fn height(&self) -> usize {
+        SharedNode::height(&self.root) as usize
+    }
+
+    /// Returns the entry with the smallest key.
+    pub fn first(&self) -> Option<(&K, &V)> {
+        let mut node = self.root.as_deref()?;
+        while let Some(left) = node.left.as_deref() {
+            node = left;
+        }
+        Some((&node.key, &node.value))
+    }
+
+    /// Returns the entry with the largest key.
+    pub fn last(&self) -> Option<(&K, &V)> {
+        let mut node = self.root.as_deref()?;
+        while let Some(right) = node.right.as_deref() {
+            node = right;
+        }
+        Some((&node.key, &node.value))
+    }
+
+    /// Iterates over the entries in key order.
+    pub fn iter(&self) -> PersistentIter<'_, K, V> {
+        PersistentIter::new(&self.root, self.len())
+    }
+
+    /// Returns the entry at zero-based `index` in sorted order.
+    pub fn select(&self, mut index: usize) -> Option<(&K, &V)> {
+        let mut current = self.root.as_deref();
+        while let Some(node) = current {
+            let left_size = SharedNode::size(&node.left);
+            match index.cmp(&left_size) {
+                Ordering::Less => current = node.left.as_deref(),
+                Ordering::Equal => return Some((&node.key, &node.value)),
+                Ordering::Greater => {
+                    index -= left_size + 1;
+                    current = node.right.as_deref();
+                }
+            }
+        }
+        None
+    }
+
+    /// Removes every entry in `O(1)` time. Nodes that no snapshot shares are
+    /// freed, which takes `O(n)` time.
+    pub fn clear(&mut self) {
+        self.root = None;
+    }
+
+    /// Summarizes the shape of the tree like [`Tree::stats`], in `O(n)` time.
+    /// Nodes shared with other versions are counted in full.
+    pub fn stats(&self) -> Stats {
+        let len = self.len();
+        let mut total_depth = 0;
+        let mut pending: Vec<_> = self.root.as_deref().into_iter().collect();
+        while let Some(node) = pending.pop() {
+            total_depth += node.size;
+            pending.extend(node.left.as_deref());
+            pending.extend(node.right.as_deref());
+        }
+
+        // Each node sits behind its strong and weak reference counts.
+        let node_bytes = mem::size_of::<SharedNode<K, V>>() + 2 * mem::size_of::<usize>();
+        Stats {
+            len,
+            height: self.height(),
+            height_bound: Tree::<K, V, C>::height_bound(len),
+            node_bytes: len * node_bytes,
+            average_depth: if len == 0 {
+                0.0
+            } else {
+                total_depth as f64 / len as f64
+            },
+        }
+    }
+
+    /// Counts the nodes of this tree that `other` does not share, skipping
+    /// each shared subtree as a whole.
+    #[cfg(test)]
+    pub(super) fn unshared_nodes(&self, other: &Self) -> usize {
+        use std::collections::HashSet;
+
+        let mut shared = HashSet::new();
+        let mut stack: Vec<_> = other.root.iter().collect();
+        while let Some(node) = stack.pop() {
+            shared.insert(Rc::as_ptr(node));
+            stack.extend(node.left.iter().chain(&node.right));
+        }
+
+        let mut unshared = 0;
+        let mut stack: Vec<_> = self.root.iter().collect();
+        while let Some(node) = stack.pop() {
+            if !shared.contains(&Rc::as_ptr(node)) {
+                unshared += 1;
+                stack.extend(node.left.iter().chain(&node.right));
+            }
+        }
+        unshared
+    }
+}
+
+impl<K, V, C: Comparator<K>> PersistentTree<K, V, C> {
+    pub fn find(&self, key: &K) -> Option<&V> {
+        let mut current = self.root.as_deref();
+        while let Some(node) = current {
+            match self.compare.compare(key, &node.key) {
+                Ordering::Less => current = node.left.as_deref(),
+                Ordering::Greater => current = node.right.as_deref(),
+                Ordering::Equal => return Some(&node.value),
+            }
+        }
+        None
+    }
+
+    pub fn has(&self, key: &K) -> bool {
+        self.find(key).is_some()
+    }
+
+    /// Returns the zero-based position of `key` in sorted order, or `None`
+    /// when it is absent.
+    pub fn rank(&self, key: &K) -> Option<usize> {
+        let (count, found) = self.count_below(key);
+        found.then_some(count)
+    }
+
+    /// Counts the keys strictly less than `key`, which need not be present.
+    pub fn count_less_than(&self, key: &K) -> usize {
+        self.count_below(key).0
+    }
+
+    /// Returns the entry with the largest key less than or equal to `key`.
+    pub fn floor(&self, key: &K) -> Option<(&K, &V)> {
+        self.nearest(key, Ordering::Less, true)
+    }
+
+    /// Returns the entry with the smallest key greater than or equal to `key`.
+    pub fn ceiling(&self, key: &K) -> Option<(&K, &V)> {
+        self.nearest(key, Ordering::Greater, true)
+    }
+
+    /// Returns the entry with the largest key strictly less than `key`.
+    pub fn predecessor(&self, key: &K) -> Option<(&K, &V)> {
+        self.nearest(key, Ordering::Less, false)
+    }
+
+    /// Returns the entry with the smallest key strictly greater than `key`.
+    pub fn successor(&self, key: &K) -> Option<(&K, &V)> {
+        self.nearest(key, Ordering::Greater, false)
+    }
+
+    /// Iterates over the entries whose keys fall within `range`, in ascending
+    /// key order, like [`Tree::range`].
+    ///
+    /// Positioning the start takes `O(log n)` time and each step is amortized
+    /// `O(1)`. A range whose start lies after its end is empty.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> PersistentIter<'_, K, V> {
+        let (start, end) = (range.start_bound(), range.end_bound());
+        let remaining = self.count_bounds(start, end);
+        PersistentIter::range(&self.root, start, &self.compare, remaining)
+    }
+
+    /// Counts the keys within `range` in `O(log n)` time without visiting them.
+    pub fn count_range<R: RangeBounds<K>>(&self, range: R) -> usize {
+        self.count_bounds(range.start_bound(), range.end_bound())
+    }
+
+    /// Sums subtree sizes to the left of the search path for `key`, and
+    /// reports whether the search found it.
+    fn count_below(&self, key: &K) -> (usize, bool) {
+        let mut current = self.root.as_deref();
+        let mut count = 0;
+        while let Some(node) = current {
+            match self.compare.compare(key, &node.key) {
+                Ordering::Less => current = node.left.as_deref(),
+                Ordering::Equal => return (count + SharedNode::size(&node.left), true),
+                Ordering::Greater => {
+                    count += SharedNode::size(&node.left) + 1;
+                    current = node.right.as_deref();
+                }
+            }
+        }
+        (count, false)
+    }
+
+    /// Subtracts the sorted positions of both bounds.
+    fn count_bounds(&self, start: Bound<&K>, end: Bound<&K>) -> usize {
+        let below_start = match start {
+            Bound::Included(key) => self.count_less_than(key),
+            Bound::Excluded(key) => {
+                let (count, found) = self.count_below(key);
+                count + usize::from(found)
+            }
+            Bound::Unbounded => 0,
+        };
+        let through_end = match end {
+            Bound::Included(key) => {
+                let (count, found) = self.count_below(key);
+                count + usize::from(found)
+            }
+            Bound::Excluded(key) => self.count_less_than(key),
+            Bound::Unbounded => self.len(),
+        };
+        through_end.saturating_sub(below_start)
+    }
+
+    /// Finds the closest key on the `side` of `key`, or `key` itself when
+    /// `inclusive`, remembering the last qualifying node on the search path.
+    fn nearest(&self, key: &K, side: Ordering, inclusive: bool) -> Option<(&K, &V)> {
+        let mut current = self.root.as_deref();
+        let mut candidate = None;
+        while let Some(node) = current {
+            let order = self.compare.compare(&node.key, key);
+            if order == Ordering::Equal && inclusive {
+                return Some((&node.key, &node.value));
+            }
+            if order == side {
+                candidate = Some(node);
+            }
+            current = match (order, side) {
+                (Ordering::Less, _) | (Ordering::Equal, Ordering::Greater) => node.right.as_deref(),
+                _ => node.left.as_deref(),
+            };
+        }
+        candidate.map(|node| (&node.key, &node.value))
+    }
+}
+
+impl<K: Clone, V: Clone, C: Comparator<K>> PersistentTree<K, V, C> {
+    /// Inserts `value` under `key` and returns the value it replaced.
+    ///
+    /// Runs in `O(log n)` time and allocates at most `O(log n)` nodes.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let mut replaced = None;
+        self.root = Some(Self::insert_node(
+            self.root.take(),
+            key,
+            value,
+            &self.compare,
+            &mut replaced,
+        ));
+        replaced
+    }
+
+    /// Removes `key` and returns its value. A missing key copies nothing.
+    ///
+    /// Runs in `O(log n)` time and allocates at most `O(log n)` nodes.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        if !self.has(key) {
+            return None;
+        }
+        let root = self.root.take().expect("a tree holding the key has a root");
+        let (root, removed) = Self::remove_node(root, key, &self.compare);
+        self.root = root;
+        Some(removed)
+    }
+
+    /// Removes and returns the entry with the smallest key in `O(log n)` time.
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        let key = self.first()?.0.clone();
+        let value = self.remove(&key)?;
+        Some((key, value))
+    }
+
+    /// Removes and returns the entry with the largest key in `O(log n)` time.
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        let key = self.last()?.0.clone();
+        let value = self.remove(&key)?;
+        Some((key, value))
+    }
+
+    /// Combines two trees like [`Tree::union`], calling
+    /// `merge(key, self_value, other_value)` for keys present in both. Both
+    /// trees must order keys the same way; the result keeps this tree's
+    /// comparator and, for shared keys, this tree's key.
+    ///
+    /// Runs in `O(m log(n / m + 1))` time for sizes `m <= n`. Only the nodes
+    /// on the split paths are copied: subtrees that neither split reaches are
+    /// shared with the inputs and their snapshots.
+    pub fn union<F: FnMut(&K, V, V) -> V>(mut self, mut other: Self, mut merge: F) -> Self {
+        self.root = Self::union_nodes(
+            self.root.take(),
+            other.root.take(),
+            &self.compare,
+            &mut merge,
+        );
+        self
+    }
+
+    /// Keeps the entries of this tree whose keys also occur in `other`.
+    ///
+    /// Runs in `O(m log(n / m + 1))` time for sizes `m <= n`.
+    pub fn intersection(mut self, mut other: Self) -> Self {
+        self.root = Self::intersection_nodes(self.root.take(), other.root.take(), &self.compare);
+        self
+    }
+
+    /// Keeps the entries of this tree whose keys do not occur in `other`.
+    ///
+    /// Runs in `O(m log(n / m + 1))` time for sizes `m <= n`.
+    pub fn difference(mut self, mut other: Self) -> Self {
+        self.root = Self::difference_nodes(self.root.take(), other.root.take(), &self.compare);
+        self
+    }
+
+    /// Keeps the entries whose keys occur in exactly one of the two trees.
+    ///
+    /// Runs in `O(m log(n / m + 1))` time for sizes `m <= n`.
+    pub fn symmetric_difference(mut self, mut other: Self) -> Self {
+        self.root =
+            Self::symmetric_difference_nodes(self.root.take(), other.root.take(), &self.compare);
+        self
+    }
+
+    fn union_nodes<F: FnMut(&K, V, V) -> V>(
+        left: SharedLink<K, V>,
+        right: SharedLink<K, V>,
+        compare: &C,
+        merge: &mut F,
+    ) -> SharedLink<K, V> {
+        let Some(right) = right else {
+            return left;
+        };
+        if left.is_none() {
+            return Some(right);
+        }
+        let (right_lower, mut pivot, right_upper) = Self::expose(right);
+        let (left_lower, found, left_upper) = Self::split(left, &pivot.key, compare);
+        let lower = Self::union_nodes(left_lower, right_lower, compare, merge);
+        let upper = Self::union_nodes(left_upper, right_upper, compare, merge);
+        if let Some(found) = found {
+            // Both nodes were detached by `expose`, so unwrapping them copies
+            // nothing.
+            let mut found = Rc::unwrap_or_clone(found);
+            found.value = merge(&found.key, found.value, Rc::unwrap_or_clone(pivot).value);
+            pivot = Rc::new(found);
+        }
+        Some(Self::join(lower, pivot, upper))
+    }
+
+    fn intersection_nodes(
+        left: SharedLink<K, V>,
+        right: SharedLink<K, V>,
+        compare: &C,
+    ) -> SharedLink<K, V> {
+        let (Some(_), Some(right)) = (&left, right) else {
+            return None;
+        };
+        let (right_lower, pivot, right_upper) = Self::expose(right);
+        let (left_lower, found, left_upper) = Self::split(left, &pivot.key, compare);
+        let lower = Self::intersection_nodes(left_lower, right_lower, compare);
+        let upper = Self::intersection_nodes(left_upper, right_upper, compare);
+        match found {
+            Some(found) => Some(Self::join(lower, found, upper)),
+            None => Self::join2(lower, upper),
+        }
+    }
+
+    fn difference_nodes(
+        left: SharedLink<K, V>,
+        right: SharedLink<K, V>,
+        compare: &C,
+    ) -> SharedLink<K, V> {
+        let (Some(_), Some(right)) = (&left, right) else {
+            return left;
+        };
+        let (right_lower, pivot, right_upper) = Self::expose(right);
+        let (left_lower, _, left_upper) = Self::split(left, &pivot.key, compare);
+        let lower = Self::difference_nodes(left_lower, right_lower, compare);
+        let upper = Self::difference_nodes(left_upper, right_upper, compare);
+        Self::join2(lower, upper)
+    }
+
+    fn symmetric_difference_nodes(
+        left: SharedLink<K, V>,
+        right: SharedLink<K, V>,
+        compare: &C,
+    ) -> SharedLink<K, V> {
+        let Some(right) = right else {
+            return left;
+        };
+        if left.is_none() {
+            return Some(right);
+        }
+        let (right_lower, pivot, right_upper) = Self::expose(right);
+        let (left_lower, found, left_upper) = Self::split(left, &pivot.key, compare);
+        let lower = Self::symmetric_difference_nodes(left_lower, right_lower, compare);
+        let upper = Self::symmetric_difference_nodes(left_upper, right_upper, compare);
+        match found {
+            Some(_) => Self::join2(lower, upper),
+            None => Some(Self::join(lower, pivot, upper)),
+        }
+    }
+
+    /// Detaches the root of a subtree from its children, copying the root
+    /// if another version shares it.
+    fn expose(mut node: Rc<SharedNode<K, V>>) -> SharedExposed<K, V> {
+        let inner = Rc::make_mut(&mut node);
+        let left = inner.left.take();
+        let right = inner.right.take();
+        (left, node, right)
+    }
+
+    /// Divides a subtree like [`Tree`]'s split into the keys less than `key`,
+    /// the node holding `key` if present (detached from its children), and
+    /// the keys greater than `key`.
+    fn split(
+        node: SharedLink<K, V>,
+        key: &K,
+        compare: &C,
+    ) -> (SharedLink<K, V>, SharedLink<K, V>, SharedLink<K, V>) {
+        let Some(node) = node else {
+            return (None, None, None);
+        };
+        let (left, node, right) = Self::expose(node);
+
+        match compare.compare(key, &node.key) {
+            Ordering::Less => {
+                let (lower, found, upper) = Self::split(left, key, compare);
+                (lower, found, Some(Self::join(upper, node, right)))
+            }
+            Ordering::Greater => {
+                let (lower, found, upper) = Self::split(right, key, compare);
+                (Some(Self::join(left, node, lower)), found, upper)
+            }
+            Ordering::Equal => (left, Some(node), right),
+        }
+    }
+
+    /// Joins two subtrees around a detached `middle` node, copying only the
+    /// shared nodes on the spine it descends.
+    fn join(
+        left: SharedLink<K, V>,
+        mut middle: Rc<SharedNode<K, V>>,
+        right: SharedLink<K, V>,
+    ) -> Rc<SharedNode<K, V>> {
+        let left_height = SharedNode::height(&left);
+        let right_height = SharedNode::height(&right);
+
+        if left_height > right_height + 1 {
+            let mut left = left.expect("a taller left subtree is not empty");
+            let inner = Rc::make_mut(&mut left);
+            inner.right = Some(Self::join(inner.right.take(), middle, right));
+            inner.update();
+            Self::balance(left)
+        } else if right_height > left_height + 1 {
+            let mut right = right.expect("a taller right subtree is not empty");
+            let inner = Rc::make_mut(&mut right);
+            inner.left = Some(Self::join(left, middle, inner.left.take()));
+            inner.update();
+            Self::balance(right)
+        } else {
+            let inner = Rc::make_mut(&mut middle);
+            inner.left = left;
+            inner.right = right;
+            inner.update();
+            middle
+        }
+    }
+
+    /// Concatenates two subtrees where every key in `left` precedes every key
+    /// in `right`, using the minimum of `right` as the joining node.
+    fn join2(left: SharedLink<K, V>, right: SharedLink<K, V>) -> SharedLink<K, V> {
+        let Some(right) = right else {
+            return left;
+        };
+        let (right, minimum) = Self::remove_min(right);
+        Some(Self::join(left, minimum, right))
+    }
+
+    fn insert_node(
+        node: SharedLink<K, V>,
+        key: K,
+        value: V,
+        compare: &C,
+        replaced: &mut Option<V>,
+    ) -> Rc<SharedNode<K, V>> {
+        let Some(mut node) = node else {
+            return Rc::new(SharedNode::new(key, value));
+        };
+        let inner = Rc::make_mut(&mut node);
+        match compare.compare(&key, &inner.key) {
+            Ordering::Less => {
+                inner.left = Some(Self::insert_node(
+                    inner.left.take(),
+                    key,
+                    value,
+                    compare,
+                    replaced,
+                ));
+            }
+            Ordering::Greater => {
+                inner.right = Some(Self::insert_node(
+                    inner.right.take(),
+                    key,
+                    value,
+                    compare,
+                    replaced,
+                ));
+            }
+            Ordering::Equal => {
+                *replaced = Some(mem::replace(&mut inner.value, value));
+                return node;
+            }
+        }
+
+        if replaced.is_none() {
+            inner.update();
+            Self::balance(node)
+        } else {
+            node
+        }
+    }
+
+    /// Removes `key`, which must be present below `node`, and returns the
+    /// new subtree with the removed value.
+    fn remove_node(mut node: Rc<SharedNode<K, V>>, key: &K, compare: &C) -> (SharedLink<K, V>, V) {
+        let inner = Rc::make_mut(&mut node);
+        match compare.compare(key, &inner.key) {
+            Ordering::Less => {
+                let left = inner
+                    .left
+                    .take()
+                    .expect("a smaller present key is in the left subtree");
+                let (new_left, removed) = Self::remove_node(left, key, compare);
+                inner.left = new_left;
+                inner.update();
+                (Some(Self::balance(node)), removed)
+            }
+            Ordering::Greater => {
+                let right = inner
+                    .right
+                    .take()
+                    .expect("a larger present key is in the right subtree");
+                let (new_right, removed) = Self::remove_node(right, key, compare);
+                inner.right = new_right;
+                inner.update();
+                (Some(Self::balance(node)), removed)
+            }
+            Ordering::Equal => {
+                let SharedNode {
+                    value, left, right, ..
+                } = Rc::unwrap_or_clone(node);
+
+                match (left, right) {
+                    (None, right) => (right, value),
+                    (left, None) => (left, value),
+                    (Some(left), Some(right)) => {
+                        let (new_right, mut successor) = Self::remove_min(right);
+                        let inner = Rc::make_mut(&mut successor);
+                        inner.left = Some(left);
+                        inner.right = new_right;
+                        inner.update();
+                        (Some(Self::balance(successor)), value)
+                    }
+                }
+            }
+        }
+    }
+
+    fn remove_min(mut node: Rc<SharedNode<K, V>>) -> (SharedLink<K, V>, Rc<SharedNode<K, V>>) {
+        let inner = Rc::make_mut(&mut node);
+        match inner.left.take() {
+            None => (inner.right.take(), node),
+            Some(left) => {
+                let (new_left, minimum) = Self::remove_min(left);
+                inner.left = new_left;
+                inner.update();
+                (Some(Self::balance(node)), minimum)
+            }
+        }
+    }
+
+    /// Restores the balance of a node whose height and size are current and
+    /// which no other version shares.
+    fn balance(mut node: Rc<SharedNode<K, V>>) -> Rc<SharedNode<K, V>> {
+        let balance_factor = node.balance_factor();
+
+        if balance_factor > 1 {
+            let inner = Rc::make_mut(&mut node);
+            let left = inner
+                .left
+                .take()
+                .expect("a left-heavy node must have a left child");
+            inner.left = Some(if left.balance_factor() < 0 {
+                Self::rotate_left(left)
+            } else {
+                left
+            });
+            Self::rotate_right(node)
+        } else if balance_factor < -1 {
+            let inner = Rc::make_mut(&mut node);
+            let right = inner
+                .right
+                .take()
+                .expect("a right-heavy node must have a right child");
+            inner.right = Some(if right.balance_factor() > 0 {
+                Self::rotate_right(right)
+            } else {
+                right
+            });
+            Self::rotate_left(node)
+        } else {
+            node
+        }
+    }
+
+    fn rotate_right(mut root: Rc<SharedNode<K, V>>) -> Rc<SharedNode<K, V>> {
+        let inner = Rc::make_mut(&mut root);
+        let mut pivot = inner
+            .left
+            .take()
+            .expect("right rotation requires a left child");
+        let pivot_inner = Rc::make_mut(&mut pivot);
+        inner.left = pivot_inner.right.take();
+        inner.update();
+        pivot_inner.right = Some(root);
+        pivot_inner.update();
+        pivot
+    }
+
+    fn rotate_left(mut root: Rc<SharedNode<K, V>>) -> Rc<SharedNode<K, V>> {
+        let inner = Rc::make_mut(&mut root);
+        let mut pivot = inner
+            .right
+            .take()
+            .expect("left rotation requires a right child");
+        let pivot_inner = Rc::make_mut(&mut pivot);
+        inner.right = pivot_inner.left.take();
+        inner.update();
+        pivot_inner.left = Some(root);
+        pivot_inner.update();
+        pivot
+    }
+}
+
+impl<K: Clone, V, C: Comparator<K>> PersistentTree<K, V, C> {
+    /// Checks the invariants that [`Tree::validate`] checks, in `O(n)` time.
+    /// The entry count is the size stored at the root, so a wrong count shows
+    /// up as a [`InvariantViolation::SizeMismatch`].
+    pub fn validate(&self) -> Result<(), InvariantViolation<K>> {
+        Self::validate_node(&self.root, &self.compare, None, None).map(drop)
+    }
+
+    /// Validates a subtree whose keys must lie strictly between `lower` and
+    /// `upper`, returning its height and size.
+    fn validate_node(
+        node: &SharedLink<K, V>,
+        compare: &C,
+        lower: Option<&K>,
+        upper: Option<&K>,
+    ) -> Result<(i32, usize), InvariantViolation<K>> {
+        let Some(node) = node else {
+            return Ok((0, 0));
+        };
+
+        let misplaced = |ancestor: &K| InvariantViolation::Unordered {
+            key: node.key.clone(),
+            ancestor: ancestor.clone(),
+        };
+        if let Some(lower) = lower.filter(|lower| compare.compare(&node.key, lower).is_le()) {
+            return Err(misplaced(lower));
+        }
+        if let Some(upper) = upper.filter(|upper| compare.compare(&node.key, upper).is_ge()) {
+            return Err(misplaced(upper));
+        }
+
+        let (left_height, left_size) =
+            Self::validate_node(&node.left, compare, lower, Some(&node.key))?;
+        let (right_height, right_size) =
+            Self::validate_node(&node.right, compare, Some(&node.key), upper)?;
+
+        let height = 1 + left_height.max(right_height);
+        if node.height != height {
+            return Err(InvariantViolation::HeightMismatch {
+                key: node.key.clone(),
+                stored: node.height,
+                actual: height,
+            });
+        }
+        let balance_factor = left_height - right_height;
+        if !(-1..=1).contains(&balance_factor) {
+            return Err(InvariantViolation::Unbalanced {
+                key: node.key.clone(),
+                balance_factor,
+            });
+        }
+        let size = 1 + left_size + right_size;
+        if node.size != size {
+            return Err(InvariantViolation::SizeMismatch {
+                key: node.key.clone(),
+                stored: node.size,
+                actual: size,
+            });
+        }
+        Ok((height, size))
+    }
+}
+
+impl<K: Display, V: Display, C> PersistentTree<K, V, C> {
+    /// Lists the entries in the format of [`Tree::dump`], in `O(n)` time.
+    pub fn dump(&self) -> String {
+        dump_entries(self.len(), self)
+    }
+}
+
+impl<K, V, C: Clone> Clone for PersistentTree<K, V, C> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            compare: self.compare.clone(),
+        }
+    }
+}
+
+impl<K, V, C: Default> Default for PersistentTree<K, V, C> {
+    fn default() -> Self {
+        Self::with_comparator(C::default())
+    }
+}
+
+impl<K, V, C: Comparator<K> + Default> FromIterator<(K, V)> for PersistentTree<K, V, C> {
+    /// Builds the tree like [`Tree`]'s `FromIterator`, in `O(n)` time for
+    /// sorted input.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Tree::from_iter(iter).into()
+    }
+}
+
+/// Moves every node of `tree` behind an [`Rc`] in `O(n)` time, keeping its
+/// shape.
+impl<K, V, C> From<Tree<K, V, C>> for PersistentTree<K, V, C> {
+    fn from(tree: Tree<K, V, C>) -> Self {
+        fn share<K, V>(node: Node<K, V>) -> Rc<SharedNode<K, V>> {
+            let Node {
+                key,
+                value,
+                height,
+                size,
+                left,
+                right,
+            } = node;
+            Rc::new(SharedNode {
+                key,
+                value,
+                height,
+                size,
+                left: left.map(|node| share(*node)),
+                right: right.map(|node| share(*node)),
+            })
+        }
+
+        Self {
+            root: tree.root.map(|node| share(*node)),
+            compare: tree.compare,
+        }
+    }
+}
+
+/// Copies a version into a mutable [`Tree`] of the same shape in `O(n)` time.
+impl<K: Clone, V: Clone, C: Clone> From<&PersistentTree<K, V, C>> for Tree<K, V, C> {
+    fn from(tree: &PersistentTree<K, V, C>) -> Self {
+        fn copy<K: Clone, V: Clone>(node: &SharedNode<K, V>) -> Box<Node<K, V>> {
+            Box::new(Node {
+                key: node.key.clone(),
+                value: node.value.clone(),
+                height: node.height,
+                size: node.size,
+                left: node.left.as_deref().map(copy),
+                right: node.right.as_deref().map(copy),
+            })
+        }
+
+        Self {
+            root: tree.root.as_deref().map(copy),
+            len: tree.len(),
+            compare: tree.compare.clone(),
+        }
+    }
+}
+
+impl<'a, K, V, C> IntoIterator for &'a PersistentTree<K, V, C> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = PersistentIter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// A borrowing iterator over a [`PersistentTree`] or one of its ranges in key
+/// order.
+pub struct PersistentIter<'a, K, V> {
+    stack: Vec<&'a SharedNode<K, V>>,
+    remaining: usize,
+}
+
+impl<'a, K, V> PersistentIter<'a, K, V> {
+    fn new(root: &'a SharedLink<K, V>, len: usize) -> Self {
+        let mut iter = Self {
+            stack: Vec::new(),
+            remaining: len,
+        };
+        iter.push_left(root.as_deref());
+        iter
+    }
+
+    /// Positions the iterator at the first key within `start`. `remaining`
+    /// must be the exact number of entries within the range, which stops the
+    /// iterator at its end.
+    fn range<C: Comparator<K>>(
+        root: &'a SharedLink<K, V>,
+        start: Bound<&K>,
+        compare: &C,
+        remaining: usize,
+    ) -> Self {
+        let mut iter = Self {
+            stack: Vec::new(),
+            remaining,
+        };
+        if remaining == 0 {
+            return iter;
+        }
+
+        let mut current = root.as_deref();
+        while let Some(node) = current {
+            let above_start = match start {
+                Bound::Included(start) => compare.compare(&node.key, start) != Ordering::Less,
+                Bound::Excluded(start) => compare.compare(&node.key, start) == Ordering::Greater,
+                Bound::Unbounded => true,
+            };
+            if above_start {
+                iter.stack.push(node);
+                current = node.left.as_deref();
+            } else {
+                current = node.right.as_deref();
+            }
+        }
+        iter
+    }
+
+    fn push_left(&mut self, mut node: Option<&'a SharedNode<K, V>>) {
+        while let Some(current) = node {
+            self.stack.push(current);
+            node = current.left.as_deref();
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for PersistentIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.stack.pop()?;
+        self.push_left(node.right.as_deref());
+        self.remaining -= 1;
+        Some((&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for PersistentIter<'_, K, V> {}
+
+impl<K, V> FusedIterator for PersistentIter<'_, K, V> {}
+
+type SharedLink<K, V> = Option<Rc<SharedNode<K, V>>>;
+
+/// A shared node detached from its left and right subtrees.
+type SharedExposed<K, V> = (SharedLink<K, V>, Rc<SharedNode<K, V>>, SharedLink<K, V>);
+
+/// A [`Node`] that versions of a [`PersistentTree`] may share.
+#[derive(Clone)]
+struct SharedNode<K, V> {
+    key: K,
+    value: V,
+    height: i32,
+    size: usize,
+    left: SharedLink<K, V>,
+    right: SharedLink<K, V>,
+}
+
+impl<K, V> SharedNode<K, V> {
+    fn new(key: K, value: V) -> Self {
+        Self {
+            key,
+            value,
+            height: 1,
+            size: 1,
+            left: None,
+            right: None,
+        }
+    }
+
+    fn height(node: &SharedLink<K, V>) -> i32 {
+        node.as_ref().map_or(0, |node| node.height)
+    }
+
+    fn size(node: &SharedLink<K, V>) -> usize {
+        node.as_ref().map_or(0, |node| node.size)
+    }
+
+    /// Recomputes the cached height and subtree size from the children.
+    fn update(&mut self) {
+        self.height = 1 + Self::height(&self.left).max(Self::height(&self.right));
+        self.size = 1 + Self::size(&self.left) + Self::size(&self.right);
+    }
+
+    fn balance_factor(&self) -> i32 {
+        Self::height(&self.left) - Self::height(&self.right)
+    }
+}
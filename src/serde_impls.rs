@@ -0,0 +1,66 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Formatter};
+use std::marker::PhantomData;
+
+use serde::de::{Error, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{Comparator, Tree};
+
+/// Serializes the tree as a map with its entries in tree order.
+impl<K: Serialize, V: Serialize, C> Serialize for Tree<K, V, C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self)
+    }
+}
+
+/// Deserializes a map in any order, rejecting a repeated key. A map whose keys
+/// already ascend is built in `O(n)` time; anything else costs one sort.
+impl<'de, K, V, C> Deserialize<'de> for Tree<K, V, C>
+where
+    K: Deserialize<'de>,
+    V: Deserialize<'de>,
+    C: Comparator<K> + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(TreeVisitor(PhantomData))
+    }
+}
+
+/// Produces the tree type without owning one, so the visitor stays `Send`
+/// and `Sync` whatever the key, value, and comparator types are.
+type Marker<T> = PhantomData<fn() -> T>;
+
+struct TreeVisitor<K, V, C>(Marker<Tree<K, V, C>>);
+
+impl<'de, K, V, C> Visitor<'de> for TreeVisitor<K, V, C>
+where
+    K: Deserialize<'de>,
+    V: Deserialize<'de>,
+    C: Comparator<K> + Default,
+{
+    type Value = Tree<K, V, C>;
+
+    fn expecting(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a map with unique keys")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        // The size hint comes from the input, so it only guides a bounded
+        // reservation.
+        let mut entries = Vec::with_capacity(map.size_hint().unwrap_or(0).min(4096));
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+
+        let compare = C::default();
+        if entries
+            .windows(2)
+            .any(|pair| compare.compare(&pair[0].0, &pair[1].0) == Ordering::Greater)
+        {
+            entries.sort_by(|left, right| compare.compare(&left.0, &right.0));
+        }
+        Tree::from_sorted_with(entries, compare, false)
+            .map_err(|_| A::Error::custom("duplicate key in map"))
+    }
+}
@@ -1,63 +1,109 @@
+use std::cmp::{Ordering, Reverse};
 use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Debug;
+use std::fs;
+use std::io::{self, Read};
 use std::mem::size_of;
+use std::num::ParseIntError;
+use std::ops::Bound;
+use std::path::PathBuf;
+use std::process;
+use std::rc::Rc;
 
 use proptest::prelude::*;
 
-use super::{Node, Tree};
+use super::binary::crc32;
+use super::{
+    Comparator, DeserializeError, DurableError, DurableTree, Entry, History, InvariantViolation,
+    JsonError, Link, LogRecord, Node, OccupiedError, PersistentTree, Stats, Transaction, Tree,
+    UnsortedError, WriteAheadLog, LOG_FILE, LOG_MAGIC, LOG_VERSION, SNAPSHOT_FILE,
+};
 
-fn validate_node(
-    node: &Option<Box<Node>>,
-    lower: Option<i32>,
-    upper: Option<i32>,
-    entries: &mut Vec<(i32, String)>,
+fn validate_node<'a, K: Debug, V, C: Comparator<K>>(
+    node: &'a Link<K, V>,
+    compare: &C,
+    lower: Option<&K>,
+    upper: Option<&K>,
+    entries: &mut Vec<(&'a K, &'a V)>,
 ) -> (i32, usize) {
     let Some(node) = node else {
         return (0, 0);
     };
 
     if let Some(lower) = lower {
-        assert!(node.key > lower, "{} is not greater than {lower}", node.key);
+        assert_eq!(
+            compare.compare(&node.key, lower),
+            Ordering::Greater,
+            "{:?} is not greater than {lower:?}",
+            node.key
+        );
     }
     if let Some(upper) = upper {
-        assert!(node.key < upper, "{} is not less than {upper}", node.key);
+        assert_eq!(
+            compare.compare(&node.key, upper),
+            Ordering::Less,
+            "{:?} is not less than {upper:?}",
+            node.key
+        );
     }
 
-    let (left_height, left_count) = validate_node(&node.left, lower, Some(node.key), entries);
-    entries.push((node.key, node.value.clone()));
-    let (right_height, right_count) = validate_node(&node.right, Some(node.key), upper, entries);
+    let (left_height, left_count) =
+        validate_node(&node.left, compare, lower, Some(&node.key), entries);
+    entries.push((&node.key, &node.value));
+    let (right_height, right_count) =
+        validate_node(&node.right, compare, Some(&node.key), upper, entries);
 
     let expected_height = 1 + left_height.max(right_height);
     assert_eq!(
         node.height, expected_height,
-        "incorrect stored height at key {}",
+        "incorrect stored height at key {:?}",
         node.key
     );
     assert!(
         (-1..=1).contains(&(left_height - right_height)),
-        "unbalanced node at key {}",
+        "unbalanced node at key {:?}",
+        node.key
+    );
+    let expected_size = left_count + right_count + 1;
+    assert_eq!(
+        node.size, expected_size,
+        "incorrect stored size at key {:?}",
         node.key
     );
 
-    (expected_height, left_count + right_count + 1)
+    (expected_height, expected_size)
 }
 
-fn assert_valid(tree: &Tree) -> Vec<(i32, String)> {
+fn assert_ordered<K: Clone + Debug, V, C: Comparator<K>>(tree: &Tree<K, V, C>) -> Vec<(&K, &V)> {
+    if let Err(violation) = tree.validate() {
+        panic!("{violation}");
+    }
     let mut entries = Vec::new();
-    let (_, count) = validate_node(&tree.root, None, None, &mut entries);
+    let (_, count) = validate_node(&tree.root, &tree.compare, None, None, &mut entries);
 
     assert_eq!(count, entries.len());
     assert_eq!(count, tree.len);
     assert_eq!(tree.len(), tree.len);
     assert_eq!(tree.is_empty(), tree.len == 0);
     assert!(
-        entries.windows(2).all(|pair| pair[0].0 < pair[1].0),
+        entries
+            .windows(2)
+            .all(|pair| tree.compare.compare(pair[0].0, pair[1].0) == Ordering::Less),
         "in-order traversal is not strictly ordered"
     );
 
     entries
 }
 
-fn assert_matches_model(tree: &Tree, model: &BTreeMap<i32, String>) {
+fn assert_valid(tree: &Tree<i32, String>) -> Vec<(i32, String)> {
+    assert_ordered(tree)
+        .into_iter()
+        .map(|(&key, value)| (key, value.clone()))
+        .collect()
+}
+
+fn assert_matches_model(tree: &Tree<i32, String>, model: &BTreeMap<i32, String>) {
     let entries = assert_valid(tree);
     let expected: Vec<_> = model
         .iter()
@@ -66,19 +112,19 @@ fn assert_matches_model(tree: &Tree, model: &BTreeMap<i32, String>) {
 
     assert_eq!(entries, expected);
     for (&key, value) in model {
-        assert_eq!(tree.find(key), Some(value.as_str()));
-        assert!(tree.has(key));
+        assert_eq!(tree.find(&key).map(String::as_str), Some(value.as_str()));
+        assert!(tree.has(&key));
     }
 }
 
 #[test]
 fn empty_tree_behavior() {
     let mut tree = Tree::new();
-    let default_tree = Tree::default();
+    let default_tree = Tree::<i32, String>::default();
 
-    assert_eq!(tree.find(1), None);
-    assert!(!tree.has(1));
-    assert_eq!(tree.remove(1), None);
+    assert_eq!(tree.find(&1).map(String::as_str), None);
+    assert!(!tree.has(&1));
+    assert_eq!(tree.remove(&1), None);
     assert_eq!(tree.dump(), "");
     assert!(assert_valid(&tree).is_empty());
     assert!(default_tree.is_empty());
@@ -89,11 +135,11 @@ fn one_insertion_and_duplicate_replacement() {
     let mut tree = Tree::new();
 
     tree.insert(7, "first".to_owned());
-    assert_eq!(tree.find(7), Some("first"));
+    assert_eq!(tree.find(&7).map(String::as_str), Some("first"));
     assert_eq!(tree.dump(), "{ key: 7, value: 'first' }");
 
     tree.insert(7, "replacement".to_owned());
-    assert_eq!(tree.find(7), Some("replacement"));
+    assert_eq!(tree.find(&7).map(String::as_str), Some("replacement"));
     assert_eq!(tree.len(), 1);
     assert_eq!(assert_valid(&tree), vec![(7, "replacement".to_owned())]);
 }
@@ -103,10 +149,10 @@ fn find_and_has_hits_and_misses() {
     let mut tree = Tree::new();
     tree.insert(10, "ten".to_owned());
 
-    assert_eq!(tree.find(10), Some("ten"));
-    assert_eq!(tree.find(11), None);
-    assert!(tree.has(10));
-    assert!(!tree.has(11));
+    assert_eq!(tree.find(&10).map(String::as_str), Some("ten"));
+    assert_eq!(tree.find(&11).map(String::as_str), None);
+    assert!(tree.has(&10));
+    assert!(!tree.has(&11));
     assert_valid(&tree);
 }
 
@@ -118,7 +164,7 @@ fn missing_removal_preserves_tree() {
     }
     let before = tree.dump();
 
-    assert_eq!(tree.remove(99), None);
+    assert_eq!(tree.remove(&99), None);
     assert_eq!(tree.dump(), before);
     assert_valid(&tree);
 }
@@ -129,32 +175,32 @@ fn removes_leaf_and_non_root_nodes_with_each_child_shape() {
     for key in [4, 2, 6, 1, 3, 5, 7] {
         leaf_tree.insert(key, key.to_string());
     }
-    assert_eq!(leaf_tree.remove(1), Some("1".to_owned()));
-    assert_eq!(leaf_tree.find(1), None);
+    assert_eq!(leaf_tree.remove(&1), Some("1".to_owned()));
+    assert_eq!(leaf_tree.find(&1).map(String::as_str), None);
     assert_valid(&leaf_tree);
 
     let mut left_child_tree = Tree::new();
     for key in [10, 5, 15, 3] {
         left_child_tree.insert(key, key.to_string());
     }
-    assert_eq!(left_child_tree.remove(5), Some("5".to_owned()));
-    assert_eq!(left_child_tree.find(3), Some("3"));
+    assert_eq!(left_child_tree.remove(&5), Some("5".to_owned()));
+    assert_eq!(left_child_tree.find(&3).map(String::as_str), Some("3"));
     assert_valid(&left_child_tree);
 
     let mut right_child_tree = Tree::new();
     for key in [10, 5, 15, 7] {
         right_child_tree.insert(key, key.to_string());
     }
-    assert_eq!(right_child_tree.remove(5), Some("5".to_owned()));
-    assert_eq!(right_child_tree.find(7), Some("7"));
+    assert_eq!(right_child_tree.remove(&5), Some("5".to_owned()));
+    assert_eq!(right_child_tree.find(&7).map(String::as_str), Some("7"));
     assert_valid(&right_child_tree);
 
     let mut two_child_tree = Tree::new();
     for key in [10, 5, 15, 3, 7, 6, 8] {
         two_child_tree.insert(key, key.to_string());
     }
-    assert_eq!(two_child_tree.remove(5), Some("5".to_owned()));
-    assert_eq!(two_child_tree.find(5), None);
+    assert_eq!(two_child_tree.remove(&5), Some("5".to_owned()));
+    assert_eq!(two_child_tree.find(&5).map(String::as_str), None);
     assert_valid(&two_child_tree);
 }
 
@@ -162,29 +208,29 @@ fn removes_leaf_and_non_root_nodes_with_each_child_shape() {
 fn removes_root_with_each_child_shape() {
     let mut leaf = Tree::new();
     leaf.insert(1, "leaf".to_owned());
-    assert_eq!(leaf.remove(1), Some("leaf".to_owned()));
+    assert_eq!(leaf.remove(&1), Some("leaf".to_owned()));
     assert!(assert_valid(&leaf).is_empty());
 
     let mut left = Tree::new();
     left.insert(2, "root".to_owned());
     left.insert(1, "left".to_owned());
-    assert_eq!(left.remove(2), Some("root".to_owned()));
-    assert_eq!(left.find(1), Some("left"));
+    assert_eq!(left.remove(&2), Some("root".to_owned()));
+    assert_eq!(left.find(&1).map(String::as_str), Some("left"));
     assert_valid(&left);
 
     let mut right = Tree::new();
     right.insert(1, "root".to_owned());
     right.insert(2, "right".to_owned());
-    assert_eq!(right.remove(1), Some("root".to_owned()));
-    assert_eq!(right.find(2), Some("right"));
+    assert_eq!(right.remove(&1), Some("root".to_owned()));
+    assert_eq!(right.find(&2).map(String::as_str), Some("right"));
     assert_valid(&right);
 
     let mut two_children = Tree::new();
     for key in [2, 1, 3] {
         two_children.insert(key, key.to_string());
     }
-    assert_eq!(two_children.remove(2), Some("2".to_owned()));
-    assert_eq!(two_children.find(2), None);
+    assert_eq!(two_children.remove(&2), Some("2".to_owned()));
+    assert_eq!(two_children.find(&2).map(String::as_str), None);
     assert_valid(&two_children);
 }
 
@@ -214,12 +260,12 @@ fn rebalances_after_deletion() {
     }
     let root_before = tree.root.as_ref().map(|node| node.key);
 
-    assert_eq!(tree.remove(10), Some("10".to_owned()));
+    assert_eq!(tree.remove(&10), Some("10".to_owned()));
     assert_ne!(tree.root.as_ref().map(|node| node.key), root_before);
     assert_valid(&tree);
 
     for key in [11, 9, 6, 5, 2, 1, 0, -1] {
-        tree.remove(key);
+        tree.remove(&key);
         assert_valid(&tree);
     }
 }
@@ -231,13 +277,100 @@ fn supports_i32_boundaries() {
     tree.insert(0, "zero".to_owned());
     tree.insert(i32::MAX, "maximum".to_owned());
 
-    assert_eq!(tree.find(i32::MIN), Some("minimum"));
-    assert_eq!(tree.find(i32::MAX), Some("maximum"));
-    assert_eq!(tree.remove(i32::MIN), Some("minimum".to_owned()));
-    assert_eq!(tree.remove(i32::MAX), Some("maximum".to_owned()));
+    assert_eq!(tree.find(&i32::MIN).map(String::as_str), Some("minimum"));
+    assert_eq!(tree.find(&i32::MAX).map(String::as_str), Some("maximum"));
+    assert_eq!(tree.remove(&i32::MIN), Some("minimum".to_owned()));
+    assert_eq!(tree.remove(&i32::MAX), Some("maximum".to_owned()));
     assert_valid(&tree);
 }
 
+#[test]
+fn supports_other_key_and_value_types() {
+    #[derive(Debug, PartialEq)]
+    struct Slot {
+        owner: &'static str,
+        minutes: u16,
+    }
+
+    let mut ids = Tree::new();
+    for id in [u64::MAX, 0, 42] {
+        ids.insert(
+            id,
+            Slot {
+                owner: "ops",
+                minutes: 30,
+            },
+        );
+    }
+    assert_eq!(ids.len(), 3);
+    assert_eq!(ids.find(&0).map(|slot| slot.minutes), Some(30));
+    assert_eq!(
+        ids.remove(&u64::MAX),
+        Some(Slot {
+            owner: "ops",
+            minutes: 30,
+        })
+    );
+    assert!(!ids.has(&u64::MAX));
+
+    let mut names = Tree::new();
+    for name in ["delta", "alpha", "charlie", "bravo"] {
+        names.insert(name.to_owned(), name.len());
+    }
+    assert_eq!(names.find(&"charlie".to_owned()), Some(&7));
+    assert_eq!(
+        names.dump(),
+        "{ key: alpha, value: '5' }, { key: bravo, value: '5' }, { key: charlie, value: '7' }, { key: delta, value: '5' }"
+    );
+}
+
+#[test]
+fn orders_keys_with_a_custom_comparator() {
+    let mut descending = Tree::with_comparator(|left: &i32, right: &i32| right.cmp(left));
+    for key in 0..1_000 {
+        descending.insert(key, key.to_string());
+    }
+    for key in (0..1_000).step_by(3) {
+        assert_eq!(descending.remove(&key), Some(key.to_string()));
+        assert_ordered(&descending);
+    }
+
+    let entries = assert_ordered(&descending);
+    assert_eq!(entries.len(), 666);
+    assert_eq!(entries.first().map(|(&key, _)| key), Some(998));
+    assert_eq!(entries.last().map(|(&key, _)| key), Some(1));
+    assert_eq!(descending.find(&500).map(String::as_str), Some("500"));
+    assert!(!descending.has(&999));
+}
+
+#[test]
+fn comparator_equality_replaces_values() {
+    struct CaseInsensitive;
+
+    impl Comparator<String> for CaseInsensitive {
+        fn compare(&self, left: &String, right: &String) -> Ordering {
+            left.bytes()
+                .map(|byte| byte.to_ascii_lowercase())
+                .cmp(right.bytes().map(|byte| byte.to_ascii_lowercase()))
+        }
+    }
+
+    let mut tree = Tree::with_comparator(CaseInsensitive);
+    tree.insert("Bravo".to_owned(), 1);
+    tree.insert("alpha".to_owned(), 2);
+    tree.insert("ALPHA".to_owned(), 3);
+    tree.insert("Charlie".to_owned(), 4);
+
+    assert_eq!(tree.len(), 3);
+    assert_eq!(tree.find(&"Alpha".to_owned()), Some(&3));
+    assert_eq!(
+        tree.dump(),
+        "{ key: alpha, value: '3' }, { key: Bravo, value: '1' }, { key: Charlie, value: '4' }"
+    );
+    assert_eq!(tree.remove(&"bRAVO".to_owned()), Some(1));
+    assert_ordered(&tree);
+}
+
 #[test]
 fn remains_balanced_for_sorted_insertions() {
     let mut ascending = Tree::new();
@@ -272,10 +405,13 @@ fn deterministic_mixed_sequence_matches_btree_map() {
                 tree.insert(key, value.clone());
                 model.insert(key, value);
             }
-            2 => assert_eq!(tree.remove(key), model.remove(&key)),
+            2 => assert_eq!(tree.remove(&key), model.remove(&key)),
             _ => {
-                assert_eq!(tree.find(key), model.get(&key).map(String::as_str));
-                assert_eq!(tree.has(key), model.contains_key(&key));
+                assert_eq!(
+                    tree.find(&key).map(String::as_str),
+                    model.get(&key).map(String::as_str)
+                );
+                assert_eq!(tree.has(&key), model.contains_key(&key));
             }
         }
 
@@ -284,80 +420,2338 @@ fn deterministic_mixed_sequence_matches_btree_map() {
 }
 
 #[test]
-fn dump_preserves_exact_legacy_format() {
+fn navigation_queries_on_empty_and_single_entry_trees() {
     let mut tree = Tree::new();
-    tree.insert(3, String::new());
-    tree.insert(1, "quotes: ' and \"".to_owned());
-    tree.insert(2, "commas, braces { }".to_owned());
-    tree.insert(4, "Unicode: Привіт 🌳".to_owned());
+    assert_eq!(tree.first(), None);
+    assert_eq!(tree.last(), None);
+    assert_eq!(tree.floor(&0), None);
+    assert_eq!(tree.ceiling(&0), None);
+    assert_eq!(tree.predecessor(&0), None);
+    assert_eq!(tree.successor(&0), None);
 
-    assert_eq!(
-        tree.dump(),
-        "{ key: 1, value: 'quotes: ' and \"' }, { key: 2, value: 'commas, braces { }' }, { key: 3, value: '' }, { key: 4, value: 'Unicode: Привіт 🌳' }"
-    );
-    assert_valid(&tree);
+    tree.insert(5, "five".to_owned());
+    let five = Some((&5, &"five".to_owned()));
+    assert_eq!(tree.first(), five);
+    assert_eq!(tree.last(), five);
+    assert_eq!(tree.floor(&5), five);
+    assert_eq!(tree.floor(&4), None);
+    assert_eq!(tree.ceiling(&5), five);
+    assert_eq!(tree.ceiling(&6), None);
+    assert_eq!(tree.predecessor(&5), None);
+    assert_eq!(tree.predecessor(&6), five);
+    assert_eq!(tree.successor(&5), None);
+    assert_eq!(tree.successor(&4), five);
 }
 
 #[test]
-fn records_baseline_node_size() {
-    eprintln!("size_of::<Node>() = {}", size_of::<Node>());
-    assert!(size_of::<Node>() > 0);
+fn navigation_queries_match_btree_map() {
+    let mut tree = Tree::new();
+    let mut model = BTreeMap::new();
+    let mut state = 0x4d59_5df4_d0f3_3173_u64;
+
+    for _ in 0..300 {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let key = ((state % 401) as i32) - 200;
+        tree.insert(key, key.to_string());
+        model.insert(key, key.to_string());
+    }
+
+    assert_eq!(tree.first(), model.iter().next());
+    assert_eq!(tree.last(), model.iter().next_back());
+    for probe in -205..=205 {
+        assert_eq!(tree.floor(&probe), model.range(..=probe).next_back());
+        assert_eq!(tree.ceiling(&probe), model.range(probe..).next());
+        assert_eq!(tree.predecessor(&probe), model.range(..probe).next_back());
+        assert_eq!(
+            tree.successor(&probe),
+            model
+                .range((Bound::Excluded(probe), Bound::Unbounded))
+                .next()
+        );
+    }
 }
 
-#[derive(Clone, Debug)]
-enum Operation {
-    Insert(i32, String),
-    Find(i32),
-    Has(i32),
-    Remove(i32),
+#[test]
+fn navigation_queries_follow_the_comparator() {
+    let mut tree = Tree::with_comparator(|left: &i32, right: &i32| right.cmp(left));
+    for key in [10, 20, 30] {
+        tree.insert(key, key);
+    }
+
+    assert_eq!(tree.first(), Some((&30, &30)));
+    assert_eq!(tree.last(), Some((&10, &10)));
+    assert_eq!(tree.floor(&25), Some((&30, &30)));
+    assert_eq!(tree.ceiling(&25), Some((&20, &20)));
+    assert_eq!(tree.predecessor(&20), Some((&30, &30)));
+    assert_eq!(tree.successor(&20), Some((&10, &10)));
 }
 
-fn operation_strategy() -> impl Strategy<Value = Operation> {
-    prop_oneof![
-        5 => ((-64_i32..=64), "[ -~]{0,16}")
-            .prop_map(|(key, value)| Operation::Insert(key, value)),
-        2 => (-80_i32..=80).prop_map(Operation::Find),
-        1 => (-80_i32..=80).prop_map(Operation::Has),
-        3 => (-80_i32..=80).prop_map(Operation::Remove),
-    ]
+#[test]
+fn order_statistics_on_empty_tree() {
+    let tree = Tree::<i32, String>::new();
+
+    assert_eq!(tree.rank(&0), None);
+    assert_eq!(tree.select(0), None);
+    assert_eq!(tree.count_less_than(&0), 0);
 }
 
-proptest! {
-    #![proptest_config(ProptestConfig {
-        cases: 256,
-        max_shrink_iters: 10_000,
-        ..ProptestConfig::default()
-    })]
+#[test]
+fn order_statistics_match_sorted_positions() {
+    let mut tree = Tree::new();
+    let mut model = BTreeMap::new();
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
 
-    #[test]
-    fn operation_sequences_match_btree_map(
-        operations in prop::collection::vec(operation_strategy(), 1..128)
-    ) {
-        let mut tree = Tree::new();
-        let mut model = BTreeMap::new();
+    for index in 0..2_000 {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let key = ((state % 601) as i32) - 300;
 
-        for operation in operations {
-            match operation {
-                Operation::Insert(key, value) => {
-                    tree.insert(key, value.clone());
-                    model.insert(key, value);
-                }
-                Operation::Find(key) => {
-                    prop_assert_eq!(tree.find(key), model.get(&key).map(String::as_str));
-                }
-                Operation::Has(key) => {
-                    prop_assert_eq!(tree.has(key), model.contains_key(&key));
-                }
-                Operation::Remove(key) => {
-                    prop_assert_eq!(tree.remove(key), model.remove(&key));
+        match state % 3 {
+            0 => assert_eq!(tree.remove(&key), model.remove(&key)),
+            _ => {
+                tree.insert(key, index);
+                model.insert(key, index);
+            }
+        }
+
+        if index % 100 == 0 {
+            assert_ordered(&tree);
+            let sorted: Vec<_> = model.iter().collect();
+            for (position, &(key, value)) in sorted.iter().enumerate() {
+                assert_eq!(tree.rank(key), Some(position));
+                assert_eq!(tree.select(position), Some((key, value)));
+            }
+            assert_eq!(tree.select(sorted.len()), None);
+            for probe in -305..=305 {
+                let below = model.range(..probe).count();
+                assert_eq!(tree.count_less_than(&probe), below);
+                assert_eq!(
+                    tree.rank(&probe),
+                    model.contains_key(&probe).then_some(below)
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn range_on_empty_and_inverted_bounds() {
+    let mut tree = Tree::new();
+    assert_eq!(tree.range(..).next(), None);
+    assert_eq!(tree.count_range(..), 0);
+
+    for key in 0..10 {
+        tree.insert(key, key);
+    }
+    let inverted = (Bound::Included(7), Bound::Excluded(3));
+    assert_eq!(tree.range(inverted).next(), None);
+    assert_eq!(tree.count_range(inverted), 0);
+    assert_eq!(tree.range(5..5).len(), 0);
+    assert_eq!(
+        tree.range((Bound::Excluded(5), Bound::Excluded(6))).len(),
+        0
+    );
+    assert_eq!(tree.range(5..=5).collect::<Vec<_>>(), [(&5, &5)]);
+}
+
+#[test]
+fn range_matches_btree_map() {
+    let mut tree = Tree::new();
+    let mut model = BTreeMap::new();
+    let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+
+    for _ in 0..200 {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let key = ((state % 161) as i32) - 80;
+        tree.insert(key, key.to_string());
+        model.insert(key, key.to_string());
+    }
+
+    let bounds = |key| [Bound::Included(key), Bound::Excluded(key), Bound::Unbounded];
+    for lo in (-85..=85).step_by(7) {
+        for hi in (lo..=85).step_by(5) {
+            for start in bounds(lo) {
+                for end in bounds(hi) {
+                    if matches!((start, end), (Bound::Excluded(_), Bound::Excluded(_))) && lo == hi
+                    {
+                        continue;
+                    }
+                    let expected: Vec<_> = model.range((start, end)).collect();
+                    let range = tree.range((start, end));
+                    assert_eq!(range.len(), expected.len());
+                    assert_eq!(tree.count_range((start, end)), expected.len());
+                    assert_eq!(range.clone().collect::<Vec<_>>(), expected);
+                    assert_eq!(
+                        range.rev().collect::<Vec<_>>(),
+                        expected.iter().rev().copied().collect::<Vec<_>>()
+                    );
                 }
             }
+        }
+    }
+}
 
-            let entries = assert_valid(&tree);
-            let expected: Vec<_> = model
-                .iter()
-                .map(|(&key, value)| (key, value.clone()))
-                .collect();
+#[test]
+fn range_ends_meet_without_crossing() {
+    let mut tree = Tree::new();
+    for key in 0..100 {
+        tree.insert(key, ());
+    }
+
+    let mut range = tree.range(10..90);
+    let mut keys = Vec::new();
+    while let Some((&key, _)) = range.next() {
+        keys.push(key);
+        if let Some((&key, _)) = range.next_back() {
+            keys.push(key);
+        }
+        assert_eq!(range.len(), 80 - keys.len());
+    }
+    assert_eq!(range.next_back(), None);
+    keys.sort_unstable();
+    assert_eq!(keys, (10..90).collect::<Vec<_>>());
+}
+
+#[test]
+fn range_follows_the_comparator() {
+    let mut tree = Tree::with_comparator(|left: &i32, right: &i32| right.cmp(left));
+    for key in 0..10 {
+        tree.insert(key, key);
+    }
+
+    let keys: Vec<_> = tree
+        .range((Bound::Included(7), Bound::Included(3)))
+        .map(|(&key, _)| key)
+        .collect();
+    assert_eq!(keys, [7, 6, 5, 4, 3]);
+    assert_eq!(tree.count_range(..5), 4);
+}
+
+#[test]
+fn iterators_on_empty_tree() {
+    let mut tree = Tree::<i32, String>::new();
+
+    assert_eq!(tree.iter().len(), 0);
+    assert_eq!(tree.iter().next(), None);
+    assert_eq!(tree.keys().next_back(), None);
+    assert_eq!(tree.values().next(), None);
+    assert_eq!(tree.iter_mut().next(), None);
+    assert_eq!(tree.values_mut().next_back(), None);
+    assert_eq!(tree.into_iter().next(), None);
+}
+
+#[test]
+fn iterators_match_btree_map_from_both_ends() {
+    let mut tree = Tree::new();
+    let mut model = BTreeMap::new();
+    let mut state = 0x5851_f42d_4c95_7f2d_u64;
+
+    for index in 0..500 {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let key = ((state % 701) as i32) - 350;
+        tree.insert(key, index);
+        model.insert(key, index);
+    }
+
+    assert_eq!(
+        tree.iter().collect::<Vec<_>>(),
+        model.iter().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        tree.iter().rev().collect::<Vec<_>>(),
+        model.iter().rev().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        tree.keys().collect::<Vec<_>>(),
+        model.keys().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        tree.values().rev().collect::<Vec<_>>(),
+        model.values().rev().collect::<Vec<_>>()
+    );
+    assert_eq!((&tree).into_iter().len(), model.len());
+
+    let mut entries = tree.iter();
+    let mut expected = model.iter();
+    while entries.len() > 0 {
+        assert_eq!(entries.next(), expected.next());
+        assert_eq!(entries.next_back(), expected.next_back());
+        assert_eq!(entries.len(), expected.len());
+    }
+    assert_eq!(entries.next(), None);
+    assert_eq!(entries.next_back(), None);
+
+    let mut owned = tree.into_iter();
+    let mut expected = model.into_iter();
+    assert_eq!(owned.len(), expected.len());
+    while let Some(entry) = owned.next_back() {
+        assert_eq!(Some(entry), expected.next_back());
+        assert_eq!(owned.next(), expected.next());
+        assert_eq!(owned.len(), expected.len());
+    }
+    assert_eq!(expected.next(), None);
+}
+
+#[test]
+fn mutable_iterators_update_values_in_place() {
+    let mut tree = Tree::new();
+    for key in 0..100 {
+        tree.insert(key, key * 10);
+    }
+
+    for value in tree.values_mut().rev().take(10) {
+        *value = -*value;
+    }
+    for (key, value) in &mut tree {
+        if key % 2 == 0 {
+            *value += 1;
+        }
+    }
+    let mut iter_mut = tree.iter_mut();
+    assert_eq!(iter_mut.len(), 100);
+    assert_eq!(iter_mut.next_back().map(|(&key, _)| key), Some(99));
+    assert_eq!(iter_mut.len(), 99);
+
+    let entries: Vec<_> = assert_ordered(&tree)
+        .into_iter()
+        .map(|(&key, &value)| (key, value))
+        .collect();
+    let expected: Vec<_> = (0..100)
+        .map(|key| {
+            let value = if key >= 90 { -key * 10 } else { key * 10 };
+            (key, value + i32::from(key % 2 == 0))
+        })
+        .collect();
+    assert_eq!(entries, expected);
+}
+
+#[test]
+fn owning_iterator_drops_unvisited_entries() {
+    let mut tree = Tree::new();
+    let tracker = Rc::new(());
+    for key in 0..50 {
+        tree.insert(key, Rc::clone(&tracker));
+    }
+
+    let mut owned = tree.into_iter();
+    assert_eq!(owned.next().map(|(key, _)| key), Some(0));
+    assert_eq!(owned.next_back().map(|(key, _)| key), Some(49));
+    drop(owned);
+    assert_eq!(Rc::strong_count(&tracker), 1);
+}
+
+#[test]
+fn builds_perfectly_balanced_trees_from_sorted_input() {
+    for len in [0_usize, 1, 2, 3, 7, 8, 1_000, 1_023, 1_024] {
+        let tree = Tree::from_sorted_iter((0..len).map(|key| (key, key * 2))).unwrap();
+        let entries = assert_ordered(&tree);
+
+        assert_eq!(entries.len(), len);
+        assert!(entries
+            .iter()
+            .enumerate()
+            .all(|(index, &(&key, &value))| { key == index && value == index * 2 }));
+        let minimum_height = usize::BITS - len.leading_zeros();
+        assert_eq!(Node::height(&tree.root), minimum_height as i32);
+    }
+}
+
+#[test]
+fn sorted_construction_rejects_out_of_order_input() {
+    assert_eq!(
+        Tree::from_sorted_iter([(1, 'a'), (3, 'b'), (2, 'c')]).err(),
+        Some(UnsortedError { index: 2 })
+    );
+    assert_eq!(
+        Tree::from_sorted_iter([(1, 'a'), (1, 'b')]).err(),
+        Some(UnsortedError { index: 1 })
+    );
+    assert_eq!(
+        Tree::from_sorted_iter_dedup([(1, 'a'), (2, 'b'), (0, 'c')]).err(),
+        Some(UnsortedError { index: 2 })
+    );
+    assert_eq!(
+        UnsortedError { index: 2 }.to_string(),
+        "entry 2 is out of ascending key order"
+    );
+
+    let tree =
+        Tree::from_sorted_iter_dedup([(1, 'a'), (1, 'b'), (2, 'c'), (2, 'd'), (2, 'e')]).unwrap();
+    assert_eq!(assert_ordered(&tree), [(&1, &'b'), (&2, &'e')]);
+}
+
+#[test]
+fn collecting_matches_repeated_insertion() {
+    let mut state = 0x1405_7b7e_f767_814f_u64;
+    let mut input = Vec::new();
+    for index in 0..1_000 {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        input.push((((state % 401) as i32) - 200, index));
+    }
+
+    let mut sorted = input.clone();
+    sorted.sort_unstable();
+    sorted.dedup_by_key(|&mut (key, _)| key);
+    for entries in [input.clone(), sorted, Vec::new()] {
+        let model: BTreeMap<_, _> = entries.iter().copied().collect();
+        let tree: Tree<i32, i32> = entries.iter().copied().collect();
+        let expected: Vec<_> = model.iter().collect();
+        assert_eq!(assert_ordered(&tree), expected);
+
+        let mut extended = Tree::new();
+        extended.extend(entries.iter().copied());
+        assert_eq!(assert_ordered(&extended), expected);
+
+        let mut model = model;
+        let mut extended = Tree::new();
+        extended.insert(0, -1);
+        model.insert(0, -1);
+        extended.extend(entries.iter().copied());
+        model.extend(entries.iter().copied());
+        assert_eq!(assert_ordered(&extended), model.iter().collect::<Vec<_>>());
+    }
+
+    let reversed: Tree<i32, i32, ReverseOrder> = input.iter().copied().collect();
+    let model: BTreeMap<_, _> = input
+        .iter()
+        .map(|&(key, value)| (Reverse(key), value))
+        .collect();
+    assert_eq!(
+        assert_ordered(&reversed),
+        model
+            .iter()
+            .map(|(Reverse(key), value)| (key, value))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[derive(Default)]
+struct ReverseOrder;
+
+impl Comparator<i32> for ReverseOrder {
+    fn compare(&self, left: &i32, right: &i32) -> Ordering {
+        right.cmp(left)
+    }
+}
+
+#[test]
+fn split_off_and_append_edge_cases() {
+    let mut tree: Tree<i32, i32> = (0..10).map(|key| (key, key)).collect();
+
+    let upper = tree.split_off(&-5);
+    assert!(assert_ordered(&tree).is_empty());
+    assert_eq!(assert_ordered(&upper).len(), 10);
+
+    let mut tree = upper;
+    let upper = tree.split_off(&50);
+    assert_eq!(assert_ordered(&tree).len(), 10);
+    assert!(upper.is_empty());
+
+    let mut upper = tree.split_off(&4);
+    assert_eq!(tree.keys().copied().collect::<Vec<_>>(), [0, 1, 2, 3]);
+    assert_eq!(
+        upper.keys().copied().collect::<Vec<_>>(),
+        [4, 5, 6, 7, 8, 9]
+    );
+
+    upper.append(&mut tree);
+    assert!(assert_ordered(&tree).is_empty());
+    assert_eq!(assert_ordered(&upper).len(), 10);
+
+    let mut empty = Tree::new();
+    empty.append(&mut upper);
+    assert_eq!(assert_ordered(&empty).len(), 10);
+    assert!(upper.is_empty());
+    empty.append(&mut upper);
+    assert_eq!(assert_ordered(&empty).len(), 10);
+}
+
+#[test]
+fn appending_trees_of_very_different_heights_stays_balanced() {
+    for (small, large) in [(1, 5_000), (3, 4_096), (100, 10_000)] {
+        let mut low: Tree<i32, ()> = (0..small).map(|key| (key, ())).collect();
+        let mut high: Tree<i32, ()> = (small..large).map(|key| (key, ())).collect();
+        low.append(&mut high);
+        assert_eq!(assert_ordered(&low).len(), large as usize);
+
+        let mut low: Tree<i32, ()> = (0..large - small).map(|key| (key, ())).collect();
+        let mut high: Tree<i32, ()> = (large - small..large).map(|key| (key, ())).collect();
+        high.append(&mut low);
+        assert_eq!(assert_ordered(&high).len(), large as usize);
+    }
+}
+
+#[test]
+fn union_keeps_this_trees_keys_and_merges_in_argument_order() {
+    let compare = |left: &String, right: &String| left.to_lowercase().cmp(&right.to_lowercase());
+    let mut left = Tree::with_comparator(compare);
+    let mut right = Tree::with_comparator(compare);
+    left.insert("Apple".to_owned(), 1);
+    left.insert("cherry".to_owned(), 3);
+    right.insert("APPLE".to_owned(), 10);
+    right.insert("banana".to_owned(), 20);
+
+    let mut calls = Vec::new();
+    let union = left.union(right, |key, mine, theirs| {
+        calls.push((key.clone(), mine, theirs));
+        mine * 100 + theirs
+    });
+    assert_eq!(calls, [("Apple".to_owned(), 1, 10)]);
+    assert_eq!(
+        assert_ordered(&union),
+        [
+            (&"Apple".to_owned(), &110),
+            (&"banana".to_owned(), &20),
+            (&"cherry".to_owned(), &3)
+        ]
+    );
+}
+
+#[test]
+fn entries_update_present_keys_and_insert_absent_ones() {
+    let mut tree: Tree<i32, String> = Tree::new();
+    assert!(tree.get_mut(&1).is_none());
+
+    match tree.entry(5) {
+        Entry::Vacant(entry) => {
+            assert_eq!(entry.key(), &5);
+            entry.insert("five".to_owned()).push('!');
+        }
+        Entry::Occupied(_) => panic!("an empty tree has no occupied entries"),
+    }
+    assert_eq!(tree.find(&5).map(String::as_str), Some("five!"));
+
+    match tree.entry(5) {
+        Entry::Occupied(mut entry) => {
+            assert_eq!(entry.key(), &5);
+            assert_eq!(entry.get(), "five!");
+            assert_eq!(entry.insert("FIVE".to_owned()), "five!");
+        }
+        Entry::Vacant(_) => panic!("5 was inserted"),
+    }
+
+    let mut calls = 0;
+    tree.entry(5).or_insert_with(|| {
+        calls += 1;
+        String::new()
+    });
+    assert_eq!(calls, 0);
+    tree.entry(3)
+        .and_modify(|value| value.push('?'))
+        .or_insert_with(|| "three".to_owned());
+    tree.entry(5)
+        .and_modify(|value| value.push('?'))
+        .or_default();
+    tree.entry(7).or_default().push_str("seven");
+    *tree.get_mut(&7).expect("7 was inserted") += "!";
+    if let Entry::Vacant(entry) = tree.entry(9) {
+        assert_eq!(entry.into_key(), 9);
+    }
+
+    assert_eq!(
+        assert_valid(&tree),
+        [
+            (3, "three".to_owned()),
+            (5, "FIVE?".to_owned()),
+            (7, "seven!".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn vacant_entries_insert_at_every_position_and_stay_balanced() {
+    let mut tree = Tree::new();
+    for key in (0..512).step_by(2).chain((1..512).step_by(2).rev()) {
+        *tree.entry(key).or_insert(0) += key;
+        *tree.entry(key).or_insert(0) += 1;
+    }
+
+    let entries = assert_ordered(&tree);
+    assert_eq!(entries.len(), 512);
+    for (index, (&key, &value)) in entries.into_iter().enumerate() {
+        assert_eq!(key, index as i32);
+        assert_eq!(value, key + 1);
+    }
+}
+
+#[test]
+fn insertion_reports_replaced_values_and_try_insert_refuses_to_overwrite() {
+    let mut tree = Tree::new();
+    assert_eq!(tree.insert(1, "one".to_owned()), None);
+    assert_eq!(tree.insert(1, "uno".to_owned()), Some("one".to_owned()));
+    assert_eq!(tree.len(), 1);
+
+    *tree.try_insert(2, "two".to_owned()).expect("2 is absent") += "!";
+    let OccupiedError { entry, value } = tree
+        .try_insert(1, "ein".to_owned())
+        .expect_err("1 is present");
+    assert_eq!((entry.key(), entry.get().as_str()), (&1, "uno"));
+    assert_eq!(value, "ein");
+    assert_eq!(
+        tree.try_insert(1, "ein".to_owned())
+            .expect_err("1 is present")
+            .to_string(),
+        r#"key 1 is already present with value "uno""#
+    );
+
+    assert_eq!(
+        assert_valid(&tree),
+        [(1, "uno".to_owned()), (2, "two!".to_owned())]
+    );
+}
+
+#[test]
+fn bulk_removals_on_empty_and_full_trees() {
+    let mut tree: Tree<i32, String> = Tree::new();
+    assert_eq!(tree.pop_first(), None);
+    assert_eq!(tree.pop_last(), None);
+    assert_eq!(tree.drain_range(..).next(), None);
+    tree.retain(|_, _| unreachable!("an empty tree has no entries"));
+    tree.clear();
+    assert_valid(&tree);
+
+    for key in 0..100 {
+        tree.insert(key, key.to_string());
+    }
+    assert_eq!(tree.pop_first(), Some((0, "0".to_owned())));
+    assert_eq!(tree.pop_last(), Some((99, "99".to_owned())));
+    assert_eq!(tree.len(), 98);
+
+    let drained: Vec<_> = tree.drain_range(10..=19).map(|(key, _)| key).collect();
+    assert_eq!(drained, (10..=19).collect::<Vec<_>>());
+    assert_eq!(
+        tree.drain_range((Bound::Excluded(50), Bound::Excluded(40)))
+            .len(),
+        0
+    );
+    assert_eq!(tree.drain_range(90..).len(), 9);
+    assert_eq!(tree.len(), 79);
+
+    let mut visited = Vec::new();
+    tree.retain(|&key, value| {
+        visited.push(key);
+        value.push('!');
+        key % 3 == 0
+    });
+    assert_eq!(visited.len(), 79);
+    assert!(visited.windows(2).all(|pair| pair[0] < pair[1]));
+    let entries = assert_valid(&tree);
+    assert!(entries
+        .iter()
+        .all(|(key, value)| key % 3 == 0 && *value == format!("{key}!")));
+    assert_eq!(tree.len(), entries.len());
+
+    tree.clear();
+    assert!(tree.is_empty());
+    assert_valid(&tree);
+    tree.insert(1, "one".to_owned());
+    assert_eq!(assert_valid(&tree), [(1, "one".to_owned())]);
+}
+
+#[test]
+fn stats_describe_the_tree_shape() {
+    let empty: Tree<i32, String> = Tree::new();
+    assert_eq!(empty.height(), 0);
+    assert_eq!(
+        empty.stats(),
+        Stats {
+            len: 0,
+            height: 0,
+            height_bound: 0,
+            node_bytes: 0,
+            average_depth: 0.0,
+        }
+    );
+
+    let perfect = Tree::from_sorted_iter((1..=7).map(|key| (key, ()))).expect("keys ascend");
+    let stats = perfect.stats();
+    assert_eq!((stats.len, stats.height, stats.height_bound), (7, 3, 4));
+    assert_eq!(stats.node_bytes, 7 * size_of::<Node<i32, ()>>());
+    assert_eq!(stats.average_depth, 17.0 / 7.0);
+
+    // The sparsest AVL trees of each height hold 1, 2, 4, 7, 12, 20, ... nodes.
+    let bounds: Vec<_> = [1, 2, 3, 4, 6, 7, 11, 12, 19, 20]
+        .into_iter()
+        .map(Tree::<i32, ()>::height_bound)
+        .collect();
+    assert_eq!(bounds, [1, 2, 2, 3, 3, 4, 4, 5, 5, 6]);
+
+    let mut tree = Tree::new();
+    for key in 0..1000 {
+        tree.insert(key, ());
+        assert!(tree.height() <= Tree::<i32, ()>::height_bound(tree.len()));
+    }
+    assert_eq!(tree.stats().height, tree.height());
+}
+
+#[test]
+fn validate_reports_the_first_broken_invariant() {
+    fn tree() -> Tree<i32, ()> {
+        (1..=7).map(|key| (key, ())).collect()
+    }
+    fn root(tree: &mut Tree<i32, ()>) -> &mut Node<i32, ()> {
+        tree.root.as_deref_mut().expect("the tree is not empty")
+    }
+
+    assert_eq!(tree().validate(), Ok(()));
+    assert_eq!(Tree::<i32, ()>::new().validate(), Ok(()));
+
+    let mut swapped = tree();
+    root(&mut swapped)
+        .left
+        .as_deref_mut()
+        .expect("4 has a left child")
+        .key = 5;
+    assert_eq!(
+        swapped.validate(),
+        Err(InvariantViolation::Unordered {
+            key: 5,
+            ancestor: 4,
+        })
+    );
+
+    let mut misplaced = tree();
+    let left = root(&mut misplaced)
+        .left
+        .as_deref_mut()
+        .expect("4 has a left child");
+    left.right.as_deref_mut().expect("2 has a right child").key = 1;
+    assert_eq!(
+        misplaced
+            .validate()
+            .map_err(|violation| violation.to_string()),
+        Err("key 1 is on the wrong side of ancestor 2".to_owned())
+    );
+
+    let mut tall = tree();
+    root(&mut tall).height = 4;
+    assert_eq!(
+        tall.validate(),
+        Err(InvariantViolation::HeightMismatch {
+            key: 4,
+            stored: 4,
+            actual: 3,
+        })
+    );
+
+    let mut lopsided = tree();
+    root(&mut lopsided).right = None;
+    root(&mut lopsided).update();
+    lopsided.len = 4;
+    assert_eq!(
+        lopsided.validate(),
+        Err(InvariantViolation::Unbalanced {
+            key: 4,
+            balance_factor: 2,
+        })
+    );
+
+    let mut oversized = tree();
+    root(&mut oversized).size = 8;
+    assert_eq!(
+        oversized.validate(),
+        Err(InvariantViolation::SizeMismatch {
+            key: 4,
+            stored: 8,
+            actual: 7,
+        })
+    );
+
+    let mut miscounted = tree();
+    miscounted.len = 6;
+    let violation = miscounted.validate().expect_err("len is wrong");
+    assert_eq!(
+        violation,
+        InvariantViolation::LenMismatch {
+            stored: 6,
+            actual: 7,
+        }
+    );
+    assert_eq!(
+        violation.to_string(),
+        "tree length is 6 but it holds 7 nodes"
+    );
+}
+
+#[test]
+fn dump_preserves_exact_legacy_format() {
+    let mut tree = Tree::new();
+    tree.insert(3, String::new());
+    tree.insert(1, "quotes: ' and \"".to_owned());
+    tree.insert(2, "commas, braces { }".to_owned());
+    tree.insert(4, "Unicode: Привіт 🌳".to_owned());
+
+    assert_eq!(
+        tree.dump(),
+        "{ key: 1, value: 'quotes: ' and \"' }, { key: 2, value: 'commas, braces { }' }, { key: 3, value: '' }, { key: 4, value: 'Unicode: Привіт 🌳' }"
+    );
+    assert_valid(&tree);
+}
+
+#[test]
+fn json_escapes_values_like_json_stringify() {
+    assert_eq!(Tree::new().to_json(), "[]");
+
+    let tree: Tree<i32, String> = [
+        (2, "quote \" and backslash \\".to_owned()),
+        (-1, "{ key: 1, value: 'x' }, ".to_owned()),
+        (i32::MIN, "\u{8}\t\n\u{c}\r\u{0}\u{1f}\u{7f}".to_owned()),
+        (i32::MAX, "caf\u{e9} \u{1f333} \u{2028}".to_owned()),
+    ]
+    .into_iter()
+    .collect();
+    let json = tree.to_json();
+    assert_eq!(
+        json,
+        concat!(
+            "[[-2147483648,\"\\b\\t\\n\\f\\r\\u0000\\u001f\u{7f}\"],",
+            r#"[-1,"{ key: 1, value: 'x' }, "],"#,
+            r#"[2,"quote \" and backslash \\"],"#,
+            "[2147483647,\"caf\u{e9} \u{1f333} \u{2028}\"]]",
+        )
+    );
+
+    let parsed = Tree::from_json(&json).expect("to_json output parses");
+    assert_eq!(assert_valid(&parsed), assert_valid(&tree));
+    assert_eq!(parsed.dump(), tree.dump());
+}
+
+#[test]
+fn json_parsing_accepts_any_valid_layout_and_rejects_malformed_input() {
+    let parsed = Tree::from_json(
+        " [ [3 , \"\\u00e9\\ud83c\\udf33\\/\" ] ,\n\t[-0,\"zero\"],[3,\"last\"] ] ",
+    )
+    .expect("valid JSON parses");
+    assert_eq!(
+        assert_valid(&parsed),
+        [(0, "zero".to_owned()), (3, "last".to_owned())]
+    );
+
+    let error = |json: &str| Tree::from_json(json).err();
+    for (json, offset, expected) in [
+        ("", 0, "'['"),
+        ("[", 1, "'[' opening a pair"),
+        ("[]x", 2, "the end of the input"),
+        ("[[1,\"a\"],]", 9, "'[' opening a pair"),
+        ("[[1,\"a\"] [2,\"b\"]]", 9, "',' or ']'"),
+        ("[[1.5,\"a\"]]", 2, "an integer key"),
+        ("[[01,\"a\"]]", 2, "an integer key"),
+        ("[[-,\"a\"]]", 2, "an integer key"),
+        ("[[2147483648,\"a\"]]", 2, "a key in the i32 range"),
+        ("[[1,2]]", 4, "a string value"),
+        ("[[1,\"a]]", 8, "'\"' closing the string"),
+        ("[[1,\"\n\"]]", 5, "an escaped control character"),
+        ("[[1,\"\\x\"]]", 6, "a valid escape character"),
+        ("[[1,\"\\u12\"]]", 7, "four hexadecimal digits"),
+        ("[[1,\"\\ud83c\"]]", 11, "a low surrogate escape"),
+        (
+            "[[1,\"\\udf33\"]]",
+            7,
+            "a high surrogate before a low surrogate",
+        ),
+        ("[[1,\"a\",2]]", 7, "']' closing the pair"),
+    ] {
+        assert_eq!(
+            error(json),
+            Some(JsonError { offset, expected }),
+            "parsing {json:?}"
+        );
+    }
+    assert_eq!(
+        error("[[1 \"a\"]]").map(|error| error.to_string()),
+        Some("expected ',' after the key at byte 4".to_owned())
+    );
+}
+
+fn serialized(tree: &Tree<i32, String>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    tree.serialize_into(&mut bytes)
+        .expect("writing to a Vec cannot fail");
+    bytes
+}
+
+/// Replaces the trailing checksum so forged payloads get past it.
+fn reseal(bytes: &mut Vec<u8>) {
+    bytes.truncate(bytes.len() - 4);
+    let checksum = crc32(bytes);
+    bytes.extend(checksum.to_le_bytes());
+}
+
+#[test]
+fn binary_format_has_the_documented_layout() {
+    assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+
+    let tree: Tree<i32, String> = [(-1, "é".to_owned()), (7, String::new())]
+        .into_iter()
+        .collect();
+    let bytes = serialized(&tree);
+    let mut expected = b"AVLT\x01".to_vec();
+    expected.extend(2_u64.to_le_bytes());
+    expected.extend((-1_i32).to_le_bytes());
+    expected.extend(2_u32.to_le_bytes());
+    expected.extend("é".as_bytes());
+    expected.extend(7_i32.to_le_bytes());
+    expected.extend(0_u32.to_le_bytes());
+    expected.extend(crc32(&expected).to_le_bytes());
+    assert_eq!(bytes, expected);
+
+    let mut reader = &bytes[..];
+    let decoded = Tree::deserialize_from(&mut reader).expect("the bytes are valid");
+    assert!(reader.is_empty());
+    assert_eq!(assert_valid(&decoded), assert_valid(&tree));
+
+    let empty = Tree::deserialize_from(&serialized(&Tree::new())[..]).expect("the bytes are valid");
+    assert!(empty.is_empty());
+}
+
+#[test]
+fn deserialization_rejects_truncated_and_corrupt_input() {
+    let tree: Tree<i32, String> = (0..20).map(|key| (key, format!("value {key}"))).collect();
+    let bytes = serialized(&tree);
+    let decode = |bytes: &[u8]| Tree::deserialize_from(bytes).err();
+
+    for length in 0..bytes.len() {
+        assert!(
+            matches!(decode(&bytes[..length]), Some(DeserializeError::Truncated)),
+            "prefix of {length} bytes"
+        );
+    }
+
+    let mut magic = bytes.clone();
+    magic[0] = b'X';
+    assert!(matches!(decode(&magic), Some(DeserializeError::BadMagic)));
+
+    let mut version = bytes.clone();
+    version[4] = 2;
+    assert!(matches!(
+        decode(&version),
+        Some(DeserializeError::UnsupportedVersion(2))
+    ));
+
+    let mut flipped = bytes.clone();
+    flipped[30] ^= 0x20;
+    let error = decode(&flipped).expect("a flipped bit is detected");
+    assert!(matches!(error, DeserializeError::ChecksumMismatch { .. }));
+    assert!(error
+        .to_string()
+        .starts_with("checksum mismatch: stored 0x"));
+
+    let mut not_utf8 = bytes.clone();
+    let first_value = 5 + 8 + 4 + 4;
+    not_utf8[first_value] = 0xff;
+    reseal(&mut not_utf8);
+    assert!(matches!(
+        decode(&not_utf8),
+        Some(DeserializeError::InvalidUtf8 { index: 0 })
+    ));
+
+    let mut unsorted = bytes.clone();
+    let second_key = first_value + "value 0".len();
+    unsorted[second_key..second_key + 4].copy_from_slice(&0_i32.to_le_bytes());
+    reseal(&mut unsorted);
+    let error = decode(&unsorted).expect("a repeated key is detected");
+    assert!(matches!(error, DeserializeError::Unsorted { index: 1 }));
+    assert_eq!(error.to_string(), "entry 1 is out of ascending key order");
+
+    struct Failing;
+    impl Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("disk on fire"))
+        }
+    }
+    let error = Tree::deserialize_from(Failing)
+        .err()
+        .expect("the reader fails");
+    assert!(matches!(error, DeserializeError::Io(_)));
+    assert_eq!(
+        error.to_string(),
+        "failed to read the serialized tree: disk on fire"
+    );
+}
+
+/// A fresh directory under the system temporary directory, removed on drop.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("avl-tree-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir(&path).expect("the temporary directory is writable");
+        Self(path)
+    }
+
+    fn file_names(&self) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(&self.0)
+            .expect("the directory is readable")
+            .map(|entry| {
+                let entry = entry.expect("the directory is readable");
+                entry.file_name().to_string_lossy().into_owned()
+            })
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn save_and_load_round_trip_through_a_file() {
+    let directory = TempDir::new("round-trip");
+    let path = directory.0.join("tree.bin");
+    let tree: Tree<i32, String> = (0..100).map(|key| (key, key.to_string())).collect();
+
+    tree.save(&path).expect("the directory is writable");
+    assert_eq!(fs::read(&path).expect("the file exists"), serialized(&tree));
+    let loaded = Tree::load(&path).expect("the file is valid");
+    assert_eq!(assert_valid(&loaded), assert_valid(&tree));
+    assert_eq!(directory.file_names(), ["tree.bin"]);
+
+    let smaller: Tree<i32, String> = [(7, "seven".to_owned())].into_iter().collect();
+    smaller.save(&path).expect("the file is replaced");
+    assert_eq!(
+        fs::read(&path).expect("the file exists"),
+        serialized(&smaller)
+    );
+    assert_eq!(directory.file_names(), ["tree.bin"]);
+
+    Tree::new().save(&path).expect("the file is replaced");
+    assert!(Tree::load(&path).expect("the file is valid").is_empty());
+}
+
+#[test]
+fn load_rejects_missing_and_malformed_files() {
+    let directory = TempDir::new("malformed");
+    let path = directory.0.join("tree.bin");
+    let error = Tree::load(&path).err().expect("the file is missing");
+    assert!(
+        matches!(&error, DeserializeError::Io(error) if error.kind() == io::ErrorKind::NotFound)
+    );
+
+    let mut bytes = serialized(&[(1, "one".to_owned())].into_iter().collect());
+    fs::write(&path, &bytes[..bytes.len() - 1]).expect("the directory is writable");
+    let error = Tree::load(&path).err().expect("the file is truncated");
+    assert!(matches!(error, DeserializeError::Truncated));
+
+    bytes.extend([0, 0]);
+    fs::write(&path, &bytes).expect("the directory is writable");
+    let error = Tree::load(&path)
+        .err()
+        .expect("the file has trailing bytes");
+    assert!(matches!(
+        error,
+        DeserializeError::TrailingData { length: 2 }
+    ));
+    assert_eq!(error.to_string(), "2 unexpected bytes after the checksum");
+}
+
+#[test]
+fn failed_saves_leave_no_temporary_files() {
+    let directory = TempDir::new("failed-save");
+    let tree: Tree<i32, String> = [(1, "one".to_owned())].into_iter().collect();
+
+    let missing = directory.0.join("missing").join("tree.bin");
+    assert!(tree.save(&missing).is_err());
+    let occupied = directory.0.join("occupied");
+    fs::create_dir(&occupied).expect("the directory is writable");
+    fs::write(occupied.join("inner"), "keep").expect("the directory is writable");
+    assert!(
+        tree.save(&occupied).is_err(),
+        "a directory cannot be replaced"
+    );
+    assert_eq!(directory.file_names(), ["occupied"]);
+
+    let error = tree.save("/").expect_err("the root is not a file");
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+}
+
+/// The checksum and length that open every log record.
+const FRAME_HEADER_BYTES: usize = 12;
+
+/// Returns the bytes that open every log file.
+fn log_header() -> Vec<u8> {
+    let mut header = LOG_MAGIC.to_vec();
+    header.push(LOG_VERSION);
+    header
+}
+
+fn durable_contents(directory: &TempDir) -> Vec<(i32, String)> {
+    let durable = DurableTree::open(&directory.0).expect("the durable tree opens");
+    assert_valid(durable.tree())
+}
+
+#[test]
+fn durable_tree_replays_its_log_after_reopening() {
+    let directory = TempDir::new("durable-replay");
+    let mut durable = DurableTree::open(&directory.0).expect("the directory is created");
+    assert!(durable.tree().is_empty());
+    assert!(durable.log().is_empty());
+    assert_eq!(durable.log().directory(), directory.0);
+
+    for key in 0..5 {
+        assert_eq!(durable.insert(key, key.to_string()).unwrap(), None);
+    }
+    assert_eq!(
+        durable.insert(2, "two".to_owned()).unwrap(),
+        Some("2".to_owned())
+    );
+    assert_eq!(durable.remove(&0).unwrap(), Some("0".to_owned()));
+    let logged = durable.log().len();
+    assert_eq!(durable.remove(&0).unwrap(), None);
+    assert_eq!(durable.log().len(), logged, "absent keys are not logged");
+    let expected = assert_valid(durable.tree());
+    drop(durable);
+    assert_eq!(directory.file_names(), [LOG_FILE]);
+    assert_eq!(durable_contents(&directory), expected);
+
+    let mut durable = DurableTree::open(&directory.0).expect("the durable tree opens");
+    durable.clear().unwrap();
+    durable.insert(9, "nine".to_owned()).unwrap();
+    drop(durable);
+    assert_eq!(durable_contents(&directory), [(9, "nine".to_owned())]);
+}
+
+#[test]
+fn durable_tree_compaction_snapshots_and_truncates_the_log() {
+    let directory = TempDir::new("durable-compact");
+    let mut durable = DurableTree::open(&directory.0).expect("the directory is created");
+    for key in 0..100 {
+        durable.insert(key, key.to_string()).unwrap();
+    }
+    durable.compact().expect("the snapshot is written");
+    assert!(durable.log().is_empty());
+    assert_eq!(fs::read(directory.0.join(LOG_FILE)).unwrap(), log_header());
+    let snapshot = Tree::load(directory.0.join(SNAPSHOT_FILE)).expect("the snapshot is valid");
+    assert_eq!(assert_valid(&snapshot), assert_valid(durable.tree()));
+
+    durable.remove(&50).unwrap();
+    durable.insert(100, "100".to_owned()).unwrap();
+    let expected = assert_valid(durable.tree());
+    drop(durable);
+    assert_eq!(directory.file_names(), [SNAPSHOT_FILE, LOG_FILE]);
+    assert_eq!(durable_contents(&directory), expected);
+
+    // A crash after the snapshot is replaced but before the log is truncated
+    // replays records the snapshot already reflects.
+    let log = fs::read(directory.0.join(LOG_FILE)).unwrap();
+    let mut durable = DurableTree::open(&directory.0).expect("the durable tree opens");
+    durable.compact().expect("the snapshot is written");
+    drop(durable);
+    fs::write(directory.0.join(LOG_FILE), log).unwrap();
+    assert_eq!(durable_contents(&directory), expected);
+}
+
+#[test]
+fn durable_tree_ignores_a_torn_final_record() {
+    let directory = TempDir::new("durable-torn");
+    let mut durable = DurableTree::open(&directory.0).expect("the directory is created");
+    durable.insert(1, "one".to_owned()).unwrap();
+    let complete = durable.log().len();
+    durable.insert(2, "two".to_owned()).unwrap();
+    drop(durable);
+
+    let path = directory.0.join(LOG_FILE);
+    let log = fs::read(&path).unwrap();
+    for length in complete as usize..log.len() {
+        fs::write(&path, &log[..length]).unwrap();
+        assert_eq!(durable_contents(&directory), [(1, "one".to_owned())]);
+        assert_eq!(
+            fs::metadata(&path).unwrap().len(),
+            complete,
+            "the torn tail is cut off"
+        );
+    }
+
+    let mut torn = log.clone();
+    *torn.last_mut().unwrap() ^= 1;
+    fs::write(&path, &torn).unwrap();
+    let mut durable = DurableTree::open(&directory.0).expect("a bad final checksum is torn");
+    assert_eq!(durable.log().len(), complete);
+    durable.insert(3, "three".to_owned()).unwrap();
+    drop(durable);
+    assert_eq!(
+        durable_contents(&directory),
+        [(1, "one".to_owned()), (3, "three".to_owned())]
+    );
+}
+
+#[test]
+fn durable_tree_rejects_corrupt_logs_and_snapshots() {
+    let directory = TempDir::new("durable-corrupt");
+    let mut durable = DurableTree::open(&directory.0).expect("the directory is created");
+    durable.insert(1, "one".to_owned()).unwrap();
+    let second = durable.log().len();
+    durable.insert(2, "two".to_owned()).unwrap();
+    durable.insert(3, "three".to_owned()).unwrap();
+    drop(durable);
+
+    let path = directory.0.join(LOG_FILE);
+    let log = fs::read(&path).unwrap();
+    let mut corrupt = log.clone();
+    corrupt[second as usize + FRAME_HEADER_BYTES] ^= 1;
+    fs::write(&path, &corrupt).unwrap();
+    let error = DurableTree::open(&directory.0)
+        .err()
+        .expect("a damaged record before the last one is corrupt");
+    assert!(matches!(error, DurableError::CorruptLog { offset } if offset == second));
+    assert_eq!(
+        error.to_string(),
+        format!("the log record at byte {second} is corrupt")
+    );
+    assert_eq!(
+        fs::read(&path).unwrap(),
+        corrupt,
+        "a corrupt log is left alone"
+    );
+
+    fs::write(&path, &log).unwrap();
+    fs::write(directory.0.join(SNAPSHOT_FILE), b"AVLT").unwrap();
+    let error = DurableTree::open(&directory.0)
+        .err()
+        .expect("the snapshot is truncated");
+    assert!(matches!(
+        error,
+        DurableError::Snapshot(DeserializeError::Truncated)
+    ));
+}
+
+#[test]
+fn durable_tree_rejects_a_damaged_frame_length_before_the_end() {
+    let directory = TempDir::new("durable-length");
+    let mut durable = DurableTree::open(&directory.0).expect("the directory is created");
+    durable.insert(1, "one".to_owned()).unwrap();
+    let second = durable.log().len();
+    for key in 2..=5 {
+        durable.insert(key, key.to_string()).unwrap();
+    }
+    let last = durable.log().len() - (FRAME_HEADER_BYTES + 1 + 4 + 4 + 1) as u64;
+    drop(durable);
+
+    let path = directory.0.join(LOG_FILE);
+    let log = fs::read(&path).unwrap();
+    for (offset, length_byte) in [(second, 0), (second, 3), (last, 0)] {
+        let mut corrupt = log.clone();
+        corrupt[offset as usize + length_byte] ^= 0x40;
+        fs::write(&path, &corrupt).unwrap();
+        let error = DurableTree::open(&directory.0)
+            .err()
+            .expect("a damaged length with data after it is corrupt");
+        assert!(
+            matches!(error, DurableError::CorruptLog { offset: at } if at == offset),
+            "{error}"
+        );
+        assert_eq!(
+            fs::read(&path).unwrap(),
+            corrupt,
+            "a corrupt log is not truncated"
+        );
+    }
+
+    // A damaged header with nothing after it can be the torn end of the file.
+    let mut torn = log[..last as usize + FRAME_HEADER_BYTES].to_vec();
+    torn[last as usize] ^= 0x40;
+    fs::write(&path, &torn).unwrap();
+    assert_eq!(durable_contents(&directory).len(), 4);
+    assert_eq!(fs::metadata(&path).unwrap().len(), last);
+}
+
+#[test]
+fn log_file_header_is_checked() {
+    let directory = TempDir::new("durable-header");
+    let path = directory.0.join(LOG_FILE);
+    let (_, log) = WriteAheadLog::open(&directory.0).expect("the directory is created");
+    assert_eq!(log.len(), 5);
+    drop(log);
+    assert_eq!(fs::read(&path).unwrap(), log_header());
+
+    // A header torn while the log was being created is written again.
+    for length in 0..log_header().len() {
+        fs::write(&path, &log_header()[..length]).unwrap();
+        assert!(durable_contents(&directory).is_empty());
+        assert_eq!(fs::read(&path).unwrap(), log_header());
+    }
+
+    for bytes in [&b"AVX"[..], b"AVLT\x01", b"not a log"] {
+        fs::write(&path, bytes).unwrap();
+        let error = DurableTree::open(&directory.0)
+            .err()
+            .expect("the magic bytes are wrong");
+        assert!(matches!(error, DurableError::BadLogMagic), "{bytes:?}");
+        assert_eq!(error.to_string(), "the log file is not a write-ahead log");
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+    }
+
+    let mut future = log_header();
+    future[4] = LOG_VERSION + 1;
+    fs::write(&path, &future).unwrap();
+    let error = DurableTree::open(&directory.0)
+        .err()
+        .expect("the version is unsupported");
+    assert!(matches!(
+        error,
+        DurableError::UnsupportedLogVersion(version) if version == LOG_VERSION + 1
+    ));
+}
+
+#[test]
+fn log_records_decode_only_well_formed_payloads() {
+    let mut tree = Tree::new();
+    for record in [
+        LogRecord::Insert {
+            key: -7,
+            value: "é",
+        },
+        LogRecord::Insert { key: 8, value: "" },
+        LogRecord::Remove { key: 8 },
+        LogRecord::Clear,
+        LogRecord::Insert {
+            key: 1,
+            value: "one",
+        },
+    ] {
+        record.apply(&mut tree);
+    }
+    assert_eq!(assert_valid(&tree), [(1, "one".to_owned())]);
+
+    let directory = TempDir::new("durable-records");
+    let path = directory.0.join(LOG_FILE);
+    let frame = |payload: &[u8]| {
+        let length = (payload.len() as u32).to_le_bytes();
+        let mut frame = length.to_vec();
+        frame.extend(crc32(&length).to_le_bytes());
+        frame.extend(crc32(payload).to_le_bytes());
+        frame.extend(payload);
+        frame
+    };
+    let log = |frames: &[&[u8]]| {
+        let mut log = log_header();
+        for payload in frames {
+            log.extend(frame(payload));
+        }
+        log
+    };
+    let mut insert = vec![1];
+    insert.extend(5_i32.to_le_bytes());
+    insert.extend(4_u32.to_le_bytes());
+    insert.extend(b"five");
+    fs::write(&path, log(&[&insert])).unwrap();
+    assert_eq!(durable_contents(&directory), [(5, "five".to_owned())]);
+
+    let mut batch = vec![3];
+    batch.extend(&insert);
+    batch.extend([2, 5, 0, 0, 0]);
+    batch.extend(&insert);
+    fs::write(&path, log(&[&batch])).unwrap();
+    assert_eq!(durable_contents(&directory), [(5, "five".to_owned())]);
+
+    for payload in [
+        &[][..],
+        &[9],
+        &[1, 0, 0],
+        &[1, 0, 0, 0, 0, 1, 0, 0],
+        &[1, 0, 0, 0, 0, 1, 0, 0, 0, 0xff],
+        &[1, 0, 0, 0, 0, 2, 0, 0, 0, b'a'],
+        &[2, 0],
+        &[3, 0],
+    ] {
+        fs::write(&path, log(&[payload, &insert])).unwrap();
+        let error = DurableTree::open(&directory.0)
+            .err()
+            .expect("the payload is invalid");
+        assert!(
+            matches!(error, DurableError::CorruptLog { offset: 5 }),
+            "{payload:?}"
+        );
+    }
+}
+
+#[test]
+fn log_appends_are_replayed_whole_or_not_at_all() {
+    let directory = TempDir::new("durable-batch");
+    let (_, mut log) = WriteAheadLog::open(&directory.0).expect("the directory is created");
+    log.append(&[LogRecord::Insert {
+        key: 1,
+        value: "one",
+    }])
+    .unwrap();
+    let complete = log.len();
+    log.append(&[
+        LogRecord::Remove { key: 1 },
+        LogRecord::Insert {
+            key: 2,
+            value: "two",
+        },
+    ])
+    .unwrap();
+    log.append(&[]).unwrap();
+    let full = log.len();
+    drop(log);
+    assert_eq!(durable_contents(&directory), [(2, "two".to_owned())]);
+
+    let path = directory.0.join(LOG_FILE);
+    let bytes = fs::read(&path).unwrap();
+    assert_eq!(bytes.len() as u64, full, "an empty append writes nothing");
+    for length in complete as usize..bytes.len() {
+        fs::write(&path, &bytes[..length]).unwrap();
+        assert_eq!(durable_contents(&directory), [(1, "one".to_owned())]);
+    }
+}
+
+/// Checks every invariant of a persistent tree by copying it, stored heights
+/// and sizes included, into a `Tree`.
+fn assert_persistent(tree: &PersistentTree<i32, String>) -> Vec<(i32, String)> {
+    let copy = Tree::from(tree);
+    assert_eq!(copy.height(), tree.height());
+    assert!(tree.iter().eq(copy.iter()));
+    assert_eq!(tree.validate(), Ok(()));
+    assert_valid(&copy)
+}
+
+#[test]
+fn persistent_tree_matches_the_mutable_tree() {
+    let mut tree = PersistentTree::new();
+    assert!(tree.is_empty());
+    assert_eq!((tree.len(), tree.height()), (0, 0));
+    assert_eq!(tree.first(), None);
+    assert_eq!(tree.remove(&1), None);
+
+    for key in [5, 1, 9, 3, 7, 2, 8] {
+        assert_eq!(tree.insert(key, key.to_string()), None);
+    }
+    assert_eq!(tree.insert(3, "three".to_owned()), Some("3".to_owned()));
+    assert_eq!(tree.remove(&5), Some("5".to_owned()));
+    assert_eq!(tree.remove(&5), None);
+    assert_eq!(tree.find(&3).map(String::as_str), Some("three"));
+    assert!(tree.has(&9) && !tree.has(&4));
+    assert_eq!(tree.first(), Some((&1, &"1".to_owned())));
+    assert_eq!(tree.last(), Some((&9, &"9".to_owned())));
+    assert_eq!(tree.iter().len(), 6);
+    assert_eq!(
+        assert_persistent(&tree),
+        [
+            (1, "1"),
+            (2, "2"),
+            (3, "three"),
+            (7, "7"),
+            (8, "8"),
+            (9, "9")
+        ]
+        .map(|(key, value)| (key, value.to_owned()))
+    );
+
+    let sorted: Tree<i32, String> = (0..1000).map(|key| (key, key.to_string())).collect();
+    let shape = sorted.height();
+    let persistent = PersistentTree::from(sorted);
+    assert_eq!((persistent.len(), persistent.height()), (1000, shape));
+    assert_eq!(assert_persistent(&persistent).len(), 1000);
+    let collected: PersistentTree<i32, String> =
+        (0..10).rev().map(|key| (key, String::new())).collect();
+    assert_eq!(
+        collected.iter().map(|(&key, _)| key).collect::<Vec<_>>(),
+        (0..10).collect::<Vec<_>>()
+    );
+
+    let mut reversed = PersistentTree::with_comparator(ReverseOrder);
+    for key in 0..10 {
+        reversed.insert(key, ());
+    }
+    assert_eq!(reversed.first(), Some((&9, &())));
+    assert_eq!(reversed.remove(&9), Some(()));
+    assert_eq!(reversed.first(), Some((&8, &())));
+}
+
+#[test]
+fn persistent_tree_answers_ordered_queries_like_the_mutable_tree() {
+    let entries = (0..200).map(|key| (key * 3, key.to_string()));
+    let tree: Tree<i32, String> = entries.clone().collect();
+    let mut persistent: PersistentTree<i32, String> = entries.collect();
+
+    for key in -2..602 {
+        assert_eq!(persistent.rank(&key), tree.rank(&key), "rank {key}");
+        assert_eq!(persistent.count_less_than(&key), tree.count_less_than(&key));
+        assert_eq!(persistent.floor(&key), tree.floor(&key), "floor {key}");
+        assert_eq!(persistent.ceiling(&key), tree.ceiling(&key));
+        assert_eq!(persistent.predecessor(&key), tree.predecessor(&key));
+        assert_eq!(persistent.successor(&key), tree.successor(&key));
+    }
+    for index in 0..=200 {
+        assert_eq!(persistent.select(index), tree.select(index));
+    }
+    for (start, end) in [
+        (Bound::Unbounded, Bound::Unbounded),
+        (Bound::Included(30), Bound::Excluded(90)),
+        (Bound::Excluded(30), Bound::Included(90)),
+        (Bound::Included(31), Bound::Included(31)),
+        (Bound::Excluded(90), Bound::Excluded(30)),
+        (Bound::Unbounded, Bound::Included(-1)),
+        (Bound::Included(598), Bound::Unbounded),
+    ] {
+        let range = persistent.range((start, end));
+        assert_eq!(range.len(), tree.count_range((start, end)));
+        assert!(range.eq(tree.range((start, end))), "{start:?}..{end:?}");
+        assert_eq!(
+            persistent.count_range((start, end)),
+            tree.count_range((start, end))
+        );
+    }
+    let stats = persistent.stats();
+    assert_eq!(
+        (stats.len, stats.height, stats.height_bound),
+        (tree.len(), tree.height(), tree.stats().height_bound)
+    );
+    assert_eq!(stats.average_depth, tree.stats().average_depth);
+
+    let snapshot = persistent.snapshot();
+    assert_eq!(persistent.pop_first(), Some((0, "0".to_owned())));
+    assert_eq!(persistent.pop_last(), Some((597, "199".to_owned())));
+    assert_eq!(assert_persistent(&persistent).len(), 198);
+    persistent.clear();
+    assert!(persistent.is_empty());
+    assert_eq!(persistent.pop_first(), None);
+    assert_eq!(persistent.pop_last(), None);
+    assert_eq!(assert_persistent(&snapshot).len(), 200);
+
+    let mut history = History::new(4);
+    let mut versioned = snapshot.snapshot();
+    let removed = versioned.remove(&3);
+    history.record(3, removed);
+    history.record(1, versioned.insert(1, "one".to_owned()));
+    assert!(history.undo(&mut versioned));
+    assert!(versioned.iter().eq(snapshot.iter()));
+    assert!(history.redo(&mut versioned));
+    assert_eq!(versioned.find(&1).map(String::as_str), Some("one"));
+    assert!(!versioned.has(&3));
+}
+
+#[test]
+fn persistent_tree_writes_the_formats_of_the_mutable_tree() {
+    let tree: Tree<i32, String> = (-50..50)
+        .map(|key| (key * 7, format!("\"{key}\"\n")))
+        .collect();
+    let mut persistent = PersistentTree::from(tree.clone());
+    assert_eq!(persistent.dump(), tree.dump());
+    assert_eq!(persistent.to_json(), tree.to_json());
+    let (mut bytes, mut expected) = (Vec::new(), Vec::new());
+    persistent.serialize_into(&mut bytes).unwrap();
+    tree.serialize_into(&mut expected).unwrap();
+    assert_eq!(bytes, expected);
+    assert_eq!(PersistentTree::<i32, String>::new().to_json(), "[]");
+
+    let directory = TempDir::new("persistent-save");
+    let path = directory.0.join("tree.avl");
+    persistent.save(&path).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), expected);
+    assert_eq!(directory.file_names(), ["tree.avl"]);
+
+    let (_, mut log) = WriteAheadLog::open(directory.0.join("log")).unwrap();
+    log.append(&[LogRecord::Remove { key: 0 }]).unwrap();
+    persistent.remove(&0);
+    log.compact_persistent(&persistent).unwrap();
+    assert!(log.is_empty());
+    drop(log);
+    let (recovered, _) = WriteAheadLog::open(directory.0.join("log")).unwrap();
+    assert!(recovered.iter().eq(persistent.iter()));
+}
+
+#[test]
+fn persistent_snapshots_are_unaffected_by_later_writes() {
+    let mut tree: PersistentTree<i32, String> =
+        (0..100).map(|key| (key, key.to_string())).collect();
+    let original = tree.snapshot();
+    let original_entries = assert_persistent(&original);
+
+    for key in (0..100).step_by(3) {
+        tree.remove(&key);
+    }
+    for key in (1..100).step_by(3) {
+        tree.insert(key, format!("updated {key}"));
+    }
+    let middle = tree.snapshot();
+    let middle_entries = assert_persistent(&middle);
+
+    for key in 100..200 {
+        tree.insert(key, key.to_string());
+    }
+    for key in 0..150 {
+        tree.remove(&key);
+    }
+    assert_eq!(assert_persistent(&tree).len(), 50);
+    assert_eq!(assert_persistent(&original), original_entries);
+    assert_eq!(assert_persistent(&middle), middle_entries);
+    assert_eq!(middle.find(&1).map(String::as_str), Some("updated 1"));
+    assert_eq!(original.find(&1).map(String::as_str), Some("1"));
+
+    // Writing to a snapshot leaves the tree it came from alone as well.
+    let mut branch = original.snapshot();
+    branch.insert(0, "branch".to_owned());
+    assert_eq!(original.find(&0).map(String::as_str), Some("0"));
+    assert_eq!(assert_persistent(&original), original_entries);
+
+    let current = assert_persistent(&tree);
+    drop(original);
+    drop(middle);
+    drop(branch);
+    assert_eq!(assert_persistent(&tree), current);
+}
+
+#[test]
+fn persistent_writes_copy_only_the_changed_path() {
+    let mut tree: PersistentTree<i32, i32> = (0..1024).map(|key| (key * 2, key)).collect();
+    let height = tree.height();
+    let snapshot = tree.snapshot();
+    assert_eq!(tree.unshared_nodes(&snapshot), 0);
+
+    for key in [-1, 1001, 2047, 3000] {
+        let before = tree.snapshot();
+        tree.insert(key, 0);
+        // The search path plus the new leaf, and at most two nodes per
+        // rotation off the path.
+        assert!(tree.unshared_nodes(&before) <= height + 3, "insert {key}");
+    }
+    for key in [0, 1000, 2046, 1001] {
+        let before = tree.snapshot();
+        tree.remove(&key);
+        assert!(tree.unshared_nodes(&before) <= 2 * height, "remove {key}");
+    }
+    let before = tree.snapshot();
+    assert_eq!(tree.insert(10, 50), Some(5));
+    let replaced = tree.unshared_nodes(&before);
+    assert!(
+        (1..=tree.height()).contains(&replaced),
+        "a replacement copies its search path"
+    );
+    assert_eq!(tree.remove(&1), None);
+    assert_eq!(
+        tree.unshared_nodes(&before),
+        replaced,
+        "a missing key copies nothing"
+    );
+    assert!(tree.unshared_nodes(&snapshot) <= 9 * 2 * height);
+    assert_eq!(snapshot.len(), 1024);
+}
+
+#[test]
+fn persistent_set_operations_share_untouched_subtrees() {
+    let tree: PersistentTree<i32, i32> = (0..1024).map(|key| (key * 2, key)).collect();
+    let height = tree.height();
+    let few: PersistentTree<i32, i32> = [(1, -1), (1000, -2), (2047, -3)].into_iter().collect();
+
+    let union = tree
+        .snapshot()
+        .union(few.snapshot(), |_, mine, theirs| mine + theirs);
+    assert_eq!(union.len(), 1026);
+    assert_eq!(union.find(&1000), Some(&498));
+    // Each of the three splits copies one search path, and each join
+    // along it copies at most another.
+    assert!(union.unshared_nodes(&tree) <= 3 * 2 * (height + 2));
+    assert_eq!(tree.find(&1000), Some(&500));
+
+    let difference = tree.snapshot().difference(few.snapshot());
+    assert_eq!(difference.len(), 1023);
+    assert!(difference.unshared_nodes(&tree) <= 3 * 2 * (height + 2));
+    let intersection = few.snapshot().intersection(tree.snapshot());
+    assert!(intersection.iter().eq([(&1000, &-2)]));
+    assert_eq!(tree.len(), 1024);
+    assert_eq!(tree.validate(), Ok(()));
+}
+
+#[test]
+fn transactions_read_their_staged_changes() {
+    let mut tree: Tree<i32, String> = (1..=5).map(|key| (key, key.to_string())).collect();
+    let mut transaction = tree.transaction();
+    assert!(!transaction.insert(6, "six".to_owned()));
+    assert!(transaction.insert(2, "two".to_owned()));
+    assert!(transaction.remove(3));
+    assert!(!transaction.remove(3), "a staged removal hides the key");
+    assert!(!transaction.remove(9));
+    assert!(!transaction.insert(3, "three".to_owned()));
+    assert!(transaction.remove(6));
+    assert_eq!(transaction.find(&2).map(String::as_str), Some("two"));
+    assert_eq!(transaction.find(&3).map(String::as_str), Some("three"));
+    assert_eq!(transaction.find(&4).map(String::as_str), Some("4"));
+    assert!(!transaction.has(&6));
+    assert_eq!(transaction.len(), 5);
+    transaction.commit();
+
+    assert_eq!(
+        assert_valid(&tree),
+        [
+            (1, "1".to_owned()),
+            (2, "two".to_owned()),
+            (3, "three".to_owned()),
+            (4, "4".to_owned()),
+            (5, "5".to_owned()),
+        ]
+    );
+
+    let mut transaction = tree.transaction();
+    for key in 1..=5 {
+        assert!(transaction.remove(key));
+    }
+    assert!(transaction.is_empty());
+    transaction.commit();
+    assert!(assert_valid(&tree).is_empty());
+}
+
+#[test]
+fn rolled_back_transactions_leave_the_tree_untouched() {
+    let mut tree: Tree<i32, String> = (0..100).map(|key| (key, key.to_string())).collect();
+    tree.remove(&50);
+    let dump = tree.dump();
+
+    let mut transaction = tree.transaction();
+    transaction.insert(50, "fifty".to_owned());
+    transaction.remove(7);
+    transaction.rollback();
+    assert_eq!(tree.dump(), dump);
+
+    fn stage_then_fail(tree: &mut Tree<i32, String>) -> Result<(), ParseIntError> {
+        let mut transaction = tree.transaction();
+        for value in ["100", "101", "one hundred and two"] {
+            transaction.insert(value.parse()?, value.to_owned());
+        }
+        transaction.commit();
+        Ok(())
+    }
+    assert!(stage_then_fail(&mut tree).is_err());
+    assert_eq!(tree.dump(), dump, "dropping a transaction rolls it back");
+    assert_eq!(tree.len(), 99);
+
+    let mut transaction: Transaction<'_, i32, String> = tree.transaction();
+    transaction.insert(50, "fifty".to_owned());
+    transaction.commit();
+    assert_ne!(tree.dump(), dump);
+}
+
+#[test]
+fn history_undoes_and_redoes_inserts_and_removals() {
+    let mut tree = Tree::from_iter([(1, "one".to_owned())]).with_history(8);
+    assert_eq!(tree.insert(2, "two".to_owned()), None);
+    assert_eq!(tree.insert(1, "ONE".to_owned()), Some("one".to_owned()));
+    tree.checkpoint("edit");
+    assert_eq!(tree.remove(&2), Some("two".to_owned()));
+    assert_eq!(tree.remove(&2), None);
+    assert_eq!(
+        tree.history().undo_changes(),
+        Some(&[(2, Some("two".to_owned()))][..])
+    );
+    assert_eq!(tree.history().undo_label(), None);
+
+    assert!(tree.undo(), "the unlabelled changes since the checkpoint");
+    assert_eq!(
+        assert_valid(tree.tree()),
+        [(1, "ONE".to_owned()), (2, "two".to_owned())]
+    );
+    assert_eq!(tree.history().undo_label(), Some("edit"));
+    assert!(tree.undo());
+    assert_eq!(assert_valid(tree.tree()), [(1, "one".to_owned())]);
+    assert!(!tree.undo());
+    assert_eq!(tree.history().undo_changes(), None);
+
+    assert_eq!(tree.history().redo_label(), Some("edit"));
+    assert!(tree.redo());
+    assert_eq!(
+        assert_valid(tree.tree()),
+        [(1, "ONE".to_owned()), (2, "two".to_owned())]
+    );
+    tree.insert(3, "three".to_owned());
+    assert!(!tree.redo(), "a new change discards the redo steps");
+    assert!(tree.undo());
+    assert!(tree.undo());
+    assert_eq!(assert_valid(tree.tree()), [(1, "one".to_owned())]);
+    assert_eq!(tree.into_tree().len(), 1);
+}
+
+#[test]
+fn history_keeps_only_the_newest_steps() {
+    let mut tree = Tree::new().with_history(2);
+    for key in 0..4 {
+        tree.insert(key, key.to_string());
+        tree.checkpoint(format!("insert {key}"));
+    }
+    tree.checkpoint("nothing changed");
+    assert_eq!(tree.history().depth(), 2);
+    assert_eq!(tree.history().undo_label(), Some("insert 3"));
+    assert!(tree.undo());
+    assert!(tree.undo());
+    assert!(!tree.undo(), "older steps were dropped");
+    assert_eq!(
+        assert_valid(tree.tree()),
+        [(0, "0".to_owned()), (1, "1".to_owned())]
+    );
+    assert_eq!(tree.history().redo_label(), Some("insert 2"));
+
+    let mut tree = Tree::new().with_history(0);
+    tree.insert(1, "one".to_owned());
+    assert!(!tree.undo(), "a depth of 0 records nothing");
+    assert_eq!(tree.tree().len(), 1);
+
+    let mut history = History::new(4);
+    let mut tree = Tree::new();
+    tree.insert(5, "five".to_owned());
+    history.record(5, None);
+    history.clear();
+    assert!(!history.undo(&mut tree));
+    assert_eq!(tree.len(), 1);
+}
+
+#[test]
+fn history_bounds_changes_recorded_without_checkpoints() {
+    let value = Rc::new(());
+    let mut history = History::new(3);
+    history.record(-1, None);
+    history.checkpoint("older");
+    for key in 0..10_000 {
+        history.record(key, Some(Rc::clone(&value)));
+    }
+
+    assert!(
+        Rc::strong_count(&value) <= 1 + 2 * 3,
+        "{} recorded values are still held",
+        Rc::strong_count(&value) - 1
+    );
+    assert_eq!(history.undo_label(), None);
+    assert_eq!(
+        history
+            .undo_changes()
+            .map(|changes| changes.iter().map(|&(key, _)| key).collect::<Vec<_>>()),
+        Some(vec![9_997, 9_998, 9_999])
+    );
+
+    let mut tree = Tree::new();
+    assert!(history.undo(&mut tree));
+    assert_eq!(
+        tree.keys().copied().collect::<Vec<_>>(),
+        [9_997, 9_998, 9_999]
+    );
+    assert!(
+        !history.undo(&mut tree),
+        "the checkpointed step was dropped"
+    );
+    assert_eq!(Rc::strong_count(&value), 1 + 3);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_round_trips_through_json_as_an_ordered_map() {
+    let tree: Tree<i32, String> = [(2, "two"), (-1, "minus one"), (10, "ten")]
+        .into_iter()
+        .map(|(key, value)| (key, value.to_owned()))
+        .collect();
+    let json = serde_json::to_string(&tree).expect("the tree serializes");
+    assert_eq!(json, r#"{"-1":"minus one","2":"two","10":"ten"}"#);
+    let decoded: Tree<i32, String> = serde_json::from_str(&json).expect("the map deserializes");
+    assert_eq!(assert_valid(&decoded), assert_valid(&tree));
+
+    let unordered: Tree<i32, String> =
+        serde_json::from_str(r#"{"3":"c","1":"a","2":"b"}"#).expect("any order deserializes");
+    assert_eq!(
+        assert_valid(&unordered),
+        [
+            (1, "a".to_owned()),
+            (2, "b".to_owned()),
+            (3, "c".to_owned()),
+        ]
+    );
+    let empty: Tree<i32, String> = serde_json::from_str("{}").expect("an empty map deserializes");
+    assert!(empty.is_empty());
+
+    for json in [r#"{"1":"a","1":"b"}"#, r#"{"2":"a","1":"b","2":"c"}"#] {
+        let error = serde_json::from_str::<Tree<i32, String>>(json)
+            .err()
+            .expect("a repeated key is rejected");
+        assert!(
+            error.to_string().starts_with("duplicate key in map"),
+            "{error}"
+        );
+    }
+    assert!(serde_json::from_str::<Tree<i32, String>>("[]").is_err());
+
+    let reversed: Tree<i32, &str, ReverseOrder> =
+        [(1, "a"), (3, "c"), (2, "b")].into_iter().collect();
+    let json = serde_json::to_string(&reversed).expect("the tree serializes");
+    assert_eq!(json, r#"{"3":"c","2":"b","1":"a"}"#);
+    let decoded: Tree<i32, String, ReverseOrder> =
+        serde_json::from_str(&json).expect("the map deserializes");
+    assert_eq!(decoded.first(), Some((&3, &"c".to_owned())));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_round_trips_through_a_binary_format() {
+    let tree: Tree<i32, String> = (0..1000).map(|key| (key, key.to_string())).collect();
+    let bytes = bincode::serialize(&tree).expect("the tree serializes");
+    let decoded: Tree<i32, String> = bincode::deserialize(&bytes).expect("the map deserializes");
+    assert_eq!(assert_valid(&decoded), assert_valid(&tree));
+    assert_eq!(
+        decoded.height(),
+        10,
+        "sorted input is built perfectly balanced"
+    );
+
+    // Bincode writes a map exactly like a sequence of pairs, which makes
+    // arbitrary key orders easy to forge.
+    let forged = |pairs: &[(i32, &str)]| {
+        let bytes = bincode::serialize(pairs).expect("pairs serialize");
+        bincode::deserialize::<Tree<i32, String>>(&bytes)
+    };
+    let unordered = forged(&[(5, "e"), (1, "a"), (3, "c")]).expect("any order deserializes");
+    assert_eq!(
+        assert_valid(&unordered),
+        [
+            (1, "a".to_owned()),
+            (3, "c".to_owned()),
+            (5, "e".to_owned()),
+        ]
+    );
+    let error = forged(&[(1, "a"), (2, "b"), (1, "c")])
+        .err()
+        .expect("a repeated key is rejected");
+    assert_eq!(error.to_string(), "duplicate key in map");
+}
+
+#[test]
+fn records_baseline_node_size() {
+    eprintln!(
+        "size_of::<Node<i32, String>>() = {}",
+        size_of::<Node<i32, String>>()
+    );
+    assert!(size_of::<Node<i32, String>>() > 0);
+}
+
+#[derive(Clone, Debug)]
+enum Operation {
+    Insert(i32, String),
+    Find(i32),
+    Has(i32),
+    Remove(i32),
+}
+
+fn operation_strategy() -> impl Strategy<Value = Operation> {
+    prop_oneof![
+        5 => ((-64_i32..=64), "[ -~]{0,16}")
+            .prop_map(|(key, value)| Operation::Insert(key, value)),
+        2 => (-80_i32..=80).prop_map(Operation::Find),
+        1 => (-80_i32..=80).prop_map(Operation::Has),
+        3 => (-80_i32..=80).prop_map(Operation::Remove),
+    ]
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig {
+        cases: 256,
+        max_shrink_iters: 10_000,
+        ..ProptestConfig::default()
+    })]
+
+    #[test]
+    fn operation_sequences_match_btree_map(
+        operations in prop::collection::vec(operation_strategy(), 1..128)
+    ) {
+        let mut tree = Tree::new();
+        let mut model = BTreeMap::new();
+
+        for operation in operations {
+            match operation {
+                Operation::Insert(key, value) => {
+                    prop_assert_eq!(tree.insert(key, value.clone()), model.insert(key, value));
+                }
+                Operation::Find(key) => {
+                    prop_assert_eq!(tree.find(&key).map(String::as_str), model.get(&key).map(String::as_str));
+                }
+                Operation::Has(key) => {
+                    prop_assert_eq!(tree.has(&key), model.contains_key(&key));
+                }
+                Operation::Remove(key) => {
+                    prop_assert_eq!(tree.remove(&key), model.remove(&key));
+                }
+            }
+
+            let entries = assert_valid(&tree);
+            let expected: Vec<_> = model
+                .iter()
+                .map(|(&key, value)| (key, value.clone()))
+                .collect();
+            prop_assert_eq!(entries, expected);
+        }
+    }
+
+    #[test]
+    fn entry_sequences_match_btree_map(
+        operations in prop::collection::vec(operation_strategy(), 1..128)
+    ) {
+        let mut tree = Tree::new();
+        let mut model = BTreeMap::new();
+
+        for operation in operations {
+            match operation {
+                Operation::Insert(key, value) => {
+                    let stored = tree.entry(key).or_insert_with(|| value.clone()).clone();
+                    prop_assert_eq!(&stored, model.entry(key).or_insert(value));
+                }
+                Operation::Find(key) | Operation::Has(key) => {
+                    // Looking and then dropping the entry must leave the tree
+                    // whole.
+                    let found = matches!(tree.entry(key), Entry::Occupied(_));
+                    prop_assert_eq!(found, model.contains_key(&key));
+                }
+                Operation::Remove(key) => {
+                    prop_assert_eq!(tree.remove(&key), model.remove(&key));
+                }
+            }
+
+            assert_matches_model(&tree, &model);
+        }
+    }
+
+    #[test]
+    fn persistent_snapshots_match_btree_map_versions(
+        operations in prop::collection::vec(operation_strategy(), 1..96)
+    ) {
+        let mut tree = PersistentTree::new();
+        let mut model = BTreeMap::new();
+        let mut versions = Vec::new();
+
+        for operation in operations {
+            match operation {
+                Operation::Insert(key, value) => {
+                    prop_assert_eq!(tree.insert(key, value.clone()), model.insert(key, value));
+                }
+                Operation::Find(key) => {
+                    prop_assert_eq!(tree.find(&key), model.get(&key));
+                }
+                Operation::Has(key) => {
+                    // Checking membership doubles as the cue to keep a
+                    // version.
+                    prop_assert_eq!(tree.has(&key), model.contains_key(&key));
+                    versions.push((tree.snapshot(), model.clone()));
+                }
+                Operation::Remove(key) => {
+                    prop_assert_eq!(tree.remove(&key), model.remove(&key));
+                }
+            }
+        }
+
+        versions.push((tree, model));
+        for (version, model) in &versions {
+            let expected: Vec<_> = model
+                .iter()
+                .map(|(&key, value)| (key, value.clone()))
+                .collect();
+            prop_assert_eq!(assert_persistent(version), expected);
+        }
+    }
+
+    #[test]
+    fn transactions_commit_or_roll_back_whole(
+        initial in prop::collection::vec(operation_strategy(), 0..64),
+        staged in prop::collection::vec(operation_strategy(), 0..64),
+        commit: bool,
+    ) {
+        let mut tree = Tree::new();
+        let mut model = BTreeMap::new();
+        for operation in initial {
+            if let Operation::Insert(key, value) = operation {
+                tree.insert(key, value.clone());
+                model.insert(key, value);
+            }
+        }
+        let dump = tree.dump();
+
+        let mut expected = model.clone();
+        let mut transaction = tree.transaction();
+        for operation in staged {
+            match operation {
+                Operation::Insert(key, value) => {
+                    let present = expected.insert(key, value.clone()).is_some();
+                    prop_assert_eq!(transaction.insert(key, value), present);
+                }
+                Operation::Find(key) => {
+                    prop_assert_eq!(transaction.find(&key), expected.get(&key));
+                }
+                Operation::Has(key) => {
+                    prop_assert_eq!(transaction.has(&key), expected.contains_key(&key));
+                }
+                Operation::Remove(key) => {
+                    let present = expected.remove(&key).is_some();
+                    prop_assert_eq!(transaction.remove(key), present);
+                }
+            }
+            prop_assert_eq!(transaction.len(), expected.len());
+        }
+
+        if commit {
+            transaction.commit();
+            assert_matches_model(&tree, &expected);
+        } else {
+            transaction.rollback();
+            prop_assert_eq!(tree.dump(), dump);
+            assert_matches_model(&tree, &model);
+        }
+    }
+
+    #[test]
+    fn history_undoes_and_redoes_to_every_checkpoint(
+        operations in prop::collection::vec(operation_strategy(), 1..96)
+    ) {
+        let mut tree = Tree::new().with_history(usize::MAX);
+        let mut model = BTreeMap::new();
+        let mut versions = vec![model.clone()];
+        let mut changed = false;
+
+        for operation in operations {
+            match operation {
+                Operation::Insert(key, value) => {
+                    prop_assert_eq!(tree.insert(key, value.clone()), model.insert(key, value));
+                    changed = true;
+                }
+                Operation::Remove(key) => {
+                    let removed = model.remove(&key);
+                    changed |= removed.is_some();
+                    prop_assert_eq!(tree.remove(&key), removed);
+                }
+                // Lookups do not change the tree, so they mark the
+                // checkpoints instead.
+                Operation::Find(_) | Operation::Has(_) => {
+                    if changed {
+                        tree.checkpoint(versions.len().to_string());
+                        versions.push(model.clone());
+                        changed = false;
+                    }
+                }
+            }
+        }
+        if changed {
+            versions.push(model);
+        }
+
+        for version in versions.iter().rev().skip(1) {
+            prop_assert!(tree.undo());
+            assert_matches_model(tree.tree(), version);
+        }
+        prop_assert!(!tree.undo());
+        for version in versions.iter().skip(1) {
+            prop_assert!(tree.redo());
+            assert_matches_model(tree.tree(), version);
+        }
+        prop_assert!(!tree.redo());
+    }
+
+    #[test]
+    fn split_off_matches_btree_map(
+        keys in prop::collection::btree_set(-200_i32..200, 0..300),
+        pivot in -210_i32..210,
+    ) {
+        let mut model: BTreeMap<i32, i32> = keys.iter().map(|&key| (key, key * 3)).collect();
+        let mut tree: Tree<i32, i32> = model.iter().map(|(&key, &value)| (key, value)).collect();
+        for &key in keys.iter().step_by(3) {
+            prop_assert_eq!(tree.remove(&key), model.remove(&key));
+        }
+
+        let upper = tree.split_off(&pivot);
+        let expected_upper = model.split_off(&pivot);
+        prop_assert_eq!(assert_ordered(&tree), model.iter().collect::<Vec<_>>());
+        prop_assert_eq!(assert_ordered(&upper), expected_upper.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn append_matches_btree_map(
+        left in prop::collection::btree_map(-300_i32..300, any::<u8>(), 0..200),
+        right in prop::collection::btree_map(-300_i32..300, any::<u8>(), 0..200),
+        shift in prop_oneof![Just(0_i32), Just(1_000), Just(-1_000)],
+    ) {
+        let mut model = left;
+        let mut other_model: BTreeMap<i32, u8> =
+            right.into_iter().map(|(key, value)| (key + shift, value)).collect();
+        let mut tree: Tree<i32, u8> = model.iter().map(|(&key, &value)| (key, value)).collect();
+        let mut other: Tree<i32, u8> =
+            other_model.iter().map(|(&key, &value)| (key, value)).collect();
+
+        tree.append(&mut other);
+        model.append(&mut other_model);
+        prop_assert_eq!(assert_ordered(&tree), model.iter().collect::<Vec<_>>());
+        prop_assert!(assert_ordered(&other).is_empty());
+    }
+
+    #[test]
+    fn set_operations_match_btree_map(
+        left in prop::collection::btree_map(-100_i32..100, any::<u16>(), 0..150),
+        right in prop::collection::btree_map(-100_i32..100, any::<u16>(), 0..150),
+        sparse_right in prop::collection::btree_map(-100_i32..100, any::<u16>(), 0..6),
+    ) {
+        for right in [right, sparse_right] {
+            let tree = |model: &BTreeMap<i32, u16>| -> Tree<i32, u16> {
+                let mut tree: Tree<i32, u16> = model.iter().map(|(&key, &value)| (key, value)).collect();
+                for key in model.keys().step_by(4) {
+                    tree.remove(key);
+                    tree.insert(*key, model[key]);
+                }
+                tree
+            };
+            // The operations run on snapshots, so every node they reach is
+            // shared and must be copied rather than written through.
+            let (left_shared, right_shared) =
+                (PersistentTree::from(tree(&left)), PersistentTree::from(tree(&right)));
+
+            let mut merged = right.clone();
+            for (&key, &value) in &left {
+                merged
+                    .entry(key)
+                    .and_modify(|other| *other = value.wrapping_sub(*other))
+                    .or_insert(value);
+            }
+            let union = tree(&left).union(tree(&right), |_, mine, theirs| mine.wrapping_sub(theirs));
+            prop_assert_eq!(assert_ordered(&union), merged.iter().collect::<Vec<_>>());
+            let shared = left_shared.snapshot().union(right_shared.snapshot(), |_, mine, theirs| {
+                mine.wrapping_sub(theirs)
+            });
+            prop_assert_eq!(shared.validate(), Ok(()));
+            prop_assert!(shared.iter().eq(union.iter()));
+
+            let expected: Vec<_> =
+                left.iter().filter(|(key, _)| right.contains_key(key)).collect();
+            let intersection = tree(&left).intersection(tree(&right));
+            prop_assert_eq!(assert_ordered(&intersection), expected);
+            let shared = left_shared.snapshot().intersection(right_shared.snapshot());
+            prop_assert_eq!(shared.validate(), Ok(()));
+            prop_assert!(shared.iter().eq(intersection.iter()));
+
+            let expected: Vec<_> =
+                left.iter().filter(|(key, _)| !right.contains_key(key)).collect();
+            let difference = tree(&left).difference(tree(&right));
+            prop_assert_eq!(assert_ordered(&difference), expected);
+            let shared = left_shared.snapshot().difference(right_shared.snapshot());
+            prop_assert_eq!(shared.validate(), Ok(()));
+            prop_assert!(shared.iter().eq(difference.iter()));
+
+            let mut expected: BTreeMap<_, _> = right
+                .iter()
+                .filter(|(key, _)| !left.contains_key(key))
+                .collect();
+            expected.extend(left.iter().filter(|(key, _)| !right.contains_key(key)));
+            let symmetric = tree(&left).symmetric_difference(tree(&right));
+            prop_assert_eq!(
+                assert_ordered(&symmetric),
+                expected.into_iter().collect::<Vec<_>>()
+            );
+            let shared = left_shared.snapshot().symmetric_difference(right_shared.snapshot());
+            prop_assert_eq!(shared.validate(), Ok(()));
+            prop_assert!(shared.iter().eq(symmetric.iter()));
+            prop_assert!(left_shared.iter().eq(&left));
+            prop_assert!(right_shared.iter().eq(&right));
+        }
+    }
+
+    #[test]
+    fn drain_range_and_pops_match_btree_map(
+        keys in prop::collection::btree_set(-200_i32..200, 0..300),
+        start in -210_i32..210,
+        end in -210_i32..210,
+        pops in 0_usize..8,
+    ) {
+        let mut tree: Tree<i32, String> = keys.iter().map(|&key| (key, key.to_string())).collect();
+        let mut model: BTreeMap<i32, String> = keys.iter().map(|&key| (key, key.to_string())).collect();
+        let bounds = (Bound::Excluded(start), Bound::Included(end));
+
+        let drained: Vec<_> = tree.drain_range(bounds).collect();
+        let expected: Vec<_> = if start < end {
+            let expected: Vec<_> = model.range(bounds).map(|(&key, value)| (key, value.clone())).collect();
+            for (key, _) in &expected {
+                model.remove(key);
+            }
+            expected
+        } else {
+            Vec::new()
+        };
+        prop_assert_eq!(drained, expected);
+        assert_matches_model(&tree, &model);
+
+        for _ in 0..pops {
+            prop_assert_eq!(tree.pop_first(), model.pop_first());
+            prop_assert_eq!(tree.pop_last(), model.pop_last());
+        }
+        assert_matches_model(&tree, &model);
+        prop_assert_eq!(tree.len(), model.len());
+    }
+
+    #[test]
+    fn json_round_trips_arbitrary_strings(
+        entries in prop::collection::btree_map(any::<i32>(), any::<String>(), 0..64),
+    ) {
+        let tree: Tree<i32, String> = entries.clone().into_iter().collect();
+        let parsed = Tree::from_json(&tree.to_json()).expect("to_json output parses");
+        let expected: Vec<_> = entries.into_iter().collect();
+        prop_assert_eq!(assert_valid(&parsed), expected);
+    }
+
+    #[test]
+    fn binary_format_round_trips_arbitrary_trees(
+        entries in prop::collection::btree_map(any::<i32>(), any::<String>(), 0..64),
+    ) {
+        let tree: Tree<i32, String> = entries.clone().into_iter().collect();
+        let decoded = Tree::deserialize_from(&serialized(&tree)[..]).expect("the bytes are valid");
+        let expected: Vec<_> = entries.into_iter().collect();
+        prop_assert_eq!(assert_valid(&decoded), expected);
+    }
+
+    #[test]
+    fn entry_counters_match_btree_map(
+        keys in prop::collection::vec(-100_i32..100, 0..300),
+    ) {
+        let mut tree = Tree::new();
+        let mut model = BTreeMap::new();
+        for key in keys {
+            *tree.entry(key).or_insert(0_u32) += 1;
+            *model.entry(key).or_insert(0_u32) += 1;
+        }
+
+        let entries: Vec<_> = assert_ordered(&tree)
+            .into_iter()
+            .map(|(&key, &count)| (key, count))
+            .collect();
+        let expected: Vec<_> = model.into_iter().collect();
+        prop_assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn reversed_comparator_sequences_match_btree_map(
+        operations in prop::collection::vec(operation_strategy(), 1..128)
+    ) {
+        let mut tree = Tree::with_comparator(|left: &i32, right: &i32| right.cmp(left));
+        let mut model = BTreeMap::new();
+
+        for operation in operations {
+            match operation {
+                Operation::Insert(key, value) => {
+                    tree.insert(key, value.clone());
+                    model.insert(Reverse(key), value);
+                }
+                Operation::Find(key) => {
+                    prop_assert_eq!(tree.find(&key), model.get(&Reverse(key)));
+                }
+                Operation::Has(key) => {
+                    prop_assert_eq!(tree.has(&key), model.contains_key(&Reverse(key)));
+                }
+                Operation::Remove(key) => {
+                    prop_assert_eq!(tree.remove(&key), model.remove(&Reverse(key)));
+                }
+            }
+
+            let entries = assert_ordered(&tree);
+            let expected: Vec<_> = model.iter().map(|(Reverse(key), value)| (key, value)).collect();
             prop_assert_eq!(entries, expected);
         }
     }
@@ -0,0 +1,96 @@
+use super::{Comparator, IntoIter, Link, Natural, Tree};
+
+/// A set of staged inserts and removals that is applied to a [`Tree`] all at
+/// once or not at all.
+///
+/// Created by [`Tree::transaction`]. Reads through the transaction see the
+/// staged changes over the tree, while the tree itself is left untouched until
+/// [`Transaction::commit`]. Dropping the transaction without committing, as an
+/// early return with `?` does, rolls it back.
+#[must_use = "a transaction is rolled back unless it is committed"]
+pub struct Transaction<'a, K, V, C = Natural> {
+    tree: &'a mut Tree<K, V, C>,
+    /// The staged value of each touched key, with `None` marking a removal,
+    /// ordered by the tree's comparator.
+    staged: Link<K, Option<V>>,
+    /// The number of staged keys.
+    staged_len: usize,
+    len: usize,
+}
+
+impl<'a, K, V, C: Comparator<K>> Transaction<'a, K, V, C> {
+    pub(super) fn new(tree: &'a mut Tree<K, V, C>) -> Self {
+        let len = tree.len;
+        Self {
+            tree,
+            staged: None,
+            staged_len: 0,
+            len,
+        }
+    }
+
+    /// Returns the value `key` would have after a commit.
+    pub fn find(&self, key: &K) -> Option<&V> {
+        match Tree::<K, Option<V>, C>::search_node(&self.staged, key, &self.tree.compare) {
+            Some(staged) => staged.as_ref(),
+            None => self.tree.find(key),
+        }
+    }
+
+    pub fn has(&self, key: &K) -> bool {
+        self.find(key).is_some()
+    }
+
+    /// Returns the number of entries the tree would hold after a commit.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Stages an insert of `value` under `key` and reports whether the key was
+    /// already present.
+    pub fn insert(&mut self, key: K, value: V) -> bool {
+        self.stage(key, Some(value))
+    }
+
+    /// Stages the removal of `key` and reports whether it was present.
+    pub fn remove(&mut self, key: K) -> bool {
+        self.has(&key) && self.stage(key, None)
+    }
+
+    fn stage(&mut self, key: K, value: Option<V>) -> bool {
+        let present = self.has(&key);
+        self.len = self.len - usize::from(present) + usize::from(value.is_some());
+        let mut replaced = None;
+        self.staged = Some(Tree::<K, Option<V>, C>::insert_node(
+            self.staged.take(),
+            key,
+            value,
+            &self.tree.compare,
+            &mut replaced,
+        ));
+        self.staged_len += usize::from(replaced.is_none());
+        present
+    }
+
+    /// Applies the staged changes to the tree in key order, in
+    /// `O(m log(n + m))` time for `m` staged keys.
+    pub fn commit(self) {
+        for (key, value) in IntoIter::new(self.staged, self.staged_len) {
+            match value {
+                Some(value) => {
+                    self.tree.insert(key, value);
+                }
+                None => {
+                    self.tree.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Discards the staged changes, leaving the tree exactly as it was.
+    pub fn rollback(self) {}
+}
@@ -1,94 +1,957 @@
 use std::cmp::Ordering;
-use std::fmt::Write;
+use std::error::Error;
+use std::fmt::{self, Debug, Display, Formatter, Write};
+use std::mem;
+use std::ops::{Bound, RangeBounds};
 
-/// The pure Rust AVL tree core.
-pub struct Tree {
-    root: Option<Box<Node>>,
+#[path = "binary.rs"]
+mod binary;
+#[path = "durable.rs"]
+mod durable;
+#[path = "entry.rs"]
+mod entry;
+#[path = "history.rs"]
+mod history;
+#[path = "iter.rs"]
+mod iter;
+#[path = "json.rs"]
+mod json;
+#[path = "persist.rs"]
+mod persist;
+#[path = "persistent.rs"]
+mod persistent;
+#[cfg(feature = "serde")]
+#[path = "serde_impls.rs"]
+mod serde_impls;
+#[path = "transaction.rs"]
+mod transaction;
+
+pub use binary::{DeserializeError, FORMAT_VERSION, MAGIC};
+pub use durable::{
+    DurableError, DurableTree, LogRecord, WriteAheadLog, LOG_FILE, LOG_MAGIC, LOG_VERSION,
+    SNAPSHOT_FILE,
+};
+pub use entry::{Entry, OccupiedEntry, OccupiedError, VacantEntry};
+pub use history::{History, HistoryTree, Undoable};
+pub use iter::{IntoIter, Iter, IterMut, Keys, Range, Values, ValuesMut};
+pub use json::JsonError;
+pub use persistent::{PersistentIter, PersistentTree};
+pub use transaction::Transaction;
+
+/// Orders the keys of a [`Tree`].
+///
+/// Closures of the form `Fn(&K, &K) -> Ordering` implement this trait, so a
+/// runtime-selected order can be passed to [`Tree::with_comparator`].
+pub trait Comparator<K: ?Sized> {
+    fn compare(&self, left: &K, right: &K) -> Ordering;
+}
+
+/// The default comparator, which orders keys by their [`Ord`] implementation.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Natural;
+
+impl<K: Ord + ?Sized> Comparator<K> for Natural {
+    fn compare(&self, left: &K, right: &K) -> Ordering {
+        left.cmp(right)
+    }
+}
+
+impl<K: ?Sized, F: Fn(&K, &K) -> Ordering> Comparator<K> for F {
+    fn compare(&self, left: &K, right: &K) -> Ordering {
+        self(left, right)
+    }
+}
+
+/// The pure Rust AVL tree core, ordered by `C` and storing one `V` per key.
+#[derive(Clone)]
+pub struct Tree<K, V, C = Natural> {
+    root: Link<K, V>,
     len: usize,
+    compare: C,
 }
 
-impl Tree {
+impl<K, V> Tree<K, V> {
     pub fn new() -> Self {
-        Self { root: None, len: 0 }
+        Self::with_comparator(Natural)
+    }
+}
+
+impl<K: Ord, V> Tree<K, V> {
+    /// Builds a perfectly balanced tree from entries in strictly ascending key
+    /// order in `O(n)` time, without any comparisons beyond the order check.
+    ///
+    /// Fails at the first entry whose key is not greater than the one before.
+    pub fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(
+        iter: I,
+    ) -> Result<Self, UnsortedError> {
+        Self::from_sorted_with(iter, Natural, false)
+    }
+
+    /// Like [`Tree::from_sorted_iter`], but accepts repeated keys in
+    /// non-decreasing input and keeps the last value for each, as repeated
+    /// [`Tree::insert`] calls would.
+    pub fn from_sorted_iter_dedup<I: IntoIterator<Item = (K, V)>>(
+        iter: I,
+    ) -> Result<Self, UnsortedError> {
+        Self::from_sorted_with(iter, Natural, true)
+    }
+}
+
+impl<K, V, C> Tree<K, V, C> {
+    /// Creates an empty tree that orders keys with `compare` instead of [`Ord`].
+    pub fn with_comparator(compare: C) -> Self {
+        Self {
+            root: None,
+            len: 0,
+            compare,
+        }
+    }
+
+    /// Turns on undo and redo for inserts and removals, keeping up to `depth`
+    /// steps. See [`History`].
+    pub fn with_history(self, depth: usize) -> HistoryTree<K, V, C> {
+        HistoryTree::new(self, depth)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of levels, counting a lone root as height 1 and an
+    /// empty tree as height 0.
+    pub fn height(&self) -> usize {
+        Node::height(&self.root) as usize
+    }
+
+    /// Summarizes the shape of the tree in `O(n)` time.
+    pub fn stats(&self) -> Stats {
+        // Every node lies on the search path of each node in its subtree, so
+        // the subtree sizes add up to the total search path length.
+        let mut total_depth = 0;
+        let mut pending: Vec<_> = self.root.as_deref().into_iter().collect();
+        while let Some(node) = pending.pop() {
+            total_depth += node.size;
+            pending.extend(node.left.as_deref());
+            pending.extend(node.right.as_deref());
+        }
+
+        Stats {
+            len: self.len,
+            height: self.height(),
+            height_bound: Self::height_bound(self.len),
+            node_bytes: self.len * mem::size_of::<Node<K, V>>(),
+            average_depth: if self.len == 0 {
+                0.0
+            } else {
+                total_depth as f64 / self.len as f64
+            },
+        }
     }
 
-    pub fn insert(&mut self, key: i32, value: String) {
-        let mut inserted = false;
+    /// Returns the greatest height an AVL tree with `len` nodes can reach,
+    /// which is the largest `h` whose sparsest AVL tree has at most `len`
+    /// nodes. Those minimum node counts grow like the Fibonacci numbers.
+    fn height_bound(len: usize) -> usize {
+        let (mut height, mut fewest, mut next) = (0, 0_usize, 1_usize);
+        while next <= len {
+            height += 1;
+            (fewest, next) = (next, next.saturating_add(fewest).saturating_add(1));
+        }
+        height
+    }
+
+    /// Returns the entry with the smallest key.
+    pub fn first(&self) -> Option<(&K, &V)> {
+        Self::leftmost(&self.root).map(|node| (&node.key, &node.value))
+    }
+
+    /// Returns the entry with the largest key.
+    pub fn last(&self) -> Option<(&K, &V)> {
+        Self::rightmost(&self.root).map(|node| (&node.key, &node.value))
+    }
+
+    fn leftmost(node: &Link<K, V>) -> Option<&Node<K, V>> {
+        let mut node = node.as_deref()?;
+        while let Some(left) = node.left.as_deref() {
+            node = left;
+        }
+        Some(node)
+    }
+
+    fn rightmost(node: &Link<K, V>) -> Option<&Node<K, V>> {
+        let mut node = node.as_deref()?;
+        while let Some(right) = node.right.as_deref() {
+            node = right;
+        }
+        Some(node)
+    }
+
+    /// Returns a double-ended iterator over the entries in ascending key order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter::new(&self.root, self.len)
+    }
+
+    /// Returns a double-ended iterator over the entries in ascending key order
+    /// with mutable access to each value.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut::new(&mut self.root, self.len)
+    }
+
+    /// Returns a double-ended iterator over the keys in ascending order.
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys::new(self.iter())
+    }
+
+    /// Returns a double-ended iterator over the values in ascending key order.
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values::new(self.iter())
+    }
+
+    /// Returns a double-ended iterator over mutable references to the values
+    /// in ascending key order.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut::new(self.iter_mut())
+    }
+
+    /// Removes every entry. Nodes are freed one at a time from an explicit
+    /// stack, so clearing never recurses.
+    pub fn clear(&mut self) {
+        let mut pending: Vec<_> = self.root.take().into_iter().collect();
+        self.len = 0;
+        while let Some(mut node) = pending.pop() {
+            pending.extend(node.left.take());
+            pending.extend(node.right.take());
+        }
+    }
+
+    /// Keeps only the entries for which `keep` returns `true`, calling it in
+    /// ascending key order. The survivors are rebuilt into a perfectly
+    /// balanced tree, so the whole call takes `O(n)` time whatever is removed.
+    /// If `keep` panics, the tree is left empty.
+    pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut keep: F) {
+        let entries = IntoIter::new(self.root.take(), mem::take(&mut self.len))
+            .filter_map(|(key, mut value)| keep(&key, &mut value).then_some((key, value)))
+            .collect();
+        self.build_balanced_from(entries);
+    }
+
+    /// Replaces the contents with a perfectly balanced tree over `entries`,
+    /// which must already be strictly ascending.
+    fn build_balanced_from(&mut self, entries: Vec<(K, V)>) {
+        self.len = entries.len();
+        self.root = Self::build_balanced(&mut entries.into_iter(), self.len);
+    }
+
+    /// Builds a subtree from the next `count` entries in order. Splitting the
+    /// count in half at every level keeps sibling heights within one, and the
+    /// recursion is only `O(log n)` deep.
+    fn build_balanced(entries: &mut impl Iterator<Item = (K, V)>, count: usize) -> Link<K, V> {
+        if count == 0 {
+            return None;
+        }
+        let left_count = count / 2;
+        let left = Self::build_balanced(entries, left_count);
+        let (key, value) = entries
+            .next()
+            .expect("the iterator holds at least `count` entries");
+        let mut node = Box::new(Node::new(key, value));
+        node.left = left;
+        node.right = Self::build_balanced(entries, count - left_count - 1);
+        node.update();
+        Some(node)
+    }
+
+    /// Returns the entry at zero-based `index` in sorted order.
+    pub fn select(&self, mut index: usize) -> Option<(&K, &V)> {
+        let mut current = self.root.as_deref();
+        while let Some(node) = current {
+            let left_size = Node::size(&node.left);
+            match index.cmp(&left_size) {
+                Ordering::Less => current = node.left.as_deref(),
+                Ordering::Equal => return Some((&node.key, &node.value)),
+                Ordering::Greater => {
+                    index -= left_size + 1;
+                    current = node.right.as_deref();
+                }
+            }
+        }
+        None
+    }
+
+    /// Puts a search path detached by [`Tree::entry`] back top-down and
+    /// returns the empty slot at its end.
+    fn reattach(root: &mut Link<K, V>, path: Path<K, V>) -> &mut Link<K, V> {
+        let mut slot = root;
+        for (node, side) in path {
+            let node = slot.insert(node);
+            slot = node.child_mut(side);
+        }
+        slot
+    }
+
+    /// Rebalances a detached search path for a new `leaf` in the slot at its
+    /// end. The path is rotated in place, so the leaf still goes at the end of
+    /// it; the returned node is the leaf, now holding any former ancestors that
+    /// a double rotation moved below it.
+    fn insert_into_path(path: &mut Path<K, V>, mut leaf: Box<Node<K, V>>) -> Box<Node<K, V>> {
+        // `holes[i]` is the height and size of the subtree missing below
+        // `path[i]`, after the insert.
+        let mut holes = vec![(0, 0); path.len()];
+        let mut below = (1, 1);
+        for index in (0..path.len()).rev() {
+            holes[index] = below;
+            let (node, side) = &mut path[index];
+            let old_height = node.height;
+            node.update_around(*side, below);
+            if below.0 - Node::height(node.child(side.reverse())) > 1 {
+                Self::rotate_path(path, index, &holes, &mut leaf);
+            } else if node.height != old_height {
+                below = (node.height, node.size);
+                continue;
+            }
+            for (node, _) in &mut path[..index] {
+                node.size += 1;
+            }
+            break;
+        }
+        leaf
+    }
+
+    /// Restores balance at `path[index]`, which is two levels taller on the
+    /// side of its slot. The rotated subtree keeps its height from before the
+    /// insert, so the ancestors above it only grow in size.
+    fn rotate_path(
+        path: &mut Path<K, V>,
+        index: usize,
+        holes: &[(i32, usize)],
+        leaf: &mut Box<Node<K, V>>,
+    ) {
+        let mut rest = path.split_off(index);
+        let mut lower = rest.split_off(2);
+        let (mut child, inner) = rest.pop().expect("a heavy side holds the path");
+        let (mut node, outer) = rest.pop().expect("the heavy node is on the path");
+
+        if inner == outer {
+            *node.child_mut(outer) = child.child_mut(outer.reverse()).take();
+            node.update();
+            *child.child_mut(outer.reverse()) = Some(node);
+            child.update_around(outer, holes[index + 1]);
+            path.push((child, outer));
+        } else if lower.is_empty() {
+            child.update();
+            node.update();
+            *leaf.child_mut(outer) = Some(child);
+            *leaf.child_mut(inner) = Some(node);
+            leaf.update();
+        } else {
+            let (mut grandchild, side) = lower.remove(0);
+            *child.child_mut(inner) = grandchild.child_mut(outer).take();
+            *node.child_mut(outer) = grandchild.child_mut(inner).take();
+            let hole = holes[index + 2];
+            let (upper, settled) = if side == outer {
+                node.update();
+                child.update_around(inner, hole);
+                ((child, inner), (node, inner))
+            } else {
+                child.update();
+                node.update_around(outer, hole);
+                ((node, outer), (child, outer))
+            };
+            *grandchild.child_mut(settled.1) = Some(settled.0);
+            grandchild.update_around(side, (upper.0.height, upper.0.size));
+            path.push((grandchild, side));
+            path.push(upper);
+        }
+        path.append(&mut lower);
+    }
+}
+
+impl<K, V, C: Comparator<K>> Tree<K, V, C> {
+    /// Inserts `value` under `key` and returns the value it replaced, if any.
+    /// A replaced entry keeps its original key.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let mut replaced = None;
         self.root = Some(Self::insert_node(
             self.root.take(),
             key,
             value,
-            &mut inserted,
+            &self.compare,
+            &mut replaced,
         ));
-        self.len += usize::from(inserted);
+        self.len += usize::from(replaced.is_none());
+        replaced
     }
 
-    pub fn find(&self, key: i32) -> Option<&str> {
-        Self::search_node(&self.root, key).map(String::as_str)
+    /// Inserts `value` under `key` only when the key is absent, returning a
+    /// reference to the stored value. A present key is left unchanged, and the
+    /// error hands back its entry together with the rejected `value`.
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<&mut V, OccupiedError<'_, K, V>> {
+        match self.entry(key) {
+            Entry::Occupied(entry) => Err(OccupiedError { entry, value }),
+            Entry::Vacant(entry) => Ok(entry.insert(value)),
+        }
     }
 
-    pub fn remove(&mut self, key: i32) -> Option<String> {
-        let (new_root, removed) = Self::remove_node(self.root.take(), key);
+    pub fn find(&self, key: &K) -> Option<&V> {
+        Self::search_node(&self.root, key, &self.compare)
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (new_root, removed) = Self::remove_node(self.root.take(), key, &self.compare);
         self.root = new_root;
         self.len -= usize::from(removed.is_some());
         removed
     }
 
-    pub fn has(&self, key: i32) -> bool {
-        Self::search_node(&self.root, key).is_some()
+    /// Removes and returns the entry with the smallest key.
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        let (root, first) = Self::remove_min(self.root.take()?);
+        self.root = root;
+        self.len -= 1;
+        Some((first.key, first.value))
     }
 
-    pub fn dump(&self) -> String {
-        let mut output = String::with_capacity(self.len.saturating_mul(32));
-        let mut first = true;
-        Self::write_in_order(&self.root, &mut output, &mut first);
-        output
+    /// Removes and returns the entry with the largest key.
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        let (root, last) = Self::remove_max(self.root.take()?);
+        self.root = root;
+        self.len -= 1;
+        Some((last.key, last.value))
     }
 
-    pub fn len(&self) -> usize {
-        self.len
+    /// Returns a mutable reference to the value stored under `key`.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let mut current = self.root.as_deref_mut();
+        while let Some(node) = current {
+            match self.compare.compare(key, &node.key) {
+                Ordering::Less => current = node.left.as_deref_mut(),
+                Ordering::Greater => current = node.right.as_deref_mut(),
+                Ordering::Equal => return Some(&mut node.value),
+            }
+        }
+        None
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.len == 0
+    /// Returns the [`Entry`] for `key`, for updating or inserting a value in
+    /// place, after one `O(log n)` search.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, C> {
+        let mut path = Vec::new();
+        let mut current = self.root.take();
+        while let Some(mut node) = current {
+            let side = self.compare.compare(&key, &node.key);
+            if side == Ordering::Equal {
+                let node = Self::reattach(&mut self.root, path).insert(node);
+                return Entry::Occupied(OccupiedEntry::new(&node.key, &mut node.value));
+            }
+            current = node.child_mut(side).take();
+            path.push((node, side));
+        }
+        Entry::Vacant(VacantEntry::new(key, self, path))
+    }
+
+    pub fn has(&self, key: &K) -> bool {
+        Self::search_node(&self.root, key, &self.compare).is_some()
+    }
+
+    /// Starts a [`Transaction`] that stages inserts and removals and applies
+    /// them only when committed.
+    pub fn transaction(&mut self) -> Transaction<'_, K, V, C> {
+        Transaction::new(self)
+    }
+
+    /// Returns the zero-based position of `key` in sorted order, or `None`
+    /// when it is absent.
+    pub fn rank(&self, key: &K) -> Option<usize> {
+        let (count, found) = self.count_below(key);
+        found.then_some(count)
+    }
+
+    /// Counts the keys strictly less than `key`, which need not be present.
+    pub fn count_less_than(&self, key: &K) -> usize {
+        self.count_below(key).0
+    }
+
+    /// Sums subtree sizes to the left of the search path for `key`, and
+    /// reports whether the search found it.
+    fn count_below(&self, key: &K) -> (usize, bool) {
+        let mut current = self.root.as_deref();
+        let mut count = 0;
+        while let Some(node) = current {
+            match self.compare.compare(key, &node.key) {
+                Ordering::Less => current = node.left.as_deref(),
+                Ordering::Equal => return (count + Node::size(&node.left), true),
+                Ordering::Greater => {
+                    count += Node::size(&node.left) + 1;
+                    current = node.right.as_deref();
+                }
+            }
+        }
+        (count, false)
+    }
+
+    /// Returns the entry with the largest key less than or equal to `key`.
+    pub fn floor(&self, key: &K) -> Option<(&K, &V)> {
+        self.nearest(key, Ordering::Less, true)
+    }
+
+    /// Returns the entry with the smallest key greater than or equal to `key`.
+    pub fn ceiling(&self, key: &K) -> Option<(&K, &V)> {
+        self.nearest(key, Ordering::Greater, true)
+    }
+
+    /// Returns the entry with the largest key strictly less than `key`.
+    pub fn predecessor(&self, key: &K) -> Option<(&K, &V)> {
+        self.nearest(key, Ordering::Less, false)
+    }
+
+    /// Returns the entry with the smallest key strictly greater than `key`.
+    pub fn successor(&self, key: &K) -> Option<(&K, &V)> {
+        self.nearest(key, Ordering::Greater, false)
+    }
+
+    /// Splits the tree at `key`, keeping the keys less than `key` and returning
+    /// a tree with the rest, like [`BTreeMap::split_off`].
+    ///
+    /// Runs in `O(log n)` time: the search path is cut out and the subtrees
+    /// hanging off it are joined back by height.
+    ///
+    /// [`BTreeMap::split_off`]: std::collections::BTreeMap::split_off
+    pub fn split_off(&mut self, key: &K) -> Self
+    where
+        C: Clone,
+    {
+        let (lower, upper) = Self::split_around(self.root.take(), key, &self.compare, false);
+        self.root = lower;
+        self.len = Node::size(&self.root);
+        Self {
+            len: Node::size(&upper),
+            root: upper,
+            compare: self.compare.clone(),
+        }
+    }
+
+    /// Moves every entry of `other` into this tree, leaving `other` empty.
+    ///
+    /// When all keys of one tree precede all keys of the other, the trees are
+    /// concatenated in `O(log n)` time. Overlapping trees fall back to inserting
+    /// each entry of `other` in `O(m log(n + m))` time, with values from
+    /// `other` replacing those for equal keys, like [`BTreeMap::append`].
+    ///
+    /// [`BTreeMap::append`]: std::collections::BTreeMap::append
+    pub fn append(&mut self, other: &mut Self) {
+        let other_len = mem::take(&mut other.len);
+        let other_root = other.root.take();
+        let (Some((first, _)), Some((last, _))) = (self.first(), self.last()) else {
+            self.root = other_root;
+            self.len = other_len;
+            return;
+        };
+        let (Some(other_first), Some(other_last)) =
+            (Self::leftmost(&other_root), Self::rightmost(&other_root))
+        else {
+            return;
+        };
+
+        if self.compare.compare(last, &other_first.key) == Ordering::Less {
+            self.root = Self::join2(self.root.take(), other_root);
+            self.len += other_len;
+        } else if self.compare.compare(&other_last.key, first) == Ordering::Less {
+            self.root = Self::join2(other_root, self.root.take());
+            self.len += other_len;
+        } else {
+            for (key, value) in IntoIter::new(other_root, other_len) {
+                self.insert(key, value);
+            }
+        }
+    }
+
+    /// Combines two trees into one holding every key of either, calling
+    /// `merge(key, self_value, other_value)` for keys present in both. Both
+    /// trees must order keys the same way; the result keeps this tree's
+    /// comparator and, for shared keys, this tree's key.
+    ///
+    /// Runs in `O(m log(n / m + 1))` time for sizes `m <= n`: each node of one
+    /// tree splits the other, and the pieces are rejoined by height.
+    pub fn union<F: FnMut(&K, V, V) -> V>(mut self, mut other: Self, mut merge: F) -> Self {
+        self.root = Self::union_nodes(
+            self.root.take(),
+            other.root.take(),
+            &self.compare,
+            &mut merge,
+        );
+        self.len = Node::size(&self.root);
+        self
+    }
+
+    /// Keeps the entries of this tree whose keys also occur in `other`.
+    ///
+    /// Runs in `O(m log(n / m + 1))` time for sizes `m <= n`.
+    pub fn intersection(mut self, mut other: Self) -> Self {
+        self.root = Self::intersection_nodes(self.root.take(), other.root.take(), &self.compare);
+        self.len = Node::size(&self.root);
+        self
+    }
+
+    /// Keeps the entries of this tree whose keys do not occur in `other`.
+    ///
+    /// Runs in `O(m log(n / m + 1))` time for sizes `m <= n`.
+    pub fn difference(mut self, mut other: Self) -> Self {
+        self.root = Self::difference_nodes(self.root.take(), other.root.take(), &self.compare);
+        self.len = Node::size(&self.root);
+        self
+    }
+
+    /// Keeps the entries whose keys occur in exactly one of the two trees.
+    ///
+    /// Runs in `O(m log(n / m + 1))` time for sizes `m <= n`.
+    pub fn symmetric_difference(mut self, mut other: Self) -> Self {
+        self.root =
+            Self::symmetric_difference_nodes(self.root.take(), other.root.take(), &self.compare);
+        self.len = Node::size(&self.root);
+        self
+    }
+
+    fn union_nodes<F: FnMut(&K, V, V) -> V>(
+        left: Link<K, V>,
+        right: Link<K, V>,
+        compare: &C,
+        merge: &mut F,
+    ) -> Link<K, V> {
+        let Some(right) = right else {
+            return left;
+        };
+        if left.is_none() {
+            return Some(right);
+        }
+        let (right_lower, mut pivot, right_upper) = Self::expose(right);
+        let (left_lower, found, left_upper) = Self::split(left, &pivot.key, compare);
+        let lower = Self::union_nodes(left_lower, right_lower, compare, merge);
+        let upper = Self::union_nodes(left_upper, right_upper, compare, merge);
+        if let Some(mut found) = found {
+            found.value = merge(&found.key, found.value, pivot.value);
+            pivot = found;
+        }
+        Some(Self::join(lower, pivot, upper))
+    }
+
+    fn intersection_nodes(left: Link<K, V>, right: Link<K, V>, compare: &C) -> Link<K, V> {
+        let (Some(_), Some(right)) = (&left, right) else {
+            return None;
+        };
+        let (right_lower, pivot, right_upper) = Self::expose(right);
+        let (left_lower, found, left_upper) = Self::split(left, &pivot.key, compare);
+        let lower = Self::intersection_nodes(left_lower, right_lower, compare);
+        let upper = Self::intersection_nodes(left_upper, right_upper, compare);
+        match found {
+            Some(found) => Some(Self::join(lower, found, upper)),
+            None => Self::join2(lower, upper),
+        }
+    }
+
+    fn difference_nodes(left: Link<K, V>, right: Link<K, V>, compare: &C) -> Link<K, V> {
+        let (Some(_), Some(right)) = (&left, right) else {
+            return left;
+        };
+        let (right_lower, pivot, right_upper) = Self::expose(right);
+        let (left_lower, _, left_upper) = Self::split(left, &pivot.key, compare);
+        let lower = Self::difference_nodes(left_lower, right_lower, compare);
+        let upper = Self::difference_nodes(left_upper, right_upper, compare);
+        Self::join2(lower, upper)
+    }
+
+    fn symmetric_difference_nodes(left: Link<K, V>, right: Link<K, V>, compare: &C) -> Link<K, V> {
+        let Some(right) = right else {
+            return left;
+        };
+        if left.is_none() {
+            return Some(right);
+        }
+        let (right_lower, pivot, right_upper) = Self::expose(right);
+        let (left_lower, found, left_upper) = Self::split(left, &pivot.key, compare);
+        let lower = Self::symmetric_difference_nodes(left_lower, right_lower, compare);
+        let upper = Self::symmetric_difference_nodes(left_upper, right_upper, compare);
+        match found {
+            Some(_) => Self::join2(lower, upper),
+            None => Some(Self::join(lower, pivot, upper)),
+        }
+    }
+
+    /// Detaches the root of a subtree from its children.
+    fn expose(mut node: Box<Node<K, V>>) -> Exposed<K, V> {
+        let left = node.left.take();
+        let right = node.right.take();
+        (left, node, right)
+    }
+
+    /// Divides a subtree into the keys less than `key`, the node holding
+    /// `key` if present (detached from its children), and the keys greater
+    /// than `key`, joining each side back together on the way up.
+    fn split(node: Link<K, V>, key: &K, compare: &C) -> (Link<K, V>, Link<K, V>, Link<K, V>) {
+        let Some(node) = node else {
+            return (None, None, None);
+        };
+        let (left, node, right) = Self::expose(node);
+
+        match compare.compare(key, &node.key) {
+            Ordering::Less => {
+                let (lower, found, upper) = Self::split(left, key, compare);
+                (lower, found, Some(Self::join(upper, node, right)))
+            }
+            Ordering::Greater => {
+                let (lower, found, upper) = Self::split(right, key, compare);
+                (Some(Self::join(left, node, lower)), found, upper)
+            }
+            Ordering::Equal => (left, Some(node), right),
+        }
+    }
+
+    /// Divides a subtree into the keys before and after `key`, keeping the node
+    /// holding `key` on the lower side when `key_below` is set.
+    fn split_around(
+        node: Link<K, V>,
+        key: &K,
+        compare: &C,
+        key_below: bool,
+    ) -> (Link<K, V>, Link<K, V>) {
+        let (lower, found, upper) = Self::split(node, key, compare);
+        match found {
+            Some(node) if key_below => (Some(Self::join(lower, node, None)), upper),
+            Some(node) => (lower, Some(Self::join(None, node, upper))),
+            None => (lower, upper),
+        }
+    }
+
+    /// Joins two subtrees around a detached `middle` node, where every key in
+    /// `left` precedes `middle` and every key in `right` follows it.
+    ///
+    /// Descends the spine of the taller subtree until the heights are within
+    /// one, attaches there, and rebalances on the way back up, so the cost is
+    /// proportional to the height difference.
+    fn join(left: Link<K, V>, mut middle: Box<Node<K, V>>, right: Link<K, V>) -> Box<Node<K, V>> {
+        let left_height = Node::height(&left);
+        let right_height = Node::height(&right);
+
+        if left_height > right_height + 1 {
+            let mut left = left.expect("a taller left subtree is not empty");
+            left.right = Some(Self::join(left.right.take(), middle, right));
+            left.update();
+            Self::balance(left)
+        } else if right_height > left_height + 1 {
+            let mut right = right.expect("a taller right subtree is not empty");
+            right.left = Some(Self::join(left, middle, right.left.take()));
+            right.update();
+            Self::balance(right)
+        } else {
+            middle.left = left;
+            middle.right = right;
+            middle.update();
+            middle
+        }
+    }
+
+    /// Concatenates two subtrees where every key in `left` precedes every key
+    /// in `right`, using the minimum of `right` as the joining node.
+    fn join2(left: Link<K, V>, right: Link<K, V>) -> Link<K, V> {
+        let Some(right) = right else {
+            return left;
+        };
+        let (right, minimum) = Self::remove_min(right);
+        Some(Self::join(left, minimum, right))
+    }
+
+    /// Returns a double-ended iterator over the entries whose keys fall within
+    /// `range`, in ascending key order.
+    ///
+    /// Positioning both ends takes `O(log n)` time and each step is amortized
+    /// `O(1)`. A range whose start lies after its end is empty.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V> {
+        let (start, end) = (range.start_bound(), range.end_bound());
+        let remaining = self.count_bounds(start, end);
+        Range::new(&self.root, start, end, &self.compare, remaining)
+    }
+
+    /// Counts the keys within `range` in `O(log n)` time without visiting them.
+    pub fn count_range<R: RangeBounds<K>>(&self, range: R) -> usize {
+        self.count_bounds(range.start_bound(), range.end_bound())
+    }
+
+    /// Removes the entries whose keys fall within `range` and returns them as
+    /// an owning iterator in ascending key order. Entries the iterator does
+    /// not yield are dropped with it.
+    ///
+    /// The range is cut out and the rest of the tree rejoined in `O(log n)`
+    /// time. A range whose start lies after its end removes nothing.
+    pub fn drain_range<R: RangeBounds<K>>(&mut self, range: R) -> IntoIter<K, V> {
+        let (start, end) = (range.start_bound(), range.end_bound());
+        let count = self.count_bounds(start, end);
+        if count == 0 {
+            return IntoIter::new(None, 0);
+        }
+
+        let root = self.root.take();
+        let (below, rest) = match start {
+            Bound::Included(start) => Self::split_around(root, start, &self.compare, false),
+            Bound::Excluded(start) => Self::split_around(root, start, &self.compare, true),
+            Bound::Unbounded => (None, root),
+        };
+        let (drained, above) = match end {
+            Bound::Included(end) => Self::split_around(rest, end, &self.compare, true),
+            Bound::Excluded(end) => Self::split_around(rest, end, &self.compare, false),
+            Bound::Unbounded => (rest, None),
+        };
+        self.root = Self::join2(below, above);
+        self.len -= count;
+        IntoIter::new(drained, count)
+    }
+
+    /// Subtracts the sorted positions of both bounds.
+    fn count_bounds(&self, start: Bound<&K>, end: Bound<&K>) -> usize {
+        let below_start = match start {
+            Bound::Included(key) => self.count_less_than(key),
+            Bound::Excluded(key) => {
+                let (count, found) = self.count_below(key);
+                count + usize::from(found)
+            }
+            Bound::Unbounded => 0,
+        };
+        let through_end = match end {
+            Bound::Included(key) => {
+                let (count, found) = self.count_below(key);
+                count + usize::from(found)
+            }
+            Bound::Excluded(key) => self.count_less_than(key),
+            Bound::Unbounded => self.len,
+        };
+        through_end.saturating_sub(below_start)
+    }
+
+    fn from_sorted_with<I: IntoIterator<Item = (K, V)>>(
+        iter: I,
+        compare: C,
+        keep_last: bool,
+    ) -> Result<Self, UnsortedError> {
+        let entries = Self::sorted_entries(iter, &compare, keep_last)?;
+        let mut tree = Self::with_comparator(compare);
+        tree.build_balanced_from(entries);
+        Ok(tree)
+    }
+
+    /// Collects entries while checking that keys ascend, merging runs of equal
+    /// keys into their first key and last value when `keep_last` is set.
+    fn sorted_entries<I: IntoIterator<Item = (K, V)>>(
+        iter: I,
+        compare: &C,
+        keep_last: bool,
+    ) -> Result<Vec<(K, V)>, UnsortedError> {
+        let iter = iter.into_iter();
+        let mut entries: Vec<(K, V)> = Vec::with_capacity(iter.size_hint().0);
+        for (index, (key, value)) in iter.enumerate() {
+            if let Some((last_key, last_value)) = entries.last_mut() {
+                match compare.compare(last_key, &key) {
+                    Ordering::Less => {}
+                    Ordering::Equal if keep_last => {
+                        *last_value = value;
+                        continue;
+                    }
+                    _ => return Err(UnsortedError { index }),
+                }
+            }
+            entries.push((key, value));
+        }
+        Ok(entries)
+    }
+
+    /// Fills an empty tree from entries in any order, keeping the last value
+    /// for a repeated key. Input that is already sorted skips the sort and is
+    /// built in `O(n)` time; anything else costs one stable sort.
+    fn build_from_unsorted<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        debug_assert!(self.is_empty());
+        let mut entries: Vec<(K, V)> = iter.into_iter().collect();
+        let compare = &self.compare;
+        if entries
+            .windows(2)
+            .any(|pair| compare.compare(&pair[0].0, &pair[1].0) == Ordering::Greater)
+        {
+            entries.sort_by(|left, right| compare.compare(&left.0, &right.0));
+        }
+        let entries =
+            Self::sorted_entries(entries, compare, true).expect("sorted entries never descend");
+        self.build_balanced_from(entries);
+    }
+
+    /// Finds the closest key on the `side` of `key`, or `key` itself when
+    /// `inclusive`, remembering the last qualifying node on the search path.
+    fn nearest(&self, key: &K, side: Ordering, inclusive: bool) -> Option<(&K, &V)> {
+        let mut current = self.root.as_deref();
+        let mut candidate = None;
+        while let Some(node) = current {
+            let order = self.compare.compare(&node.key, key);
+            if order == Ordering::Equal && inclusive {
+                return Some((&node.key, &node.value));
+            }
+            if order == side {
+                candidate = Some(node);
+            }
+            current = match (order, side) {
+                (Ordering::Less, _) | (Ordering::Equal, Ordering::Greater) => node.right.as_deref(),
+                _ => node.left.as_deref(),
+            };
+        }
+        candidate.map(|node| (&node.key, &node.value))
     }
 
     fn insert_node(
-        node: Option<Box<Node>>,
-        key: i32,
-        value: String,
-        inserted: &mut bool,
-    ) -> Box<Node> {
+        node: Link<K, V>,
+        key: K,
+        value: V,
+        compare: &C,
+        replaced: &mut Option<V>,
+    ) -> Box<Node<K, V>> {
         if let Some(mut node) = node {
-            match key.cmp(&node.key) {
+            match compare.compare(&key, &node.key) {
                 Ordering::Less => {
-                    node.left = Some(Self::insert_node(node.left.take(), key, value, inserted));
+                    node.left = Some(Self::insert_node(
+                        node.left.take(),
+                        key,
+                        value,
+                        compare,
+                        replaced,
+                    ));
                 }
                 Ordering::Greater => {
-                    node.right = Some(Self::insert_node(node.right.take(), key, value, inserted));
+                    node.right = Some(Self::insert_node(
+                        node.right.take(),
+                        key,
+                        value,
+                        compare,
+                        replaced,
+                    ));
                 }
                 Ordering::Equal => {
-                    node.value = value;
+                    *replaced = Some(mem::replace(&mut node.value, value));
                     return node;
                 }
             }
 
-            if *inserted {
-                node.update_height();
+            if replaced.is_none() {
+                node.update();
                 Self::balance(node)
             } else {
                 node
             }
         } else {
-            *inserted = true;
             Box::new(Node::new(key, value))
         }
     }
 
-    fn search_node(node: &Option<Box<Node>>, key: i32) -> Option<&String> {
+    fn search_node<'a>(node: &'a Link<K, V>, key: &K, compare: &C) -> Option<&'a V> {
         let mut current = node.as_ref();
         while let Some(node) = current {
-            match key.cmp(&node.key) {
+            match compare.compare(key, &node.key) {
                 Ordering::Less => current = node.left.as_ref(),
                 Ordering::Greater => current = node.right.as_ref(),
                 Ordering::Equal => return Some(&node.value),
@@ -97,7 +960,7 @@ impl Tree {
         None
     }
 
-    fn balance(mut node: Box<Node>) -> Box<Node> {
+    fn balance(mut node: Box<Node<K, V>>) -> Box<Node<K, V>> {
         let balance_factor = node.balance_factor();
 
         if balance_factor > 1 {
@@ -135,66 +998,52 @@ impl Tree {
         }
     }
 
-    fn rotate_right(mut root: Box<Node>) -> Box<Node> {
+    fn rotate_right(mut root: Box<Node<K, V>>) -> Box<Node<K, V>> {
         let mut pivot = root
             .left
             .take()
             .expect("right rotation requires a left child");
         root.left = pivot.right.take();
-        root.update_height();
+        root.update();
         pivot.right = Some(root);
-        pivot.update_height();
+        pivot.update();
         pivot
     }
 
-    fn rotate_left(mut root: Box<Node>) -> Box<Node> {
+    fn rotate_left(mut root: Box<Node<K, V>>) -> Box<Node<K, V>> {
         let mut pivot = root
             .right
             .take()
             .expect("left rotation requires a right child");
         root.right = pivot.left.take();
-        root.update_height();
+        root.update();
         pivot.left = Some(root);
-        pivot.update_height();
+        pivot.update();
         pivot
     }
 
-    fn write_in_order(node: &Option<Box<Node>>, output: &mut String, first: &mut bool) {
-        if let Some(node) = node {
-            Self::write_in_order(&node.left, output, first);
-            if *first {
-                *first = false;
-            } else {
-                output.push_str(", ");
-            }
-            write!(output, "{{ key: {}, value: '{}' }}", node.key, node.value)
-                .expect("writing to a String cannot fail");
-            Self::write_in_order(&node.right, output, first);
-        }
-    }
-
-    fn remove_node(node: Option<Box<Node>>, key: i32) -> (Option<Box<Node>>, Option<String>) {
+    fn remove_node(node: Link<K, V>, key: &K, compare: &C) -> (Link<K, V>, Option<V>) {
         let Some(mut node) = node else {
             return (None, None);
         };
 
-        match key.cmp(&node.key) {
+        match compare.compare(key, &node.key) {
             Ordering::Less => {
-                let (new_left, removed) = Self::remove_node(node.left.take(), key);
+                let (new_left, removed) = Self::remove_node(node.left.take(), key, compare);
                 node.left = new_left;
                 let Some(removed) = removed else {
                     return (Some(node), None);
                 };
-                node.update_height();
+                node.update();
                 (Some(Self::balance(node)), Some(removed))
             }
             Ordering::Greater => {
-                let (new_right, removed) = Self::remove_node(node.right.take(), key);
+                let (new_right, removed) = Self::remove_node(node.right.take(), key, compare);
                 node.right = new_right;
                 let Some(removed) = removed else {
                     return (Some(node), None);
                 };
-                node.update_height();
+                node.update();
                 (Some(Self::balance(node)), Some(removed))
             }
             Ordering::Equal => {
@@ -209,7 +1058,7 @@ impl Tree {
                         let (new_right, mut successor) = Self::remove_min(right);
                         successor.left = Some(left);
                         successor.right = new_right;
-                        successor.update_height();
+                        successor.update();
                         (Some(Self::balance(successor)), Some(value))
                     }
                 }
@@ -217,7 +1066,7 @@ impl Tree {
         }
     }
 
-    fn remove_min(mut node: Box<Node>) -> (Option<Box<Node>>, Box<Node>) {
+    fn remove_min(mut node: Box<Node<K, V>>) -> (Link<K, V>, Box<Node<K, V>>) {
         if node.left.is_none() {
             (node.right.take(), node)
         } else {
@@ -227,43 +1076,337 @@ impl Tree {
                 .expect("a non-minimum node must have a left child");
             let (new_left, minimum) = Self::remove_min(left);
             node.left = new_left;
-            node.update_height();
+            node.update();
             (Some(Self::balance(node)), minimum)
         }
     }
+
+    fn remove_max(mut node: Box<Node<K, V>>) -> (Link<K, V>, Box<Node<K, V>>) {
+        if node.right.is_none() {
+            (node.left.take(), node)
+        } else {
+            let right = node
+                .right
+                .take()
+                .expect("a non-maximum node must have a right child");
+            let (new_right, maximum) = Self::remove_max(right);
+            node.right = new_right;
+            node.update();
+            (Some(Self::balance(node)), maximum)
+        }
+    }
 }
 
-impl Default for Tree {
+impl<K: Clone, V, C: Comparator<K>> Tree<K, V, C> {
+    /// Checks every structural invariant and reports the first violation
+    /// found: key order against each ancestor, then the stored height, balance
+    /// factor, and subtree size of each node bottom-up, then the entry count.
+    ///
+    /// Runs in `O(n)` time.
+    pub fn validate(&self) -> Result<(), InvariantViolation<K>> {
+        let (_, actual) = Self::validate_node(&self.root, &self.compare, None, None)?;
+        if actual != self.len {
+            return Err(InvariantViolation::LenMismatch {
+                stored: self.len,
+                actual,
+            });
+        }
+        Ok(())
+    }
+
+    /// Validates a subtree whose keys must lie strictly between `lower` and
+    /// `upper`, returning its height and size.
+    fn validate_node(
+        node: &Link<K, V>,
+        compare: &C,
+        lower: Option<&K>,
+        upper: Option<&K>,
+    ) -> Result<(i32, usize), InvariantViolation<K>> {
+        let Some(node) = node else {
+            return Ok((0, 0));
+        };
+
+        let misplaced = |ancestor: &K| InvariantViolation::Unordered {
+            key: node.key.clone(),
+            ancestor: ancestor.clone(),
+        };
+        if let Some(lower) = lower.filter(|lower| compare.compare(&node.key, lower).is_le()) {
+            return Err(misplaced(lower));
+        }
+        if let Some(upper) = upper.filter(|upper| compare.compare(&node.key, upper).is_ge()) {
+            return Err(misplaced(upper));
+        }
+
+        let (left_height, left_size) =
+            Self::validate_node(&node.left, compare, lower, Some(&node.key))?;
+        let (right_height, right_size) =
+            Self::validate_node(&node.right, compare, Some(&node.key), upper)?;
+
+        let height = 1 + left_height.max(right_height);
+        if node.height != height {
+            return Err(InvariantViolation::HeightMismatch {
+                key: node.key.clone(),
+                stored: node.height,
+                actual: height,
+            });
+        }
+        let balance_factor = left_height - right_height;
+        if !(-1..=1).contains(&balance_factor) {
+            return Err(InvariantViolation::Unbalanced {
+                key: node.key.clone(),
+                balance_factor,
+            });
+        }
+        let size = 1 + left_size + right_size;
+        if node.size != size {
+            return Err(InvariantViolation::SizeMismatch {
+                key: node.key.clone(),
+                stored: node.size,
+                actual: size,
+            });
+        }
+        Ok((height, size))
+    }
+}
+
+impl<K: Display, V: Display, C> Tree<K, V, C> {
+    pub fn dump(&self) -> String {
+        dump_entries(self.len, self)
+    }
+}
+
+/// Writes `len` entries in the format of [`Tree::dump`].
+fn dump_entries<'a, K: Display + 'a, V: Display + 'a>(
+    len: usize,
+    entries: impl IntoIterator<Item = (&'a K, &'a V)>,
+) -> String {
+    let mut output = String::with_capacity(len.saturating_mul(32));
+    for (index, (key, value)) in entries.into_iter().enumerate() {
+        if index > 0 {
+            output.push_str(", ");
+        }
+        write!(output, "{{ key: {key}, value: '{value}' }}")
+            .expect("writing to a String cannot fail");
+    }
+    output
+}
+
+impl<K, V, C: Default> Default for Tree<K, V, C> {
     fn default() -> Self {
-        Self::new()
+        Self::with_comparator(C::default())
+    }
+}
+
+impl<K, V, C: Comparator<K> + Default> FromIterator<(K, V)> for Tree<K, V, C> {
+    /// Collects entries in any order, keeping the last value for a repeated
+    /// key. Sorted input is built in `O(n)` time.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut tree = Self::default();
+        tree.build_from_unsorted(iter);
+        tree
+    }
+}
+
+impl<K, V, C: Comparator<K>> Extend<(K, V)> for Tree<K, V, C> {
+    /// Inserts every entry in order. Extending an empty tree takes the same
+    /// bulk path as [`FromIterator`].
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        if self.is_empty() {
+            self.build_from_unsorted(iter);
+        } else {
+            for (key, value) in iter {
+                self.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Shape statistics returned by [`Tree::stats`] and [`PersistentTree::stats`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stats {
+    /// The number of entries, which equals the number of nodes.
+    pub len: usize,
+    /// The number of levels, as returned by [`Tree::height`].
+    pub height: usize,
+    /// The greatest height the AVL balance condition allows for `len` nodes.
+    pub height_bound: usize,
+    /// The bytes allocated for nodes, excluding any heap data owned by the
+    /// keys and values themselves.
+    pub node_bytes: usize,
+    /// The mean number of nodes on the search path to an entry, counting the
+    /// entry itself, or `0` for an empty tree.
+    pub average_depth: f64,
+}
+
+/// The error returned by [`Tree::from_sorted_iter`] and
+/// [`Tree::from_sorted_iter_dedup`] when the input is out of order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnsortedError {
+    /// The zero-based position of the first entry whose key does not come
+    /// after the previous key.
+    pub index: usize,
+}
+
+impl Display for UnsortedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "entry {} is out of ascending key order", self.index)
+    }
+}
+
+impl Error for UnsortedError {}
+
+/// The first broken invariant found by [`Tree::validate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InvariantViolation<K> {
+    /// `key` is not strictly on the correct side of `ancestor`.
+    Unordered { key: K, ancestor: K },
+    /// The height stored at `key` differs from the height of its subtree.
+    HeightMismatch { key: K, stored: i32, actual: i32 },
+    /// The subtree heights below `key` differ by more than one.
+    Unbalanced { key: K, balance_factor: i32 },
+    /// The size stored at `key` differs from the size of its subtree.
+    SizeMismatch {
+        key: K,
+        stored: usize,
+        actual: usize,
+    },
+    /// The tree's entry count differs from the number of nodes.
+    LenMismatch { stored: usize, actual: usize },
+}
+
+impl<K: Debug> Display for InvariantViolation<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unordered { key, ancestor } => {
+                write!(
+                    f,
+                    "key {key:?} is on the wrong side of ancestor {ancestor:?}"
+                )
+            }
+            Self::HeightMismatch {
+                key,
+                stored,
+                actual,
+            } => write!(
+                f,
+                "key {key:?} stores height {stored} but its subtree has height {actual}"
+            ),
+            Self::Unbalanced {
+                key,
+                balance_factor,
+            } => write!(f, "key {key:?} has balance factor {balance_factor}"),
+            Self::SizeMismatch {
+                key,
+                stored,
+                actual,
+            } => write!(
+                f,
+                "key {key:?} stores size {stored} but its subtree has {actual} nodes"
+            ),
+            Self::LenMismatch { stored, actual } => {
+                write!(f, "tree length is {stored} but it holds {actual} nodes")
+            }
+        }
+    }
+}
+
+impl<K: Debug> Error for InvariantViolation<K> {}
+
+impl<'a, K, V, C> IntoIterator for &'a Tree<K, V, C> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, C> IntoIterator for &'a mut Tree<K, V, C> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
     }
 }
 
-struct Node {
-    key: i32,
-    value: String,
+impl<K, V, C> IntoIterator for Tree<K, V, C> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter::new(self.root, self.len)
+    }
+}
+
+type Link<K, V> = Option<Box<Node<K, V>>>;
+
+/// A node detached from its left and right subtrees.
+type Exposed<K, V> = (Link<K, V>, Box<Node<K, V>>, Link<K, V>);
+
+/// The nodes on a search path, root first, each detached from the child the
+/// search took: `Less` for the left child and `Greater` for the right.
+type Path<K, V> = Vec<(Box<Node<K, V>>, Ordering)>;
+
+#[derive(Clone)]
+struct Node<K, V> {
+    key: K,
+    value: V,
     height: i32,
-    left: Option<Box<Node>>,
-    right: Option<Box<Node>>,
+    size: usize,
+    left: Link<K, V>,
+    right: Link<K, V>,
 }
 
-impl Node {
-    fn new(key: i32, value: String) -> Self {
+impl<K, V> Node<K, V> {
+    fn new(key: K, value: V) -> Self {
         Self {
             key,
             value,
             height: 1,
+            size: 1,
             left: None,
             right: None,
         }
     }
 
-    fn height(node: &Option<Box<Node>>) -> i32 {
+    fn height(node: &Link<K, V>) -> i32 {
         node.as_ref().map_or(0, |node| node.height)
     }
 
-    fn update_height(&mut self) {
+    fn size(node: &Link<K, V>) -> usize {
+        node.as_ref().map_or(0, |node| node.size)
+    }
+
+    /// Recomputes the cached height and subtree size from the children.
+    fn update(&mut self) {
         self.height = 1 + Self::height(&self.left).max(Self::height(&self.right));
+        self.size = 1 + Self::size(&self.left) + Self::size(&self.right);
+    }
+
+    /// Recomputes the cached height and subtree size for a node whose child
+    /// on `side` is detached, given that child's height and size.
+    fn update_around(&mut self, side: Ordering, (height, size): (i32, usize)) {
+        let other = self.child(side.reverse());
+        let (other_height, other_size) = (Self::height(other), Self::size(other));
+        self.height = 1 + height.max(other_height);
+        self.size = 1 + size + other_size;
+    }
+
+    fn child(&self, side: Ordering) -> &Link<K, V> {
+        if side == Ordering::Less {
+            &self.left
+        } else {
+            &self.right
+        }
+    }
+
+    fn child_mut(&mut self, side: Ordering) -> &mut Link<K, V> {
+        if side == Ordering::Less {
+            &mut self.left
+        } else {
+            &mut self.right
+        }
     }
 
     fn balance_factor(&self) -> i32 {
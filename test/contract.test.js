@@ -3,6 +3,8 @@
 const assert = require('node:assert/strict');
 const { describe, it } = require('node:test');
 const { pathToFileURL } = require('node:url');
+const fs = require('node:fs');
+const os = require('node:os');
 const path = require('node:path');
 
 const AvlTree = require('..');
@@ -13,10 +15,75 @@ describe('module loading', () => {
     assert.equal(AvlTree.name, 'AvlTree');
     assert.deepEqual(
       Object.getOwnPropertyNames(AvlTree.prototype).sort(),
-      ['constructor', 'dump', 'find', 'has', 'insert', 'remove'].sort()
+      [
+        'ceiling',
+        'checkpoint',
+        'clear',
+        'compact',
+        'constructor',
+        'countLessThan',
+        'countRange',
+        'difference',
+        'disableHistory',
+        'dump',
+        'dumpJson',
+        'drainRange',
+        'enableHistory',
+        'entries',
+        'find',
+        'findMany',
+        'first',
+        'floor',
+        'forEach',
+        'getOrInsert',
+        'has',
+        'hasMany',
+        'height',
+        'insert',
+        'insertMany',
+        'intersection',
+        'isEmpty',
+        'keys',
+        'last',
+        'popFirst',
+        'popLast',
+        'predecessor',
+        'rank',
+        'range',
+        'redo',
+        'redoLabel',
+        'remove',
+        'removeMany',
+        'retain',
+        'saveToFile',
+        'select',
+        'serialize',
+        'set',
+        'size',
+        'snapshot',
+        'stats',
+        'successor',
+        'symmetricDifference',
+        'toArray',
+        'transaction',
+        'undo',
+        'undoLabel',
+        'union',
+        'update',
+        'validate',
+        'values',
+      ].sort()
     );
   });
 
+  it('exposes the static factories', () => {
+    assert.equal(typeof AvlTree.fromEntries, 'function');
+    assert.equal(typeof AvlTree.deserialize, 'function');
+    assert.equal(typeof AvlTree.loadFromFile, 'function');
+    assert.equal(typeof AvlTree.openDurable, 'function');
+    assert.ok(AvlTree.fromEntries([]) instanceof AvlTree);
+  });
+
   it('exports the same constructor as an ESM default import', async () => {
     const entry = pathToFileURL(path.join(__dirname, '..', 'index.js'));
     const imported = await import(entry.href);
@@ -35,6 +102,69 @@ describe('public contract', () => {
     assert.equal(tree.dump(), '');
   });
 
+  it('reports size, height, and shape statistics', () => {
+    const tree = new AvlTree();
+
+    assert.equal(tree.size, 0);
+    assert.equal(tree.isEmpty(), true);
+    assert.equal(tree.height(), 0);
+    assert.deepEqual(tree.stats(), {
+      size: 0,
+      height: 0,
+      heightBound: 0,
+      heapBytes: 0,
+      averageDepth: 0,
+    });
+
+    for (let key = 1; key <= 7; key += 1) {
+      tree.insert(key, 'value');
+    }
+    assert.equal(tree.size, 7);
+    assert.equal(tree.isEmpty(), false);
+    assert.equal(tree.height(), 3);
+    const stats = tree.stats();
+    assert.deepEqual(
+      { ...stats, heapBytes: undefined },
+      {
+        size: 7,
+        height: 3,
+        heightBound: 4,
+        heapBytes: undefined,
+        averageDepth: 17 / 7,
+      }
+    );
+    assert.ok(stats.heapBytes >= 7 * 'value'.length);
+
+    assert.throws(() => {
+      tree.size = 100;
+    }, TypeError);
+    assert.equal(tree.size, 7);
+    tree.clear();
+    assert.equal(tree.size, 0);
+  });
+
+  it('validates the invariants of trees built through every path', () => {
+    const tree = AvlTree.fromEntries([
+      [3, 'c'],
+      [1, 'a'],
+      [2, 'b'],
+    ]);
+    assert.equal(tree.validate(), undefined);
+
+    for (let key = 0; key < 200; key += 1) {
+      tree.insert((key * 37) % 101, 'x');
+    }
+    tree.drainRange(20, 40);
+    tree.retain((value, key) => key % 3 !== 0);
+    tree.popFirst();
+    assert.equal(tree.validate(), undefined);
+    assert.equal(new AvlTree().validate(), undefined);
+    assert.equal(
+      tree.union(AvlTree.fromEntries([[500, 'y']])).validate(),
+      undefined
+    );
+  });
+
   it('inserts, finds, replaces, checks, removes, and dumps values', () => {
     const tree = new AvlTree();
 
@@ -63,6 +193,1065 @@ describe('public contract', () => {
     );
   });
 
+  it('sets values and reports the value they replaced', () => {
+    const tree = new AvlTree();
+
+    assert.equal(tree.set(1, 'one'), null);
+    assert.equal(tree.set(1, 'uno'), 'one');
+    assert.equal(tree.insert(1, 'ein'), undefined);
+    assert.equal(tree.set(1, 'one'), 'ein');
+    assert.deepEqual(tree.toArray(), [[1, 'one']]);
+  });
+
+  it('updates and upserts values in place', () => {
+    const tree = new AvlTree();
+    const bump = (value) => String(Number(value ?? 0) + 1);
+
+    assert.equal(tree.update(7, bump), '1');
+    assert.equal(tree.update(7, bump), '2');
+    const seen = [];
+    tree.update(-1, (value, key) => {
+      seen.push([value, key]);
+      return 'minus one';
+    });
+    assert.deepEqual(seen, [[null, -1]]);
+
+    assert.equal(tree.getOrInsert(7, 'ignored'), '2');
+    assert.equal(tree.getOrInsert(8, 'eight'), 'eight');
+    assert.deepEqual(tree.toArray(), [
+      [-1, 'minus one'],
+      [7, '2'],
+      [8, 'eight'],
+    ]);
+  });
+
+  it('leaves the tree unchanged when an update callback fails', () => {
+    const tree = AvlTree.fromEntries([[1, 'one']]);
+
+    assert.throws(
+      () =>
+        tree.update(1, () => {
+          throw new Error('rejected');
+        }),
+      /rejected/
+    );
+    assert.throws(
+      () => tree.update(2, () => 2),
+      (error) => error.code === 'StringExpected'
+    );
+    assert.deepEqual(tree.toArray(), [[1, 'one']]);
+    assert.equal(
+      tree.update(1, (value) => {
+        assert.throws(() => tree.remove(1), /while one of its callbacks/);
+        assert.throws(
+          () => tree.insert(5, 'five'),
+          /while one of its callbacks/
+        );
+        assert.equal(tree.find(1), 'one');
+        return `${value}!`;
+      }),
+      'one!'
+    );
+    assert.deepEqual(tree.toArray(), [[1, 'one!']]);
+  });
+
+  it('pops, drains, retains, and clears entries', () => {
+    const tree = AvlTree.fromEntries(
+      Array.from({ length: 10 }, (_, key) => [key, `v${key}`])
+    );
+
+    assert.deepEqual(tree.popFirst(), [0, 'v0']);
+    assert.deepEqual(tree.popLast(), [9, 'v9']);
+    assert.deepEqual(tree.drainRange(3, 5), [
+      [3, 'v3'],
+      [4, 'v4'],
+    ]);
+    assert.deepEqual(tree.drainRange(7, null, { inclusive: true }), [
+      [7, 'v7'],
+      [8, 'v8'],
+    ]);
+    assert.deepEqual(tree.drainRange(6, 2), []);
+    assert.deepEqual([...tree.keys()], [1, 2, 5, 6]);
+
+    const seen = [];
+    assert.equal(
+      tree.retain((value, key) => {
+        seen.push([key, value]);
+        return key % 2 === 0 ? 'truthy' : 0;
+      }),
+      undefined
+    );
+    assert.deepEqual(seen, [
+      [1, 'v1'],
+      [2, 'v2'],
+      [5, 'v5'],
+      [6, 'v6'],
+    ]);
+    assert.deepEqual(tree.toArray(), [
+      [2, 'v2'],
+      [6, 'v6'],
+    ]);
+
+    assert.equal(tree.clear(), undefined);
+    assert.deepEqual(tree.toArray(), []);
+    assert.equal(tree.popFirst(), null);
+    assert.equal(tree.popLast(), null);
+  });
+
+  it('rejects retain callback writes and aborts on callback errors', () => {
+    const tree = AvlTree.fromEntries([
+      [1, 'one'],
+      [2, 'two'],
+      [3, 'three'],
+    ]);
+
+    assert.throws(
+      () =>
+        tree.retain((value, key) => {
+          if (key === 2) {
+            throw new Error('stop');
+          }
+          return false;
+        }),
+      /stop/
+    );
+    assert.deepEqual([...tree.keys()], [1, 2, 3]);
+
+    tree.retain((value, key) => {
+      if (key === 1) {
+        assert.throws(() => tree.remove(3), /while one of its callbacks/);
+        assert.throws(
+          () => tree.insert(4, 'four'),
+          /while one of its callbacks/
+        );
+        assert.equal(tree.size, 3);
+      }
+      return key !== 2;
+    });
+    assert.deepEqual(tree.toArray(), [
+      [1, 'one'],
+      [3, 'three'],
+    ]);
+  });
+
+  it('answers ordered navigation queries with pairs or null', () => {
+    const tree = new AvlTree();
+
+    assert.equal(tree.first(), null);
+    assert.equal(tree.last(), null);
+    assert.equal(tree.floor(1), null);
+    assert.equal(tree.ceiling(1), null);
+    assert.equal(tree.predecessor(1), null);
+    assert.equal(tree.successor(1), null);
+
+    for (const key of [40, 10, 30, 20]) {
+      tree.insert(key, `slot-${key}`);
+    }
+
+    assert.deepEqual(tree.first(), [10, 'slot-10']);
+    assert.deepEqual(tree.last(), [40, 'slot-40']);
+    assert.deepEqual(tree.floor(25), [20, 'slot-20']);
+    assert.deepEqual(tree.floor(30), [30, 'slot-30']);
+    assert.equal(tree.floor(9), null);
+    assert.deepEqual(tree.ceiling(25), [30, 'slot-30']);
+    assert.deepEqual(tree.ceiling(30), [30, 'slot-30']);
+    assert.equal(tree.ceiling(41), null);
+    assert.deepEqual(tree.predecessor(30), [20, 'slot-20']);
+    assert.equal(tree.predecessor(10), null);
+    assert.deepEqual(tree.successor(30), [40, 'slot-40']);
+    assert.equal(tree.successor(40), null);
+  });
+
+  it('answers rank and select queries by sorted position', () => {
+    const tree = new AvlTree();
+    const keys = Array.from({ length: 100 }, (_, index) => index * 10);
+    for (const key of [...keys].reverse()) {
+      tree.insert(key, `value-${key}`);
+    }
+
+    for (const [index, key] of keys.entries()) {
+      assert.equal(tree.rank(key), index);
+      assert.deepEqual(tree.select(index), [key, `value-${key}`]);
+      assert.equal(tree.countLessThan(key), index);
+      assert.equal(tree.countLessThan(key + 5), index + 1);
+    }
+    assert.equal(tree.rank(5), null);
+    assert.equal(tree.select(100), null);
+    assert.equal(tree.countLessThan(-1), 0);
+
+    tree.remove(0);
+    assert.equal(tree.rank(10), 0);
+    assert.deepEqual(tree.select(0), [10, 'value-10']);
+  });
+
+  it('returns key ranges as pairs with optional bounds', () => {
+    const tree = new AvlTree();
+    assert.deepEqual(tree.range(), []);
+    assert.equal(tree.countRange(), 0);
+
+    for (const key of [50, 10, 40, 20, 30]) {
+      tree.insert(key, `slot-${key}`);
+    }
+
+    assert.deepEqual(tree.range(20, 40), [
+      [20, 'slot-20'],
+      [30, 'slot-30'],
+    ]);
+    assert.deepEqual(tree.range(20, 40, { inclusive: true }), [
+      [20, 'slot-20'],
+      [30, 'slot-30'],
+      [40, 'slot-40'],
+    ]);
+    assert.deepEqual(tree.range(null, 20), [[10, 'slot-10']]);
+    assert.deepEqual(tree.range(45), [[50, 'slot-50']]);
+    assert.deepEqual(
+      tree.range().map(([key]) => key),
+      [10, 20, 30, 40, 50]
+    );
+    assert.deepEqual(tree.range(40, 20), []);
+    assert.equal(tree.countRange(15, 50), 3);
+    assert.equal(tree.countRange(15, 50, { inclusive: true }), 4);
+    assert.equal(tree.countRange(undefined, undefined), 5);
+  });
+
+  it('iterates entries, keys, and values in ascending key order', () => {
+    const tree = new AvlTree();
+    for (const key of [3, -1, 2, 10]) {
+      tree.insert(key, `value-${key}`);
+    }
+    const expected = [
+      [-1, 'value--1'],
+      [2, 'value-2'],
+      [3, 'value-3'],
+      [10, 'value-10'],
+    ];
+
+    assert.deepEqual([...tree], expected);
+    assert.deepEqual([...tree.entries()], expected);
+    assert.deepEqual(tree.toArray(), expected);
+    assert.deepEqual([...tree.keys()], [-1, 2, 3, 10]);
+    assert.deepEqual(
+      [...tree.values()],
+      ['value--1', 'value-2', 'value-3', 'value-10']
+    );
+    assert.deepEqual(
+      Array.from(tree.keys(), (key) => key * 2),
+      [-2, 4, 6, 20]
+    );
+    assert.equal(new Map(tree).get(3), 'value-3');
+
+    const visited = [];
+    assert.equal(
+      tree.forEach((value, key) => visited.push([key, value])),
+      undefined
+    );
+    assert.deepEqual(visited, expected);
+  });
+
+  it('iterates empty trees without yielding', () => {
+    const tree = new AvlTree();
+    const iterator = tree[Symbol.iterator]();
+
+    assert.deepEqual(iterator.next(), { done: true, value: undefined });
+    assert.deepEqual(iterator.next(), { done: true, value: undefined });
+    assert.deepEqual([...tree.keys()], []);
+    assert.deepEqual([...tree.values()], []);
+    assert.deepEqual(tree.toArray(), []);
+    tree.forEach(() => assert.fail('the callback must not run'));
+  });
+
+  it('follows the iterator protocol including early return', () => {
+    const tree = new AvlTree();
+    tree.insert(1, 'one');
+    tree.insert(2, 'two');
+    const iterator = tree.keys();
+
+    assert.equal(iterator[Symbol.iterator](), iterator);
+    assert.deepEqual(iterator.next(), { done: false, value: 1 });
+    assert.deepEqual(iterator.return('stop'), { done: true, value: 'stop' });
+    assert.deepEqual(iterator.next(), { done: true, value: undefined });
+
+    const seen = [];
+    for (const [key] of tree) {
+      seen.push(key);
+      break;
+    }
+    assert.deepEqual(seen, [1]);
+  });
+
+  it('iterates a snapshot when the tree is mutated mid-iteration', () => {
+    const tree = new AvlTree();
+    for (const key of [1, 2, 3, 4]) {
+      tree.insert(key, `value-${key}`);
+    }
+
+    const iterator = tree.entries();
+    assert.deepEqual(iterator.next().value, [1, 'value-1']);
+    tree.remove(2);
+    tree.insert(3, 'replaced');
+    tree.insert(5, 'value-5');
+    assert.deepEqual(
+      [...iterator],
+      [
+        [2, 'value-2'],
+        [3, 'value-3'],
+        [4, 'value-4'],
+      ]
+    );
+    assert.deepEqual([...tree.keys()], [1, 3, 4, 5]);
+    assert.equal(tree.find(3), 'replaced');
+
+    const visited = [];
+    tree.forEach((value, key) => {
+      visited.push(key);
+      tree.remove(key + 1);
+      tree.insert(key + 100, 'added');
+    });
+    assert.deepEqual(visited, [1, 3, 4, 5]);
+    assert.deepEqual([...tree.keys()], [1, 3, 101, 103, 104, 105]);
+  });
+
+  it('returns read-only snapshots that ignore later writes', () => {
+    const tree = AvlTree.fromEntries([
+      [1, 'one'],
+      [2, 'two'],
+      [3, 'three'],
+      [4, 'four'],
+    ]);
+    const snapshot = tree.snapshot();
+    assert.equal(snapshot.constructor.name, 'AvlTreeSnapshot');
+    assert.ok(!(snapshot instanceof AvlTree));
+    assert.deepEqual(
+      Object.getOwnPropertyNames(Object.getPrototypeOf(snapshot)).sort(),
+      [
+        'constructor',
+        'entries',
+        'find',
+        'has',
+        'keys',
+        'range',
+        'size',
+        'values',
+      ]
+    );
+
+    tree.remove(1);
+    tree.set(2, 'TWO');
+    tree.insertMany([
+      [5, 'five'],
+      [0, 'zero'],
+    ]);
+    tree.clear();
+
+    assert.equal(snapshot.size, 4);
+    assert.equal(snapshot.find(2), 'two');
+    assert.equal(snapshot.find(5), null);
+    assert.equal(snapshot.has(1), true);
+    assert.equal(snapshot.has(0), false);
+    assert.deepEqual(snapshot.range(2, 4), [
+      [2, 'two'],
+      [3, 'three'],
+    ]);
+    assert.deepEqual(snapshot.range(null, 2, { inclusive: true }), [
+      [1, 'one'],
+      [2, 'two'],
+    ]);
+    assert.deepEqual([...snapshot].map(([key]) => key), [1, 2, 3, 4]);
+    assert.deepEqual([...snapshot.keys()], [1, 2, 3, 4]);
+    assert.deepEqual([...snapshot.values()], ['one', 'two', 'three', 'four']);
+    assert.equal(tree.size, 0);
+    assert.equal(new AvlTree().snapshot().size, 0);
+  });
+
+  it('keeps snapshots stable while iterating them during writes', () => {
+    const tree = AvlTree.fromEntries([
+      [1, 'one'],
+      [2, 'two'],
+      [3, 'three'],
+    ]);
+    const snapshot = tree.snapshot();
+    const visited = [];
+    for (const [key, value] of snapshot) {
+      visited.push([key, value]);
+      tree.remove(key + 1);
+      tree.insert(key * 10, 'added');
+    }
+    assert.deepEqual(visited, [
+      [1, 'one'],
+      [2, 'two'],
+      [3, 'three'],
+    ]);
+    assert.deepEqual(tree.toArray(), [
+      [1, 'one'],
+      [10, 'added'],
+      [20, 'added'],
+      [30, 'added'],
+    ]);
+
+    const later = tree.snapshot();
+    tree.insert(40, 'added');
+    assert.equal(later.size, 4);
+    assert.equal(snapshot.size, 3);
+    assert.equal(tree.size, 5);
+    assert.throws(() => snapshot.insert(9, 'nine'), TypeError);
+    assert.throws(() => {
+      'use strict';
+      snapshot.size = 0;
+    }, TypeError);
+  });
+
+  it('keeps every snapshot intact across each kind of write', () => {
+    const tree = AvlTree.fromEntries(
+      Array.from({ length: 100 }, (_, key) => [key, `v${key}`])
+    );
+    tree.enableHistory(10);
+    const writes = [
+      () => tree.insert(200, 'new'),
+      () => tree.set(5, 'five'),
+      () => tree.remove(6),
+      () => tree.update(7, (value) => value.toUpperCase()),
+      () => tree.getOrInsert(201, 'other'),
+      () => tree.popFirst(),
+      () => tree.popLast(),
+      () => tree.retain((value, key) => key % 10 !== 3),
+      () => tree.drainRange(40, 50),
+      () =>
+        tree.insertMany([
+          [300, 'a'],
+          [8, 'b'],
+        ]),
+      () => tree.removeMany([9, 10, 999]),
+      () => tree.transaction((transaction) => transaction.remove(11)),
+      () => tree.undo(),
+      () => tree.redo(),
+      () => tree.clear(),
+    ];
+    const versions = [];
+    const iterator = tree.keys();
+    for (const write of writes) {
+      versions.push([tree.snapshot(), tree.toArray()]);
+      write();
+      tree.validate();
+    }
+    for (const [snapshot, entries] of versions) {
+      assert.deepEqual([...snapshot], entries);
+      assert.equal(snapshot.size, entries.length);
+    }
+    assert.deepEqual([...iterator], versions[0][1].map(([key]) => key));
+    assert.equal(tree.size, 0);
+  });
+
+  it('commits transactions that read their staged changes', () => {
+    const tree = AvlTree.fromEntries([
+      [1, 'one'],
+      [2, 'two'],
+      [3, 'three'],
+    ]);
+    let handle;
+    const result = tree.transaction((transaction) => {
+      handle = transaction;
+      assert.equal(transaction.constructor.name, 'AvlTreeTransaction');
+      transaction.insert(4, 'four');
+      transaction.insert(2, 'TWO');
+      assert.equal(transaction.remove(1), 'one');
+      assert.equal(transaction.remove(1), null);
+      assert.equal(transaction.remove(9), null);
+      assert.equal(transaction.find(2), 'TWO');
+      assert.equal(transaction.has(4), true);
+      assert.equal(transaction.has(1), false);
+      assert.equal(transaction.size, 3);
+      assert.deepEqual(tree.toArray(), [
+        [1, 'one'],
+        [2, 'two'],
+        [3, 'three'],
+      ]);
+      assert.throws(() => tree.insert(5, 'five'), /while one of its callbacks/);
+      assert.throws(() => tree.clear(), /while one of its callbacks/);
+      return 'done';
+    });
+
+    assert.equal(result, 'done');
+    assert.deepEqual(tree.toArray(), [
+      [2, 'TWO'],
+      [3, 'three'],
+      [4, 'four'],
+    ]);
+    assert.doesNotThrow(() => tree.validate());
+    assert.throws(() => handle.insert(5, 'five'), /already ended/);
+    assert.throws(() => handle.size, /already ended/);
+    assert.equal(tree.transaction(() => {}), undefined);
+    assert.equal(tree.size, 3);
+  });
+
+  it('rolls back transactions when the callback throws', () => {
+    const tree = new AvlTree();
+    for (let key = 0; key < 100; key += 1) {
+      tree.insert(key, `value-${key}`);
+    }
+    const dump = tree.dump();
+
+    let handle;
+    assert.throws(
+      () =>
+        tree.transaction((transaction) => {
+          handle = transaction;
+          for (let key = 0; key < 200; key += 2) {
+            transaction.remove(key);
+            transaction.insert(key + 1, 'changed');
+          }
+          throw new Error('abort');
+        }),
+      /abort/
+    );
+    assert.equal(tree.dump(), dump);
+    assert.equal(tree.size, 100);
+    assert.throws(() => handle.find(1), /already ended/);
+  });
+
+  it('rejects asynchronous transaction callbacks without applying them', () => {
+    const tree = AvlTree.fromEntries([[1, 'one']]);
+    let handle;
+    assert.throws(
+      () =>
+        tree.transaction(async (transaction) => {
+          handle = transaction;
+          transaction.insert(2, 'two');
+          transaction.remove(1);
+        }),
+      /must be synchronous.*returned a Promise/
+    );
+    assert.throws(
+      () => tree.transaction(() => Promise.resolve('later')),
+      /must be synchronous/
+    );
+    assert.deepEqual(tree.toArray(), [[1, 'one']]);
+    assert.throws(() => handle.insert(3, 'three'), /already ended/);
+  });
+
+  it('undoes and redoes edits between checkpoints', () => {
+    const tree = AvlTree.fromEntries([[1, 'one']]);
+    assert.throws(() => tree.undo(), /enableHistory/);
+    assert.throws(() => tree.checkpoint('edit'), /enableHistory/);
+    assert.equal(tree.undoLabel(), null);
+
+    tree.enableHistory(10);
+    assert.equal(tree.undo(), false);
+    tree.insert(2, 'two');
+    tree.insert(1, 'ONE');
+    tree.checkpoint('typing');
+    tree.remove(2);
+    tree.remove(9);
+    assert.equal(tree.undoLabel(), null);
+    assert.equal(tree.undo(), true);
+    assert.deepEqual(tree.toArray(), [
+      [1, 'ONE'],
+      [2, 'two'],
+    ]);
+    assert.equal(tree.undoLabel(), 'typing');
+    assert.equal(tree.undo(), true);
+    assert.deepEqual(tree.toArray(), [[1, 'one']]);
+    assert.equal(tree.undo(), false);
+
+    assert.equal(tree.redoLabel(), 'typing');
+    assert.equal(tree.redo(), true);
+    assert.deepEqual(tree.toArray(), [
+      [1, 'ONE'],
+      [2, 'two'],
+    ]);
+    tree.set(3, 'three');
+    assert.equal(tree.redo(), false);
+    assert.equal(tree.redoLabel(), null);
+
+    tree.disableHistory();
+    assert.throws(() => tree.redo(), /redo requires an AvlTree with history/);
+    assert.equal(tree.size, 3);
+  });
+
+  it('records every mutating method in the history', () => {
+    const initial = [
+      [1, 'one'],
+      [2, 'two'],
+      [3, 'three'],
+      [4, 'four'],
+      [5, 'five'],
+    ];
+    const tree = AvlTree.fromEntries(initial);
+    tree.enableHistory(100);
+    const edits = [
+      () => tree.update(1, (value) => value.toUpperCase()),
+      () => tree.getOrInsert(6, 'six'),
+      () => tree.popFirst(),
+      () => tree.popLast(),
+      () => tree.retain((value) => value !== 'three'),
+      () => tree.drainRange(4, 5, { inclusive: true }),
+      () =>
+        tree.insertMany([
+          [2, 'TWO'],
+          [7, 'seven'],
+        ]),
+      () => tree.removeMany([7, 8]),
+      () =>
+        tree.transaction((transaction) => {
+          transaction.insert(8, 'eight');
+          transaction.remove(2);
+        }),
+      () => tree.clear(),
+    ];
+    const states = [tree.toArray()];
+    for (const [index, edit] of edits.entries()) {
+      edit();
+      tree.checkpoint(`edit ${index}`);
+      states.push(tree.toArray());
+    }
+    assert.equal(tree.size, 0);
+
+    for (const state of states.slice(0, -1).reverse()) {
+      assert.equal(tree.undo(), true);
+      assert.deepEqual(tree.toArray(), state);
+    }
+    assert.deepEqual(tree.toArray(), initial);
+    for (const state of states.slice(1)) {
+      assert.equal(tree.redo(), true);
+      assert.deepEqual(tree.toArray(), state);
+    }
+    assert.doesNotThrow(() => tree.validate());
+  });
+
+  it('keeps only the newest history steps', () => {
+    const tree = new AvlTree();
+    tree.enableHistory(2);
+    for (const key of [1, 2, 3]) {
+      tree.insert(key, `value-${key}`);
+      tree.checkpoint(`insert ${key}`);
+    }
+    assert.equal(tree.undo(), true);
+    assert.equal(tree.undo(), true);
+    assert.equal(tree.undo(), false);
+    assert.deepEqual(tree.toArray(), [[1, 'value-1']]);
+
+    tree.enableHistory(0);
+    tree.insert(4, 'value-4');
+    assert.equal(tree.undo(), false);
+    assert.equal(tree.redo(), false);
+    assert.equal(tree.size, 2);
+  });
+
+  it('counts uncheckpointed changes toward the history depth', () => {
+    const tree = AvlTree.fromEntries(
+      Array.from({ length: 1000 }, (_, key) => [key, `v${key}`])
+    );
+    tree.enableHistory(3);
+    tree.insert(-1, 'checkpointed');
+    tree.checkpoint('insert -1');
+    tree.clear();
+
+    assert.equal(tree.undoLabel(), null);
+    assert.equal(tree.undo(), true);
+    assert.deepEqual(tree.toArray(), [
+      [997, 'v997'],
+      [998, 'v998'],
+      [999, 'v999'],
+    ]);
+    assert.equal(tree.undo(), false);
+  });
+
+  it('stops forEach when the callback throws', () => {
+    const tree = new AvlTree();
+    for (const key of [1, 2, 3]) {
+      tree.insert(key, `value-${key}`);
+    }
+
+    const visited = [];
+    assert.throws(
+      () =>
+        tree.forEach((value, key) => {
+          visited.push(key);
+          if (key === 2) {
+            throw new Error('stop here');
+          }
+        }),
+      /stop here/
+    );
+    assert.deepEqual(visited, [1, 2]);
+  });
+
+  it('builds trees from entries in any order with last-wins duplicates', () => {
+    const sorted = AvlTree.fromEntries([
+      [1, 'one'],
+      [2, 'two'],
+      [3, 'three'],
+    ]);
+    assert.deepEqual([...sorted.keys()], [1, 2, 3]);
+
+    const unsorted = AvlTree.fromEntries([
+      [3, 'three'],
+      [1, 'one'],
+      [3, 'THREE'],
+      [2, 'two'],
+    ]);
+    assert.deepEqual(unsorted.toArray(), [
+      [1, 'one'],
+      [2, 'two'],
+      [3, 'THREE'],
+    ]);
+    unsorted.insert(0, 'zero');
+    assert.equal(unsorted.rank(3), 3);
+
+    assert.deepEqual(AvlTree.fromEntries([]).toArray(), []);
+    assert.throws(
+      () => AvlTree.fromEntries([[1, 1]]),
+      (error) => error.code === 'StringExpected'
+    );
+  });
+
+  it('inserts, finds, checks, and removes keys in batches', () => {
+    const tree = new AvlTree();
+
+    assert.equal(
+      tree.insertMany([
+        [3, 'three'],
+        [1, 'one'],
+        [2, 'two'],
+        [3, 'THREE'],
+      ]),
+      undefined
+    );
+    assert.deepEqual([...tree.keys()], [1, 2, 3]);
+    assert.equal(tree.find(3), 'THREE');
+
+    assert.deepEqual(tree.findMany([3, 9, 1]), ['THREE', null, 'one']);
+    assert.deepEqual(tree.findMany(new Int32Array([2, -1])), ['two', null]);
+    assert.deepEqual(tree.hasMany([1, 4]), [true, false]);
+    assert.deepEqual(tree.hasMany(new Int32Array([3])), [true]);
+    assert.deepEqual(tree.findMany([]), []);
+    assert.deepEqual(tree.hasMany(new Int32Array(0)), []);
+
+    assert.deepEqual(tree.removeMany(new Int32Array([1, 1, 9])), [
+      'one',
+      null,
+      null,
+    ]);
+    assert.deepEqual(tree.removeMany([3]), ['THREE']);
+    assert.deepEqual(tree.toArray(), [[2, 'two']]);
+    tree.insertMany([]);
+    assert.deepEqual(tree.toArray(), [[2, 'two']]);
+  });
+
+  it('rejects a malformed batch before inserting any entry', () => {
+    const tree = new AvlTree();
+    tree.insert(1, 'one');
+
+    assert.throws(
+      () =>
+        tree.insertMany([
+          [2, 'two'],
+          [3, 3],
+        ]),
+      (error) => error.code === 'StringExpected'
+    );
+    assert.throws(() => tree.insertMany([[4]]), /length/);
+    assert.throws(() => tree.findMany(['1']));
+    assert.throws(() => tree.removeMany(new Float64Array([1])));
+    assert.deepEqual(tree.toArray(), [[1, 'one']]);
+  });
+
+  it('combines trees with set operations that return new trees', () => {
+    const left = AvlTree.fromEntries([
+      [1, 'left-1'],
+      [2, 'left-2'],
+      [3, 'left-3'],
+    ]);
+    const right = AvlTree.fromEntries([
+      [2, 'right-2'],
+      [3, 'right-3'],
+      [4, 'right-4'],
+    ]);
+
+    const union = left.union(right);
+    assert.ok(union instanceof AvlTree);
+    assert.deepEqual(union.toArray(), [
+      [1, 'left-1'],
+      [2, 'right-2'],
+      [3, 'right-3'],
+      [4, 'right-4'],
+    ]);
+
+    const calls = [];
+    const merged = left.union(right, (key, value, otherValue) => {
+      calls.push(key);
+      return `${value}+${otherValue}`;
+    });
+    assert.deepEqual(calls.sort(), [2, 3]);
+    assert.deepEqual(merged.values().next().value, 'left-1');
+    assert.equal(merged.find(2), 'left-2+right-2');
+
+    assert.deepEqual(left.intersection(right).toArray(), [
+      [2, 'left-2'],
+      [3, 'left-3'],
+    ]);
+    assert.deepEqual(left.difference(right).toArray(), [[1, 'left-1']]);
+    assert.deepEqual(left.symmetricDifference(right).toArray(), [
+      [1, 'left-1'],
+      [4, 'right-4'],
+    ]);
+    assert.deepEqual([...left.union(new AvlTree()).keys()], [1, 2, 3]);
+    assert.deepEqual([...new AvlTree().intersection(left).keys()], []);
+
+    union.insert(9, 'only in the result');
+    assert.deepEqual([...left.keys()], [1, 2, 3]);
+    assert.deepEqual([...right.keys()], [2, 3, 4]);
+  });
+
+  it('lets merge write to either input without changing the union', () => {
+    const left = AvlTree.fromEntries([
+      [1, 'left-1'],
+      [2, 'left-2'],
+    ]);
+    const right = AvlTree.fromEntries([[2, 'right-2']]);
+
+    const union = left.union(right, (key, value, otherValue) => {
+      left.clear();
+      right.insert(3, 'right-3');
+      return `${value}+${otherValue}`;
+    });
+    assert.deepEqual(union.toArray(), [
+      [1, 'left-1'],
+      [2, 'left-2+right-2'],
+    ]);
+    assert.equal(left.size, 0);
+    assert.deepEqual([...right.keys()], [2, 3]);
+  });
+
+  it('propagates merge failures without changing either tree', () => {
+    const left = AvlTree.fromEntries([[1, 'one']]);
+    const right = AvlTree.fromEntries([[1, 'uno']]);
+
+    assert.throws(
+      () =>
+        left.union(right, () => {
+          throw new Error('conflict');
+        }),
+      /conflict/
+    );
+    assert.throws(
+      () => left.union(right, () => 42),
+      (error) => error.code === 'StringExpected'
+    );
+    assert.throws(() => left.union({}), /AvlTree|object|class/i);
+    assert.deepEqual(left.toArray(), [[1, 'one']]);
+    assert.deepEqual(right.toArray(), [[1, 'uno']]);
+  });
+
+  it('dumps lossless JSON that matches JSON.stringify', () => {
+    const tree = new AvlTree();
+    assert.equal(tree.dumpJson(), '[]');
+
+    const values = [
+      "{ key: 1, value: 'x' }, ",
+      'quote " backslash \\ slash /',
+      '\b\t\n\f\r\u0000\u001f\u007f',
+      'caf\u00e9 \u{1f333} \u2028\u2029',
+      '',
+    ];
+    values.forEach((value, index) => tree.insert(index - 2, value));
+
+    const json = tree.dumpJson();
+    assert.equal(json, JSON.stringify(tree.toArray()));
+    assert.deepEqual(JSON.parse(json), tree.toArray());
+    assert.deepEqual(
+      AvlTree.fromEntries(JSON.parse(json)).toArray(),
+      tree.toArray()
+    );
+    assert.equal(
+      tree.dump().startsWith("{ key: -2, value: '{ key: 1, value: 'x' }, ' }"),
+      true
+    );
+  });
+
+  it('round-trips trees through the checksummed binary format', () => {
+    const tree = AvlTree.fromEntries([
+      [-5, 'minus five'],
+      [0, ''],
+      [7, 'caf\u00e9 \u{1f333}'],
+    ]);
+
+    const bytes = tree.serialize();
+    assert.ok(Buffer.isBuffer(bytes));
+    assert.equal(bytes.subarray(0, 5).toString('latin1'), 'AVLT\x01');
+    assert.equal(bytes.readBigUInt64LE(5), 3n);
+
+    const copy = AvlTree.deserialize(bytes);
+    assert.ok(copy instanceof AvlTree);
+    assert.deepEqual(copy.toArray(), tree.toArray());
+    assert.deepEqual(
+      AvlTree.deserialize(new Uint8Array(bytes)).toArray(),
+      tree.toArray()
+    );
+    assert.equal(AvlTree.deserialize(new AvlTree().serialize()).size, 0);
+  });
+
+  it('rejects truncated, corrupt, and foreign binary input', () => {
+    const bytes = AvlTree.fromEntries([[1, 'one']]).serialize();
+
+    assert.throws(
+      () => AvlTree.deserialize(bytes.subarray(0, bytes.length - 1)),
+      /truncated/
+    );
+    const corrupt = Buffer.from(bytes);
+    corrupt[corrupt.length - 6] ^= 1;
+    assert.throws(() => AvlTree.deserialize(corrupt), /checksum mismatch/);
+    assert.throws(
+      () => AvlTree.deserialize(Buffer.from('{ key: 1 }')),
+      /not a serialized tree/
+    );
+    assert.throws(
+      () => AvlTree.deserialize(Buffer.concat([bytes, Buffer.from([0])])),
+      /1 unexpected bytes after the checksum/
+    );
+    assert.throws(() => AvlTree.deserialize('AVLT'));
+  });
+
+  it('saves and loads trees through files with atomic replacement', (t) => {
+    const directory = fs.mkdtempSync(path.join(os.tmpdir(), 'avl-tree-'));
+    t.after(() => fs.rmSync(directory, { recursive: true, force: true }));
+    const file = path.join(directory, 'tree.bin');
+    const tree = AvlTree.fromEntries([
+      [3, 'three'],
+      [-1, 'minus one'],
+    ]);
+
+    assert.equal(tree.saveToFile(file), undefined);
+    assert.deepEqual(fs.readFileSync(file), tree.serialize());
+    const loaded = AvlTree.loadFromFile(file);
+    assert.ok(loaded instanceof AvlTree);
+    assert.deepEqual(loaded.toArray(), tree.toArray());
+
+    new AvlTree().saveToFile(file);
+    assert.equal(AvlTree.loadFromFile(file).size, 0);
+    assert.deepEqual(fs.readdirSync(directory), ['tree.bin']);
+  });
+
+  it('recovers durable trees from the snapshot and the log', (t) => {
+    const directory = path.join(
+      fs.mkdtempSync(path.join(os.tmpdir(), 'avl-tree-')),
+      'durable'
+    );
+    t.after(() =>
+      fs.rmSync(path.dirname(directory), { recursive: true, force: true })
+    );
+
+    const tree = AvlTree.openDurable(directory);
+    assert.ok(tree instanceof AvlTree);
+    tree.insertMany([
+      [1, 'one'],
+      [2, 'two'],
+      [3, 'three'],
+      [4, 'four'],
+      [5, 'five'],
+    ]);
+    assert.equal(tree.set(2, 'TWO'), 'two');
+    tree.remove(1);
+    tree.update(3, (value) => value.toUpperCase());
+    tree.getOrInsert(6, 'six');
+    tree.popLast();
+    tree.removeMany([4, 40]);
+    tree.compact();
+    assert.equal(
+      fs.readFileSync(path.join(directory, 'wal.log'), 'latin1'),
+      'AVLW\x01'
+    );
+    tree.insert(7, 'seven');
+    tree.drainRange(7, 8);
+    tree.retain((value) => value !== 'five');
+    tree.popFirst();
+
+    assert.deepEqual(tree.toArray(), [[3, 'THREE']]);
+    assert.deepEqual(AvlTree.openDurable(directory).toArray(), tree.toArray());
+
+    tree.clear();
+    const log = path.join(directory, 'wal.log');
+    const complete = fs.statSync(log).size;
+    tree.insert(8, 'eight');
+    fs.truncateSync(log, fs.statSync(log).size - 1);
+    assert.equal(AvlTree.openDurable(directory).size, 0);
+    assert.equal(fs.statSync(log).size, complete);
+  });
+
+  it('logs a durable transaction as one all-or-nothing append', (t) => {
+    const directory = fs.mkdtempSync(path.join(os.tmpdir(), 'avl-tree-'));
+    t.after(() => fs.rmSync(directory, { recursive: true, force: true }));
+    const log = path.join(directory, 'wal.log');
+
+    const tree = AvlTree.openDurable(directory);
+    tree.insert(1, 'one');
+    const complete = fs.statSync(log).size;
+    tree.transaction((transaction) => {
+      transaction.remove(1);
+      transaction.insert(2, 'two');
+      transaction.insert(3, 'three');
+    });
+    assert.deepEqual(AvlTree.openDurable(directory).toArray(), [
+      [2, 'two'],
+      [3, 'three'],
+    ]);
+
+    fs.truncateSync(log, fs.statSync(log).size - 1);
+    assert.deepEqual(AvlTree.openDurable(directory).toArray(), [[1, 'one']]);
+    assert.equal(fs.statSync(log).size, complete);
+  });
+
+  it('logs undo and redo on a durable tree', (t) => {
+    const directory = fs.mkdtempSync(path.join(os.tmpdir(), 'avl-tree-'));
+    t.after(() => fs.rmSync(directory, { recursive: true, force: true }));
+
+    const tree = AvlTree.openDurable(directory);
+    tree.enableHistory(10);
+    tree.insert(1, 'one');
+    tree.insert(1, 'ONE');
+    tree.insert(2, 'two');
+    tree.checkpoint('edit');
+    tree.remove(1);
+    tree.undo();
+    assert.deepEqual(AvlTree.openDurable(directory).toArray(), [
+      [1, 'ONE'],
+      [2, 'two'],
+    ]);
+    tree.undo();
+    assert.equal(AvlTree.openDurable(directory).size, 0);
+    tree.redo();
+    assert.deepEqual(AvlTree.openDurable(directory).toArray(), tree.toArray());
+  });
+
+  it('keeps only durable trees in a write-ahead log', () => {
+    assert.throws(() => new AvlTree().compact(), /openDurable/);
+    assert.throws(
+      () => AvlTree.openDurable(path.join(__filename, 'durable')),
+      /cannot open durable AvlTree/
+    );
+  });
+
+  it('reports missing, malformed, and unwritable files', (t) => {
+    const directory = fs.mkdtempSync(path.join(os.tmpdir(), 'avl-tree-'));
+    t.after(() => fs.rmSync(directory, { recursive: true, force: true }));
+    const file = path.join(directory, 'tree.bin');
+
+    assert.throws(
+      () => AvlTree.loadFromFile(file),
+      /cannot load AvlTree from .*tree\.bin/
+    );
+    const bytes = AvlTree.fromEntries([[1, 'one']]).serialize();
+    fs.writeFileSync(file, Buffer.concat([bytes, Buffer.from([0])]));
+    assert.throws(
+      () => AvlTree.loadFromFile(file),
+      /1 unexpected bytes after the checksum/
+    );
+    assert.throws(
+      () => new AvlTree().saveToFile(path.join(directory, 'missing', 'x')),
+      /cannot save AvlTree to/
+    );
+    assert.deepEqual(fs.readdirSync(directory), ['tree.bin']);
+  });
+
   it('keeps instances independent', () => {
     const first = new AvlTree();
     const second = new AvlTree();
@@ -160,6 +1349,7 @@ describe('observed NAPI input conversion', () => {
     assert.throws(() => tree.find(), { code: 'NumberExpected' });
     assert.throws(() => tree.remove(), { code: 'NumberExpected' });
     assert.throws(() => tree.has(), { code: 'NumberExpected' });
+    assert.throws(() => tree.floor(), { code: 'NumberExpected' });
 
     assert.equal(tree.insert(1, 'one', 'ignored'), undefined);
     assert.equal(tree.find(1, 'ignored'), 'one');